@@ -6,50 +6,63 @@
 */
 
 use proc_macro::TokenStream;
-use quote::{quote, quote_spanned};
-use syn::{parse_macro_input, spanned::Spanned, ItemFn};
+use quote::{format_ident, quote, quote_spanned};
+use syn::{parse_macro_input, spanned::Spanned, Ident, ItemFn};
 
-macro_rules! gen_checker {
-    ($marcro_name:ident, $code:block) => {
-        #[proc_macro_attribute]
-        pub fn $marcro_name(_attr: TokenStream, item: TokenStream) -> TokenStream {
-            let input = parse_macro_input!(item as ItemFn);
+// returns the compile error if the handler doesn't take message and state
+fn check_handler_signature(input: &ItemFn) -> Option<TokenStream> {
+    let fn_inputs = &input.sig.inputs;
 
-            let fn_inputs = &input.sig.inputs;
+    if fn_inputs.len() != 2 {
+        return Some(
+            quote! {
+                compile_error!("only works with 2 arguments");
+            }
+            .into(),
+        );
+    }
 
-            if fn_inputs.len() != 2 {
-                return quote! {
-                    compile_error!("only works with 2 arguments");
+    let param_names = input
+        .sig
+        .inputs
+        .iter()
+        .filter_map(|arg| {
+            if let syn::FnArg::Typed(pat_type) = arg {
+                if let syn::Pat::Ident(ident) = &*pat_type.pat {
+                    Some(ident.ident.to_string())
+                } else {
+                    None
                 }
-                .into();
+            } else {
+                None
             }
+        })
+        .collect::<Vec<String>>();
 
-            let param_names = input
-                .sig
-                .inputs
-                .iter()
-                .filter_map(|arg| {
-                    if let syn::FnArg::Typed(pat_type) = arg {
-                        if let syn::Pat::Ident(ident) = &*pat_type.pat {
-                            Some(ident.ident.to_string())
-                        } else {
-                            None
-                        }
-                    } else {
-                        None
-                    }
-                })
-                .collect::<Vec<String>>();
-
-            let expected_param_names = ["message", "state"];
-
-            for expected in expected_param_names {
-                if !param_names.contains(&expected.to_string()) {
-                    return quote! {
-                        compile_error!(concat!("expect parameter name: ", #expected));
-                    }
-                    .into();
+    let expected_param_names = ["message", "state"];
+
+    for expected in expected_param_names {
+        if !param_names.contains(&expected.to_string()) {
+            return Some(
+                quote! {
+                    compile_error!(concat!("expect parameter name: ", #expected));
                 }
+                .into(),
+            );
+        }
+    }
+
+    None
+}
+
+macro_rules! gen_checker {
+    ($marcro_name:ident, $code:block) => {
+        #[proc_macro_attribute]
+        pub fn $marcro_name(_attr: TokenStream, item: TokenStream) -> TokenStream {
+            let input = parse_macro_input!(item as ItemFn);
+
+            if let Some(error) = check_handler_signature(&input) {
+                return error;
             }
 
             let fn_attrs = &input.attrs;
@@ -71,35 +84,70 @@ macro_rules! gen_checker {
     };
 }
 
-gen_checker!(check_in_group, {
-    match message.chat() {
-        grammers_client::types::Chat::Group(_) => {}
-        _ => {
-            const CHECK_IN_GROUP_FAILED: &str = r"
-This bot must be used in a Group!
+// like #[require_role(uploader)], senders with a lower role or not in the acl are stopped
+#[proc_macro_attribute]
+pub fn require_role(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let role = parse_macro_input!(attr as Ident);
+    let input = parse_macro_input!(item as ItemFn);
 
-Add this bot to a Group as Admin, and give it ability to Delete Messages.
-";
-
-            message
-                .respond(CHECK_IN_GROUP_FAILED)
-                .await
-                .context(CHECK_IN_GROUP_FAILED)?;
-
-            return Ok(());
+    let role_variant = match role.to_string().as_str() {
+        "admin" => format_ident!("Admin"),
+        "uploader" => format_ident!("Uploader"),
+        "viewer" => format_ident!("Viewer"),
+        _ => {
+            return quote_spanned! {role.span() =>
+                compile_error!("expect role: admin, uploader or viewer");
+            }
+            .into();
         }
+    };
+
+    if let Some(error) = check_handler_signature(&input) {
+        return error;
     }
-});
 
-gen_checker!(check_senders, {
-    let users = &crate::env::ENV.get().unwrap().telegram_user.users;
+    let fn_attrs = &input.attrs;
+    let fn_visibility = &input.vis;
+    let fn_sig = &input.sig;
+    let fn_block = &input.block;
 
-    if let Some(sender) = message.sender() {
-        if let Some(username) = sender.username() {
-            if !users.is_empty() && !users.contains(&username.to_string()) {
+    let tokens = quote_spanned! {input.span() =>
+        #(#fn_attrs)*
+        #fn_visibility #fn_sig {
+            if !crate::acl::check_role(&message, &state, crate::acl::Role::#role_variant).await? {
                 return Ok(());
             }
+
+            #fn_block
         }
+    };
+
+    tokens.into()
+}
+
+// private chats are only allowed once users are set, otherwise anyone could use the bot
+gen_checker!(check_chat_type, {
+    let is_allowed = match message.chat() {
+        grammers_client::types::Chat::Group(_) => true,
+        grammers_client::types::Chat::User(_) => !crate::acl::is_open(&state).await?,
+        grammers_client::types::Chat::Channel(_) => false,
+    };
+
+    if !is_allowed {
+        const CHECK_CHAT_TYPE_FAILED: &str = r"
+This bot must be used in a Group, or in a private chat once users are set!
+
+Add this bot to a Group as Admin, and give it ability to Delete Messages.
+
+To use it in a private chat, set tg_user_name or add users by /users in a Group.
+";
+
+        message
+            .respond(CHECK_CHAT_TYPE_FAILED)
+            .await
+            .context(CHECK_CHAT_TYPE_FAILED)?;
+
+        return Ok(());
     }
 });
 
@@ -115,6 +163,20 @@ gen_checker!(check_tg_login, {
     }
 });
 
+// only onedrive needs authorization among the storages that tasks upload to
+gen_checker!(check_storage_login, {
+    let is_authorized = state.current_storage_type().await != crate::storage::StorageType::OneDrive
+        || state.onedrive.is_authorized().await;
+
+    if !is_authorized {
+        let response = "You haven't authorize OneDrive.";
+        message.respond(response).await.context(response)?;
+
+        crate::handlers::auth::authorize_onedrive(message.clone(), state.clone(), false, None)
+            .await?;
+    }
+});
+
 gen_checker!(check_od_login, {
     let is_authorized = state.onedrive.is_authorized().await;
 
@@ -122,8 +184,7 @@ gen_checker!(check_od_login, {
         let response = "You haven't authorize OneDrive.";
         message.respond(response).await.context(response)?;
 
-        let (_, rx, _server_abort_handle) = crate::auth_server::spawn().await?;
-        crate::handlers::auth::authorize_onedrive(message.clone(), state.clone(), false, rx)
+        crate::handlers::auth::authorize_onedrive(message.clone(), state.clone(), false, None)
             .await?;
     }
 });
@@ -0,0 +1,138 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+// access control by numeric telegram user id, checked by #[require_role]
+
+mod entries;
+mod session;
+
+use crate::{env::ENV, message::TelegramMessage, state::AppState};
+use anyhow::{anyhow, Context, Result};
+use grammers_client::types::Chat;
+pub use session::AclSession;
+use std::{fmt::Display, str::FromStr};
+
+// ordered by permission, a role can do everything lower roles can
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Role {
+    // read only commands, like /quota
+    Viewer,
+    // transfer files and manage onedrive items
+    Uploader,
+    // manage accounts, settings and users
+    Admin,
+}
+
+impl FromStr for Role {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "admin" => Ok(Self::Admin),
+            "uploader" => Ok(Self::Uploader),
+            "viewer" => Ok(Self::Viewer),
+            _ => Err(anyhow!("role should be admin, uploader or viewer")),
+        }
+    }
+}
+
+impl Display for Role {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let role = match self {
+            Self::Admin => "admin",
+            Self::Uploader => "uploader",
+            Self::Viewer => "viewer",
+        };
+
+        write!(f, "{}", role)
+    }
+}
+
+// everyone can use the bot if neither tg_user_name nor the acl has a user
+pub async fn is_open(state: &AppState) -> Result<bool> {
+    let users = &ENV.get().unwrap().telegram_user.users;
+
+    Ok(users.is_empty() && state.acl_session.is_empty().await?)
+}
+
+// without it, nobody could manage users, and the bot would be open to everyone once the acl is empty
+pub async fn has_other_admin(state: &AppState, user_id: i64) -> Result<bool> {
+    let users = &ENV.get().unwrap().telegram_user.users;
+
+    if !users.is_empty() {
+        return Ok(true);
+    }
+
+    let has_other_admin = state
+        .acl_session
+        .get_users()
+        .await?
+        .iter()
+        .any(|user| user.id != user_id && user.role.parse().ok() == Some(Role::Admin));
+
+    Ok(has_other_admin)
+}
+
+pub async fn get_sender_role(message: &TelegramMessage, state: &AppState) -> Result<Option<Role>> {
+    get_role(state, message.sender()).await
+}
+
+// the sender of a message or a button click
+pub async fn get_role(state: &AppState, sender: Option<Chat>) -> Result<Option<Role>> {
+    if is_open(state).await? {
+        return Ok(Some(Role::Admin));
+    }
+
+    // anonymous group admins and channels send as a chat, they can't be identified
+    let Some(Chat::User(user)) = sender else {
+        return Ok(None);
+    };
+
+    if let Some(role) = state.acl_session.get_role(user.id()).await? {
+        return Ok(Some(role));
+    }
+
+    // users in tg_user_name are admins
+    let users = &ENV.get().unwrap().telegram_user.users;
+    let is_env_user = user
+        .username()
+        .is_some_and(|username| users.iter().any(|user| user == username));
+
+    Ok(is_env_user.then_some(Role::Admin))
+}
+
+// returns whether the handler should go on
+pub async fn check_role(
+    message: &TelegramMessage,
+    state: &AppState,
+    required: Role,
+) -> Result<bool> {
+    match get_sender_role(message, state).await? {
+        Some(role) if role >= required => Ok(true),
+        Some(_) => {
+            // plain text may be a chat instead of a link, so it's not answered
+            if message.media().is_some() || message.text().trim_start().starts_with('/') {
+                let response = format!("You need the {} role to do this.", required);
+                message.reply(response.as_str()).await.context(response)?;
+            }
+
+            Ok(false)
+        }
+        None => {
+            // logged so that admins can find the id to add
+            if let Some(sender) = message.sender() {
+                tracing::info!(
+                    "ignored message from {} {}",
+                    sender.id(),
+                    sender.username().unwrap_or_default()
+                );
+            }
+
+            Ok(false)
+        }
+    }
+}
@@ -0,0 +1,115 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use super::{entries, Role};
+use anyhow::{Context, Result};
+use sea_orm::{
+    sea_query::OnConflict, ConnectionTrait, DatabaseConnection, EntityName, EntityTrait,
+    PaginatorTrait, QueryOrder, Schema, Set,
+};
+
+pub struct AclSession {
+    connection: DatabaseConnection,
+}
+
+impl AclSession {
+    pub async fn new(session_path: &str) -> Result<Self> {
+        let connection = Self::connect_db(session_path).await?;
+
+        Ok(Self { connection })
+    }
+
+    async fn connect_db(path: &str) -> Result<DatabaseConnection> {
+        let connection = sea_orm::Database::connect(format!("sqlite://{}?mode=rwc", path))
+            .await
+            .context("failed to connect to acl session")?;
+
+        Self::create_table_if_not_exists(&connection).await?;
+
+        Ok(connection)
+    }
+
+    async fn create_table_if_not_exists(connection: &DatabaseConnection) -> Result<()> {
+        if !Self::is_table_exists(connection).await {
+            let backend = connection.get_database_backend();
+
+            let table_create_statement =
+                Schema::new(backend).create_table_from_entity(entries::Entity);
+
+            connection
+                .execute(backend.build(&table_create_statement))
+                .await
+                .context(format!(
+                    "failed to create table {}",
+                    entries::Entity.table_name()
+                ))?;
+        }
+
+        Ok(())
+    }
+
+    async fn is_table_exists(connection: &DatabaseConnection) -> bool {
+        let result = entries::Entity::find().one(connection).await;
+
+        result.is_ok()
+    }
+
+    pub async fn get_role(&self, id: i64) -> Result<Option<Role>> {
+        let entry = entries::Entity::find_by_id(id)
+            .one(&self.connection)
+            .await
+            .context(format!("failed to get user {}", id))?;
+
+        entry.map(|entry| entry.role.parse()).transpose()
+    }
+
+    pub async fn get_users(&self) -> Result<Vec<entries::Model>> {
+        entries::Entity::find()
+            .order_by_asc(entries::Column::Id)
+            .all(&self.connection)
+            .await
+            .context("failed to get users")
+    }
+
+    pub async fn is_empty(&self) -> Result<bool> {
+        let count = entries::Entity::find()
+            .count(&self.connection)
+            .await
+            .context("failed to count users")?;
+
+        Ok(count == 0)
+    }
+
+    pub async fn set_user(&self, id: i64, role: Role) -> Result<()> {
+        let insert_item = entries::ActiveModel {
+            id: Set(id),
+            role: Set(role.to_string()),
+        };
+
+        entries::Entity::insert(insert_item)
+            .on_conflict(
+                OnConflict::column(entries::Column::Id)
+                    .update_column(entries::Column::Role)
+                    .to_owned(),
+            )
+            .exec(&self.connection)
+            .await
+            .context(format!("failed to set user {}", id))?;
+
+        Ok(())
+    }
+
+    // returns false if the user is not in the acl
+    pub async fn delete_user(&self, id: i64) -> Result<bool> {
+        let result = entries::Entity::delete_by_id(id)
+            .exec(&self.connection)
+            .await
+            .context(format!("failed to delete user {}", id))?;
+
+        Ok(result.rows_affected > 0)
+    }
+}
@@ -5,28 +5,43 @@
 :license: MIT, see LICENSE for more details.
 */
 
+use super::clear_code_senders;
 use axum_server::Handle;
 use tokio::task::AbortHandle;
 
+// ends the login when dropped
 pub struct AutoAbortHandle {
-    abort_handle: AbortHandle,
-    shutdown_handle: Handle,
+    login_id: u64,
+    // None if the auth routes are served by the dashboard server
+    handles: Option<(AbortHandle, Handle)>,
 }
 
 impl AutoAbortHandle {
-    pub const fn new(abort_handle: AbortHandle, shutdown_handle: Handle) -> Self {
+    pub const fn new(login_id: u64, abort_handle: AbortHandle, shutdown_handle: Handle) -> Self {
         Self {
-            abort_handle,
-            shutdown_handle,
+            login_id,
+            handles: Some((abort_handle, shutdown_handle)),
+        }
+    }
+
+    pub const fn without_server(login_id: u64) -> Self {
+        Self {
+            login_id,
+            handles: None,
         }
     }
 }
 
 impl Drop for AutoAbortHandle {
     fn drop(&mut self) {
-        self.shutdown_handle.shutdown();
-        self.abort_handle.abort();
+        // the persistent server keeps running, but stops accepting codes
+        clear_code_senders(self.login_id);
+
+        if let Some((abort_handle, shutdown_handle)) = &self.handles {
+            shutdown_handle.shutdown();
+            abort_handle.abort();
 
-        tracing::debug!("auth server auto aborted");
+            tracing::debug!("auth server auto aborted");
+        }
     }
 }
@@ -0,0 +1,188 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+// tasks submitted here are announced in the chat set by /api, the same as /url and message links
+
+use super::{
+    models::TaskInfo,
+    utils::{get_bearer_token, is_token_matched},
+};
+use crate::{
+    env::ENV,
+    error::{ErrorExt, HttpError, ResultUnwrapExt},
+    handlers::{link::insert_link_task, url::insert_url_task},
+    message::TelegramMessage,
+    state::AppState,
+    storage::StorageType,
+    tasker::cancel_task,
+};
+use anyhow::{anyhow, Result};
+use axum::{
+    debug_handler,
+    extract::Path,
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    Extension, Json,
+};
+use grammers_client::InputMessage;
+use serde::{Deserialize, Serialize};
+
+#[derive(Deserialize)]
+pub struct UrlParams {
+    pub url: String,
+}
+
+#[derive(Deserialize)]
+pub struct LinkParams {
+    pub link: String,
+}
+
+#[derive(Serialize)]
+struct InsertedTask {
+    id: i64,
+}
+
+pub const URL_PATH: &str = "/api/tasks/url";
+
+#[debug_handler]
+pub async fn url_handler(
+    headers: HeaderMap,
+    Extension(state): Extension<AppState>,
+    Json(UrlParams { url }): Json<UrlParams>,
+) -> axum::response::Result<Response> {
+    authorize(&headers)?;
+
+    let message = announce(&state, &format!("Task submitted through API:\n{}", url))
+        .await
+        .map_err(|e| HttpError::new(format!("{:#}", e)))?;
+
+    let result = insert_url_task(message.clone(), state, &url).await;
+    let id = reply_on_error(message, result).await?;
+
+    Ok(Json(InsertedTask { id }).into_response())
+}
+
+pub const LINK_PATH: &str = "/api/tasks/link";
+
+#[debug_handler]
+pub async fn link_handler(
+    headers: HeaderMap,
+    Extension(state): Extension<AppState>,
+    Json(LinkParams { link }): Json<LinkParams>,
+) -> axum::response::Result<Response> {
+    authorize(&headers)?;
+
+    let message = announce(&state, &format!("Task submitted through API:\n{}", link))
+        .await
+        .map_err(|e| HttpError::new(format!("{:#}", e)))?;
+
+    let result = insert_link_task(message.clone(), state, &link).await;
+    let id = reply_on_error(message, result).await?;
+
+    Ok(Json(InsertedTask { id }).into_response())
+}
+
+pub const TASK_PATH: &str = "/api/tasks/:id";
+
+#[debug_handler]
+pub async fn task_handler(
+    headers: HeaderMap,
+    Extension(state): Extension<AppState>,
+    Path(id): Path<i64>,
+) -> axum::response::Result<Response> {
+    authorize(&headers)?;
+
+    let session = &state.task_session;
+
+    let task = session
+        .get_task(id)
+        .await
+        .map_err(|e| HttpError::new(format!("{:#}", e)))?;
+
+    // finished tasks are only kept in history
+    let task_info = if let Some(task) = task {
+        Some(TaskInfo::from(&task))
+    } else {
+        session
+            .get_history()
+            .await
+            .iter()
+            .find(|record| record.task.id == id)
+            .map(TaskInfo::from)
+    };
+
+    let Some(task_info) = task_info else {
+        return Ok((StatusCode::NOT_FOUND, format!("task {} not found", id)).into_response());
+    };
+
+    Ok(Json(task_info).into_response())
+}
+
+pub const CANCEL_PATH: &str = "/api/tasks/:id/cancel";
+
+#[debug_handler]
+pub async fn cancel_handler(
+    headers: HeaderMap,
+    Extension(state): Extension<AppState>,
+    Path(id): Path<i64>,
+) -> axum::response::Result<Response> {
+    authorize(&headers)?;
+
+    cancel_task(state, id)
+        .await
+        .map_err(|e| HttpError::new(format!("{:#}", e)))?;
+
+    Ok(StatusCode::OK.into_response())
+}
+
+fn authorize(headers: &HeaderMap) -> Result<(), StatusCode> {
+    let api_token = ENV.get().unwrap().api_token.as_ref();
+
+    if get_bearer_token(headers).is_some_and(|token| is_token_matched(token, api_token)) {
+        Ok(())
+    } else {
+        Err(StatusCode::UNAUTHORIZED)
+    }
+}
+
+// the message takes the place of the command message of /url and message links
+async fn announce(state: &AppState, response: &str) -> Result<TelegramMessage> {
+    let chat = state
+        .api_chat
+        .lock()
+        .await
+        .ok_or_else(|| anyhow!("no chat is set for api, send /api on in a group first"))?;
+
+    if !state.telegram_user.is_authorized().await? {
+        return Err(anyhow!("telegram user is not logged in, send /auth first"));
+    }
+
+    if state.current_storage_type().await == StorageType::OneDrive
+        && !state.onedrive.is_authorized().await
+    {
+        return Err(anyhow!("onedrive is not authorized, send /auth first"));
+    }
+
+    state
+        .telegram_bot
+        .send_message(chat, InputMessage::html(response))
+        .await
+}
+
+// the error is also replied to the announcement
+async fn reply_on_error<T>(message: TelegramMessage, result: Result<T>) -> Result<T, HttpError> {
+    match result {
+        Ok(value) => Ok(value),
+        Err(e) => {
+            let error = format!("{:#}", e);
+
+            e.send(message).await.unwrap_both().trace();
+
+            Err(HttpError::new(error))
+        }
+    }
+}
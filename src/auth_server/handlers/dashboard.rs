@@ -0,0 +1,291 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use super::{
+    models::TaskInfo,
+    utils::{get_bearer_token, hash_token, is_token_matched},
+};
+use crate::{
+    env::{Env, ENV},
+    error::HttpError,
+    state::AppState,
+    tasker::{cancel_task, retry_task},
+    utils::get_current_timestamp,
+};
+use anyhow::{anyhow, Context, Result};
+use axum::{
+    debug_handler,
+    extract::Path,
+    http::{
+        header::{COOKIE, SET_COOKIE},
+        HeaderMap, StatusCode,
+    },
+    response::{
+        sse::{Event, KeepAlive},
+        Html, IntoResponse, Response, Sse,
+    },
+    Extension, Json,
+};
+use futures::{stream, Stream};
+use rand::{distributions::Alphanumeric, thread_rng, Rng};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, convert::Infallible, sync::Mutex, time::Duration};
+use tokio::fs;
+
+const COOKIE_NAME: &str = "dashboard_session";
+const SESSION_ID_LENGTH: usize = 32;
+// seconds, login is required again after it
+const SESSION_MAX_AGE: i64 = 7 * 24 * 60 * 60;
+
+// hashed session id -> expiration timestamp, sessions are lost after restarting
+static SESSIONS: Mutex<BTreeMap<String, i64>> = Mutex::new(BTreeMap::new());
+
+#[derive(Deserialize)]
+pub struct LoginParams {
+    pub token: String,
+}
+
+#[derive(Serialize)]
+struct Tasks {
+    queue: Vec<TaskInfo>,
+    history: Vec<TaskInfo>,
+}
+
+#[derive(Serialize)]
+struct Accounts {
+    telegram_user_authorized: bool,
+    onedrive_usernames: Vec<String>,
+    onedrive_current_username: Option<String>,
+    onedrive_drive: Option<String>,
+    current_storage: String,
+}
+
+pub const INDEX_PATH: &str = "/dashboard";
+
+#[debug_handler]
+pub async fn index_handler() -> axum::response::Result<Html<String>> {
+    let html = fs::read_to_string("./dashboard.html")
+        .await
+        .context("failed to read dashboard.html")
+        .map_err(|e| HttpError::new(format!("{:#}", e)))?;
+
+    Ok(Html(html))
+}
+
+pub const LOGIN_PATH: &str = "/dashboard/login";
+
+#[debug_handler]
+#[allow(clippy::unused_async)]
+pub async fn login_handler(Json(LoginParams { token }): Json<LoginParams>) -> Response {
+    let Env {
+        dashboard_token,
+        server_uri,
+        ..
+    } = ENV.get().unwrap();
+
+    if !is_token_matched(&token, dashboard_token.as_ref()) {
+        return StatusCode::UNAUTHORIZED.into_response();
+    }
+
+    let session_id = match create_session() {
+        Ok(session_id) => session_id,
+        Err(e) => return HttpError::new(format!("{:#}", e)).into_response(),
+    };
+
+    let mut cookie = format!(
+        "{}={}; Path=/dashboard; Max-Age={}; HttpOnly; SameSite=Strict",
+        COOKIE_NAME, session_id, SESSION_MAX_AGE
+    );
+
+    // browsers only send it back over https then
+    if server_uri.starts_with("https://") {
+        cookie.push_str("; Secure");
+    }
+
+    (StatusCode::OK, [(SET_COOKIE, cookie)]).into_response()
+}
+
+// a random id for each login, so that a leaked cookie expires and doesn't reveal the token
+fn create_session() -> Result<String> {
+    let session_id = thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(SESSION_ID_LENGTH)
+        .map(char::from)
+        .collect::<String>();
+
+    let now = get_current_timestamp();
+
+    let mut sessions = SESSIONS
+        .lock()
+        .map_err(|_| anyhow!("failed to lock dashboard sessions"))?;
+
+    sessions.retain(|_, expiration| *expiration > now);
+    sessions.insert(hash_token(&session_id), now + SESSION_MAX_AGE);
+
+    Ok(session_id)
+}
+
+fn is_session_valid(session_id: &str) -> bool {
+    let now = get_current_timestamp();
+
+    SESSIONS.lock().is_ok_and(|sessions| {
+        sessions
+            .get(&hash_token(session_id))
+            .is_some_and(|expiration| *expiration > now)
+    })
+}
+
+pub const TASKS_PATH: &str = "/dashboard/api/tasks";
+
+#[debug_handler]
+pub async fn tasks_handler(
+    headers: HeaderMap,
+    Extension(state): Extension<AppState>,
+) -> axum::response::Result<Response> {
+    authorize(&headers)?;
+
+    let tasks = get_tasks(&state)
+        .await
+        .map_err(|e| HttpError::new(format!("{:#}", e)))?;
+
+    Ok(Json(tasks).into_response())
+}
+
+pub const EVENTS_PATH: &str = "/dashboard/api/events";
+
+// push tasks every second
+#[debug_handler]
+pub async fn events_handler(
+    headers: HeaderMap,
+    Extension(state): Extension<AppState>,
+) -> axum::response::Result<Sse<impl Stream<Item = Result<Event, Infallible>>>> {
+    authorize(&headers)?;
+
+    let stream = stream::unfold(state, |state| async move {
+        tokio::time::sleep(Duration::from_secs(1)).await;
+
+        let event = get_tasks(&state)
+            .await
+            .and_then(|tasks| {
+                Event::default()
+                    .event("tasks")
+                    .json_data(tasks)
+                    .context("failed to serialize tasks")
+            })
+            .unwrap_or_else(|e| Event::default().event("error").data(format!("{:#}", e)));
+
+        Some((Ok(event), state))
+    });
+
+    Ok(Sse::new(stream).keep_alive(KeepAlive::default()))
+}
+
+pub const ACCOUNTS_PATH: &str = "/dashboard/api/accounts";
+
+#[debug_handler]
+pub async fn accounts_handler(
+    headers: HeaderMap,
+    Extension(state): Extension<AppState>,
+) -> axum::response::Result<Response> {
+    authorize(&headers)?;
+
+    let accounts = get_accounts(&state)
+        .await
+        .map_err(|e| HttpError::new(format!("{:#}", e)))?;
+
+    Ok(Json(accounts).into_response())
+}
+
+pub const CANCEL_PATH: &str = "/dashboard/api/tasks/:id/cancel";
+
+#[debug_handler]
+pub async fn cancel_handler(
+    headers: HeaderMap,
+    Extension(state): Extension<AppState>,
+    Path(id): Path<i64>,
+) -> axum::response::Result<Response> {
+    authorize(&headers)?;
+
+    cancel_task(state, id)
+        .await
+        .map_err(|e| HttpError::new(format!("{:#}", e)))?;
+
+    Ok(StatusCode::OK.into_response())
+}
+
+pub const RETRY_PATH: &str = "/dashboard/api/tasks/:id/retry";
+
+#[debug_handler]
+pub async fn retry_handler(
+    headers: HeaderMap,
+    Extension(state): Extension<AppState>,
+    Path(id): Path<i64>,
+) -> axum::response::Result<Response> {
+    authorize(&headers)?;
+
+    retry_task(state, id)
+        .await
+        .map_err(|e| HttpError::new(format!("{:#}", e)))?;
+
+    Ok(StatusCode::OK.into_response())
+}
+
+async fn get_tasks(state: &AppState) -> Result<Tasks> {
+    let queue = state
+        .task_session
+        .get_tasks()
+        .await?
+        .iter()
+        .map(TaskInfo::from)
+        .collect();
+
+    let history = state
+        .task_session
+        .get_history()
+        .await
+        .iter()
+        .map(TaskInfo::from)
+        .collect();
+
+    Ok(Tasks { queue, history })
+}
+
+async fn get_accounts(state: &AppState) -> Result<Accounts> {
+    let onedrive = &state.onedrive;
+
+    Ok(Accounts {
+        telegram_user_authorized: state.telegram_user.is_authorized().await?,
+        onedrive_usernames: onedrive.get_usernames().await?,
+        onedrive_current_username: onedrive.get_current_username().await?,
+        onedrive_drive: onedrive.get_drive_info().await.map(|drive| drive.name),
+        current_storage: state.current_storage.read().await.to_string(),
+    })
+}
+
+// accept the session cookie set by login, or the token as a bearer token for scripts
+fn authorize(headers: &HeaderMap) -> Result<(), StatusCode> {
+    let dashboard_token = ENV.get().unwrap().dashboard_token.as_ref();
+
+    if get_bearer_token(headers).is_some_and(|token| is_token_matched(token, dashboard_token)) {
+        return Ok(());
+    }
+
+    let session = headers
+        .get_all(COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(';'))
+        .filter_map(|cookie| cookie.trim().split_once('='))
+        .find(|(name, _)| *name == COOKIE_NAME)
+        .map(|(_, value)| value);
+
+    if dashboard_token.is_some() && session.is_some_and(is_session_valid) {
+        Ok(())
+    } else {
+        Err(StatusCode::UNAUTHORIZED)
+    }
+}
@@ -0,0 +1,63 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use super::utils::{get_bearer_token, is_token_matched};
+use crate::{env::ENV, error::HttpError, metrics::METRICS, state::AppState, tasker::TaskStatus};
+use axum::{
+    debug_handler,
+    http::{header::CONTENT_TYPE, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    Extension,
+};
+use std::collections::BTreeMap;
+
+pub const METRICS_PATH: &str = "/metrics";
+
+#[debug_handler]
+pub async fn metrics_handler(
+    headers: HeaderMap,
+    Extension(state): Extension<AppState>,
+) -> axum::response::Result<Response> {
+    authorize(&headers)?;
+
+    let tasks = state
+        .task_session
+        .get_tasks()
+        .await
+        .map_err(|e| HttpError::new(format!("{:#}", e)))?;
+
+    // statuses of unfinished tasks are always exported, even if no task is in them
+    let mut queue_depth = [
+        TaskStatus::Waiting,
+        TaskStatus::Fetched,
+        TaskStatus::Started,
+        TaskStatus::Paused,
+    ]
+    .iter()
+    .map(|status| (status.to_string(), 0))
+    .collect::<BTreeMap<_, _>>();
+
+    for task in tasks {
+        *queue_depth.entry(task.status.to_string()).or_default() += 1;
+    }
+
+    Ok((
+        [(CONTENT_TYPE, "text/plain; version=0.0.4")],
+        METRICS.render(&queue_depth),
+    )
+        .into_response())
+}
+
+fn authorize(headers: &HeaderMap) -> Result<(), StatusCode> {
+    let metrics_token = ENV.get().unwrap().metrics_token.as_ref();
+
+    if get_bearer_token(headers).is_some_and(|token| is_token_matched(token, metrics_token)) {
+        Ok(())
+    } else {
+        Err(StatusCode::UNAUTHORIZED)
+    }
+}
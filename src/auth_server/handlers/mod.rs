@@ -5,6 +5,10 @@
 :license: MIT, see LICENSE for more details.
 */
 
+pub mod api;
+pub mod dashboard;
+pub mod metrics;
 mod models;
 pub mod onedrive;
 pub mod telegram;
+mod utils;
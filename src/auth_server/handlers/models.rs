@@ -5,9 +5,61 @@
 :license: MIT, see LICENSE for more details.
 */
 
-use serde::Deserialize;
+use crate::tasker::{TaskModel, TaskRecord};
+use serde::{Deserialize, Serialize};
 
 #[derive(Deserialize)]
 pub struct CodeParams {
     pub code: String,
 }
+
+#[derive(Serialize)]
+pub struct TaskInfo {
+    pub id: i64,
+    pub filename: String,
+    pub root_path: String,
+    pub storage: String,
+    pub status: String,
+    pub current_length: i64,
+    pub total_length: i64,
+    pub chat_id: i64,
+    pub error: Option<String>,
+    pub finished_at: Option<i64>,
+}
+
+// unfinished task
+impl From<&TaskModel> for TaskInfo {
+    fn from(task: &TaskModel) -> Self {
+        Self {
+            id: task.id,
+            filename: task.filename.clone(),
+            root_path: task.root_path.clone(),
+            storage: task.storage.to_string(),
+            status: task.status.to_string(),
+            current_length: task.current_length,
+            total_length: task.total_length,
+            chat_id: task.chat_id,
+            error: None,
+            finished_at: None,
+        }
+    }
+}
+
+// finished task
+impl From<&TaskRecord> for TaskInfo {
+    fn from(
+        TaskRecord {
+            task,
+            status,
+            error,
+            finished_at,
+        }: &TaskRecord,
+    ) -> Self {
+        Self {
+            status: status.to_string(),
+            error: error.clone(),
+            finished_at: Some(*finished_at),
+            ..Self::from(task)
+        }
+    }
+}
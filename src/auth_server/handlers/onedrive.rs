@@ -6,18 +6,19 @@
 */
 
 use super::models::CodeParams;
-use crate::{auth_server::SenderOD, error::HttpError};
-use axum::{debug_handler, extract::Query, response::Result, Extension};
+use crate::{auth_server::get_code_senders, error::HttpError};
+use axum::{debug_handler, extract::Query, http::StatusCode, response::Result};
 
 pub const CODE_PATH: &str = "/auth";
 
 #[debug_handler]
-pub async fn code_handler(
-    Extension(SenderOD(tx)): Extension<SenderOD>,
-    Query(CodeParams { code }): Query<CodeParams>,
-) -> Result<String> {
+pub async fn code_handler(Query(CodeParams { code }): Query<CodeParams>) -> Result<String> {
     tracing::debug!("received od auth code: {}", code);
 
+    let tx = get_code_senders()
+        .map(|code_senders| code_senders.od)
+        .ok_or(StatusCode::NOT_FOUND)?;
+
     tx.send(code).await.map_err(HttpError::new)?;
 
     Ok("Authorization successful!".to_string())
@@ -6,13 +6,13 @@
 */
 
 use super::models::CodeParams;
-use crate::{auth_server::SenderTG, error::HttpError};
+use crate::{auth_server::get_code_senders, error::HttpError};
 use anyhow::Context;
 use axum::{
     debug_handler,
     http::StatusCode,
     response::{Html, IntoResponse, Response, Result},
-    Extension, Json,
+    Json,
 };
 use tokio::fs;
 
@@ -20,6 +20,11 @@ pub const INDEX_PATH: &str = "/";
 
 #[debug_handler]
 pub async fn index_handler() -> Result<Html<String>> {
+    // only served while a login is pending
+    if get_code_senders().is_none() {
+        return Err(StatusCode::NOT_FOUND.into());
+    }
+
     let html = fs::read_to_string("./index.html")
         .await
         .context("failed to read index.html")
@@ -31,12 +36,13 @@ pub async fn index_handler() -> Result<Html<String>> {
 pub const CODE_PATH: &str = "/tg";
 
 #[debug_handler]
-pub async fn code_handler(
-    Extension(SenderTG(tx)): Extension<SenderTG>,
-    Json(CodeParams { code }): Json<CodeParams>,
-) -> Result<Response> {
+pub async fn code_handler(Json(CodeParams { code }): Json<CodeParams>) -> Result<Response> {
     tracing::debug!("received tg auth code: {}", code);
 
+    let tx = get_code_senders()
+        .map(|code_senders| code_senders.tg)
+        .ok_or(StatusCode::NOT_FOUND)?;
+
     tx.send(code)
         .await
         .context("failed to send tg auth code")
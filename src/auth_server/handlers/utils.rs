@@ -0,0 +1,26 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use axum::http::{header::AUTHORIZATION, HeaderMap};
+use sha2::{Digest, Sha256};
+
+pub fn get_bearer_token(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+}
+
+// false if the expected token is not configured
+pub fn is_token_matched(token: &str, expected_token: Option<&String>) -> bool {
+    // compare digests so that the time taken doesn't depend on the token
+    expected_token.is_some_and(|expected_token| hash_token(expected_token) == hash_token(token))
+}
+
+pub fn hash_token(token: &str) -> String {
+    hex::encode(Sha256::digest(format!("telegram-onedrive:{}", token)))
+}
@@ -12,8 +12,9 @@ mod handlers;
 use crate::{
     env::{Env, ENV},
     error::ResultExt,
+    state::AppState,
 };
-use anyhow::{Context, Result};
+use anyhow::{anyhow, Context, Result};
 use auto_abort::AutoAbortHandle;
 use axum::{
     routing::{get, post},
@@ -21,70 +22,174 @@ use axum::{
 };
 use axum_server::Handle;
 use cert::get_rustls_config;
-use handlers::{onedrive, telegram};
-use std::net::TcpListener;
-use tokio::sync::mpsc::{self, Receiver, Sender};
+use handlers::{api, dashboard, metrics, onedrive, telegram};
+use std::{
+    net::TcpListener,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Mutex,
+    },
+};
+use tokio::{
+    sync::mpsc::{self, Receiver, Sender},
+    task::AbortHandle,
+};
 
 #[derive(Clone)]
-struct SenderTG(Sender<String>);
+struct CodeSenders {
+    login_id: u64,
+    tg: Sender<String>,
+    od: Sender<String>,
+}
 
-#[derive(Clone)]
-struct SenderOD(Sender<String>);
+// replaced on every spawn, so that the persistent server passes codes to the latest authorization
+// None if no login is pending, then auth routes respond with not found
+static CODE_SENDERS: Mutex<Option<CodeSenders>> = Mutex::new(None);
+
+static NEXT_LOGIN_ID: AtomicU64 = AtomicU64::new(0);
+
+static IS_PERSISTENT_SERVER_RUNNING: AtomicBool = AtomicBool::new(false);
+
+fn get_code_senders() -> Option<CodeSenders> {
+    CODE_SENDERS
+        .lock()
+        .ok()
+        .and_then(|code_senders| code_senders.clone())
+}
+
+// a newer login may have replaced the senders already
+fn clear_code_senders(login_id: u64) {
+    if let Ok(mut code_senders) = CODE_SENDERS.lock() {
+        if code_senders
+            .as_ref()
+            .is_some_and(|code_senders| code_senders.login_id == login_id)
+        {
+            *code_senders = None;
+        }
+    }
+}
 
 pub async fn spawn() -> Result<(Receiver<String>, Receiver<String>, AutoAbortHandle)> {
     tracing::debug!("spawning auth server");
 
+    let (tx_tg, rx_tg) = mpsc::channel(1);
+    let (tx_od, rx_od) = mpsc::channel(1);
+
+    let login_id = NEXT_LOGIN_ID.fetch_add(1, Ordering::Relaxed);
+
+    let code_senders = CodeSenders {
+        login_id,
+        tg: tx_tg,
+        od: tx_od,
+    };
+
+    *CODE_SENDERS
+        .lock()
+        .map_err(|_| anyhow!("failed to lock auth code senders"))? = Some(code_senders);
+
+    // auth routes are already served by the persistent server
+    if IS_PERSISTENT_SERVER_RUNNING.load(Ordering::Acquire) {
+        return Ok((rx_tg, rx_od, AutoAbortHandle::without_server(login_id)));
+    }
+
+    let (abort_handle, shutdown_handle) = serve(auth_router(), "auth server").await?;
+
+    let auto_abort_handle = AutoAbortHandle::new(login_id, abort_handle, shutdown_handle);
+
+    Ok((rx_tg, rx_od, auto_abort_handle))
+}
+
+// serve the dashboard, the http api, metrics and auth routes on one port until the bot exits
+pub async fn spawn_persistent(state: AppState) -> Result<()> {
+    tracing::debug!("spawning persistent server");
+
     let Env {
-        port,
-        use_reverse_proxy,
+        dashboard_token,
+        api_token,
+        metrics_token,
         ..
     } = ENV.get().unwrap();
 
-    let (tx_tg, rx_tg) = mpsc::channel(1);
-    let (tx_od, rx_od) = mpsc::channel(1);
+    let mut router = auth_router();
+
+    if dashboard_token.is_some() {
+        router = router
+            .route(dashboard::INDEX_PATH, get(dashboard::index_handler))
+            .route(dashboard::LOGIN_PATH, post(dashboard::login_handler))
+            .route(dashboard::TASKS_PATH, get(dashboard::tasks_handler))
+            .route(dashboard::EVENTS_PATH, get(dashboard::events_handler))
+            .route(dashboard::ACCOUNTS_PATH, get(dashboard::accounts_handler))
+            .route(dashboard::CANCEL_PATH, post(dashboard::cancel_handler))
+            .route(dashboard::RETRY_PATH, post(dashboard::retry_handler));
+    }
+
+    if api_token.is_some() {
+        router = router
+            .route(api::URL_PATH, post(api::url_handler))
+            .route(api::LINK_PATH, post(api::link_handler))
+            .route(api::TASK_PATH, get(api::task_handler))
+            .route(api::CANCEL_PATH, post(api::cancel_handler));
+    }
+
+    if metrics_token.is_some() {
+        router = router.route(metrics::METRICS_PATH, get(metrics::metrics_handler));
+    }
+
+    // handles are dropped without shutting down the server
+    serve(router.layer(Extension(state)), "persistent server").await?;
+
+    IS_PERSISTENT_SERVER_RUNNING.store(true, Ordering::Release);
+
+    Ok(())
+}
 
-    let router = Router::new()
+fn auth_router() -> Router {
+    Router::new()
         .route(telegram::INDEX_PATH, get(telegram::index_handler))
         .route(telegram::CODE_PATH, post(telegram::code_handler))
         .route(onedrive::CODE_PATH, get(onedrive::code_handler))
-        .layer(Extension(SenderTG(tx_tg)))
-        .layer(Extension(SenderOD(tx_od)));
+}
 
-    let server =
-        TcpListener::bind(format!("0.0.0.0:{}", port)).context("failed to create tcp listener")?;
+async fn serve(router: Router, name: &'static str) -> Result<(AbortHandle, Handle)> {
+    let Env {
+        port,
+        use_reverse_proxy,
+        ..
+    } = ENV.get().unwrap();
+
+    let server = TcpListener::bind(format!("0.0.0.0:{}", port))
+        .context(format!("failed to create tcp listener for {}", name))?;
 
     let shutdown_handle = Handle::new();
     let shutdown_handle_clone = shutdown_handle.clone();
 
     let abort_handle = if use_reverse_proxy.to_owned() {
-        tracing::info!("auth server listening on http://0.0.0.0:{}", port);
+        tracing::info!("{} listening on http://0.0.0.0:{}", name, port);
 
         tokio::spawn(async move {
             axum_server::from_tcp(server)
                 .handle(shutdown_handle_clone)
                 .serve(router.into_make_service())
                 .await
-                .context("auth server failed to serve")
+                .context(format!("{} failed to serve", name))
                 .trace();
         })
         .abort_handle()
     } else {
         let config = get_rustls_config().await?;
 
-        tracing::info!("auth server listening on https://0.0.0.0:{}", port);
+        tracing::info!("{} listening on https://0.0.0.0:{}", name, port);
 
         tokio::spawn(async move {
             axum_server::from_tcp_rustls(server, config)
                 .handle(shutdown_handle_clone)
                 .serve(router.into_make_service())
                 .await
-                .context("auth server failed to serve")
+                .context(format!("{} failed to serve", name))
                 .trace();
         })
         .abort_handle()
     };
 
-    let auto_abort_handle = AutoAbortHandle::new(abort_handle, shutdown_handle);
-
-    Ok((rx_tg, rx_od, auto_abort_handle))
+    Ok((abort_handle, shutdown_handle))
 }
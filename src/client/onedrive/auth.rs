@@ -0,0 +1,216 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+// microsoft identity platform authorization code flow, https://learn.microsoft.com/en-us/entra/identity-platform/v2-oauth2-auth-code-flow
+// and device code flow, https://learn.microsoft.com/en-us/entra/identity-platform/v2-oauth2-device-code
+// authority and graph url are configurable for national clouds
+
+use anyhow::{anyhow, Context, Result};
+use serde::{de::DeserializeOwned, Deserialize};
+use std::time::{Duration, Instant};
+use url::Url;
+
+const GLOBAL_GRAPH_URL: &str = "https://graph.microsoft.com";
+const DEVICE_CODE_GRANT_TYPE: &str = "urn:ietf:params:oauth:grant-type:device_code";
+
+#[derive(Deserialize)]
+pub struct TokenResponse {
+    #[serde(rename = "expires_in")]
+    pub expires_in_secs: u64,
+    pub access_token: String,
+    pub refresh_token: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct DeviceCodeResponse {
+    pub device_code: String,
+    pub user_code: String,
+    pub verification_uri: String,
+    pub expires_in: u64,
+    pub interval: u64,
+}
+
+#[derive(Deserialize)]
+struct ErrorResponse {
+    error: String,
+    #[serde(default)]
+    error_description: String,
+}
+
+pub struct Auth {
+    client_id: String,
+    client_secret: String,
+    redirect_uri: String,
+    scope: String,
+    // authority url with tenant
+    endpoint_url: String,
+    // device code flow requires a public client, which shouldn't send client secret
+    is_public_client: bool,
+    http_client: reqwest::Client,
+}
+
+impl Auth {
+    pub fn new(
+        client_id: &str,
+        client_secret: &str,
+        redirect_uri: &str,
+        authority_url: &str,
+        tenant: &str,
+        graph_url: &str,
+        is_public_client: bool,
+    ) -> Self {
+        // scopes of national clouds should be prefixed with their graph url
+        let scope = if graph_url == GLOBAL_GRAPH_URL {
+            "offline_access Files.ReadWrite.All".to_string()
+        } else {
+            format!("offline_access {}/Files.ReadWrite.All", graph_url)
+        };
+
+        Self {
+            client_id: client_id.to_string(),
+            client_secret: client_secret.to_string(),
+            redirect_uri: redirect_uri.to_string(),
+            scope,
+            endpoint_url: format!("{}/{}/oauth2/v2.0", authority_url, tenant),
+            is_public_client,
+            http_client: reqwest::Client::new(),
+        }
+    }
+
+    pub fn code_auth_url(&self) -> Result<Url> {
+        Url::parse_with_params(
+            &format!("{}/authorize", self.endpoint_url),
+            &[
+                ("client_id", self.client_id.as_str()),
+                ("scope", self.scope.as_str()),
+                ("redirect_uri", self.redirect_uri.as_str()),
+                ("response_type", "code"),
+            ],
+        )
+        .context("failed to build onedrive auth url")
+    }
+
+    pub async fn login_with_code(&self, code: &str) -> Result<TokenResponse> {
+        self.request_token(&[
+            ("grant_type", "authorization_code"),
+            ("code", code),
+            ("redirect_uri", self.redirect_uri.as_str()),
+        ])
+        .await
+    }
+
+    pub async fn login_with_refresh_token(&self, refresh_token: &str) -> Result<TokenResponse> {
+        self.request_token(&[
+            ("grant_type", "refresh_token"),
+            ("refresh_token", refresh_token),
+            ("redirect_uri", self.redirect_uri.as_str()),
+        ])
+        .await
+    }
+
+    pub async fn request_device_code(&self) -> Result<DeviceCodeResponse> {
+        self.post_endpoint("devicecode", &[])
+            .await?
+            .map_err(|e| anyhow!("{}: {}", e.error, e.error_description))
+            .context("failed to get onedrive device code")
+    }
+
+    pub async fn login_with_device_code(
+        &self,
+        device_code: &DeviceCodeResponse,
+    ) -> Result<TokenResponse> {
+        let deadline = Instant::now() + Duration::from_secs(device_code.expires_in);
+        let mut interval = device_code.interval.max(1);
+
+        loop {
+            tokio::time::sleep(Duration::from_secs(interval)).await;
+
+            if Instant::now() > deadline {
+                return Err(anyhow!("onedrive device code expired"));
+            }
+
+            let result = self
+                .post_endpoint(
+                    "token",
+                    &[
+                        ("grant_type", DEVICE_CODE_GRANT_TYPE),
+                        ("device_code", device_code.device_code.as_str()),
+                    ],
+                )
+                .await?;
+
+            match result {
+                Ok(token_response) => return Ok(token_response),
+                // the user hasn't finished signing in yet
+                Err(e) if e.error == "authorization_pending" => {}
+                Err(e) if e.error == "slow_down" => interval += 5,
+                Err(e) => {
+                    return Err(anyhow!("{}: {}", e.error, e.error_description))
+                        .context("failed to get onedrive token with device code")
+                }
+            }
+        }
+    }
+
+    async fn request_token(&self, params: &[(&str, &str)]) -> Result<TokenResponse> {
+        self.post_endpoint("token", params)
+            .await?
+            .map_err(|e| anyhow!("{}: {}", e.error, e.error_description))
+            .context("failed to get onedrive token")
+    }
+
+    async fn post_endpoint<T>(
+        &self,
+        endpoint: &str,
+        params: &[(&str, &str)],
+    ) -> Result<Result<T, ErrorResponse>>
+    where
+        T: DeserializeOwned,
+    {
+        let mut form = vec![
+            ("client_id", self.client_id.as_str()),
+            ("scope", self.scope.as_str()),
+        ];
+
+        if !self.is_public_client {
+            form.push(("client_secret", self.client_secret.as_str()));
+        }
+
+        form.extend_from_slice(params);
+
+        let response = self
+            .http_client
+            .post(format!("{}/{}", self.endpoint_url, endpoint))
+            .form(&form)
+            .send()
+            .await
+            .context(format!("failed to send request for onedrive {}", endpoint))?;
+
+        let status = response.status();
+
+        let content = response
+            .text()
+            .await
+            .context(format!("failed to get onedrive {} response text", endpoint))?;
+
+        if !status.is_success() {
+            let error = serde_json::from_str(&content).unwrap_or(ErrorResponse {
+                error: status.to_string(),
+                error_description: content,
+            });
+
+            return Ok(Err(error));
+        }
+
+        let value = serde_json::from_str(&content).context(format!(
+            "failed to deserialize onedrive {} response",
+            endpoint
+        ))?;
+
+        Ok(Ok(value))
+    }
+}
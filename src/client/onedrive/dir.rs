@@ -6,6 +6,7 @@
 */
 
 use super::{utils::validate_root_path, OneDriveClient};
+use crate::settings::TEMP_ROOT_PATH_KEY;
 use anyhow::Result;
 
 impl OneDriveClient {
@@ -17,6 +18,8 @@ impl OneDriveClient {
             tracing::debug!("get root path from temp and should be consumed");
 
             let temp_root_path = temp_root_path_read.clone();
+            // clearing needs the write lock
+            drop(temp_root_path_read);
             self.clear_temp_root_path().await?;
 
             temp_root_path
@@ -47,6 +50,7 @@ impl OneDriveClient {
 
     pub async fn set_root_path(&self, path: &str) -> Result<()> {
         validate_root_path(path)?;
+        self.validate_dir(path).await?;
 
         self.clear_temp_root_path().await?;
 
@@ -80,6 +84,20 @@ impl OneDriveClient {
     pub async fn set_temp_root_path(&self, path: &str) -> Result<()> {
         validate_root_path(path)?;
 
+        if !path.is_empty() {
+            self.validate_dir(path).await?;
+        }
+
+        if path.is_empty() {
+            self.settings_session
+                .delete_value(TEMP_ROOT_PATH_KEY)
+                .await?;
+        } else {
+            self.settings_session
+                .set_value(TEMP_ROOT_PATH_KEY, path)
+                .await?;
+        }
+
         *self.temp_root_path.write().await = path.to_string();
 
         tracing::info!("onedrive temp root path: {}", path);
@@ -5,9 +5,9 @@
 :license: MIT, see LICENSE for more details.
 */
 
-use super::OneDriveClient;
+use super::{utils::get_drive_location, OneDriveClient};
 use anyhow::Result;
-use onedrive_api::{DriveLocation, OneDrive};
+use onedrive_api::OneDrive;
 
 impl OneDriveClient {
     pub async fn get_usernames(&self) -> Result<Vec<String>> {
@@ -23,8 +23,10 @@ impl OneDriveClient {
 
         session.change_session(username).await?;
 
-        *self.client.write().await =
-            OneDrive::new(session.access_token.clone(), DriveLocation::me());
+        *self.client.write().await = OneDrive::new(
+            session.access_token.clone(),
+            get_drive_location(&session.drive_id),
+        );
 
         tracing::debug!("change account to {}", username);
 
@@ -0,0 +1,133 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use super::OneDriveClient;
+use anyhow::{anyhow, Context, Result};
+use percent_encoding::{utf8_percent_encode, AsciiSet, CONTROLS};
+use reqwest::{header, Method, RequestBuilder, Response};
+use serde_json::Value;
+
+const GRAPH_VERSION: &str = "v1.0";
+
+// / is kept as the path separator
+const PATH_ENCODE_SET: &AsciiSet = &CONTROLS
+    .add(b' ')
+    .add(b'"')
+    .add(b'#')
+    .add(b'%')
+    .add(b'<')
+    .add(b'>')
+    .add(b'?')
+    .add(b'`')
+    .add(b'{')
+    .add(b'}');
+
+impl OneDriveClient {
+    pub(super) async fn graph_request(&self, method: Method, path: &str) -> Result<RequestBuilder> {
+        self.refresh_access_token().await?;
+
+        let access_token = self.client.read().await.access_token().to_string();
+
+        Ok(self
+            .graph_request_with_token(method, path, &access_token)
+            .await)
+    }
+
+    pub(super) async fn graph_request_with_token(
+        &self,
+        method: Method,
+        path: &str,
+        access_token: &str,
+    ) -> RequestBuilder {
+        let url = format!("{}/{}{}", self.graph_url, GRAPH_VERSION, path);
+
+        tracing::debug!("graph request: {} {}", method, url);
+
+        self.client
+            .read()
+            .await
+            .client()
+            .request(method, url)
+            .header(header::AUTHORIZATION, format!("Bearer {}", access_token))
+    }
+}
+
+// graph api path of a drive, the personal drive of the user if drive id is empty
+pub fn drive_path(drive_id: &str) -> String {
+    if drive_id.is_empty() {
+        "/me/drive".to_string()
+    } else {
+        format!("/drives/{}", utf8_percent_encode(drive_id, PATH_ENCODE_SET))
+    }
+}
+
+impl OneDriveClient {
+    pub(super) async fn current_drive_path(&self) -> String {
+        drive_path(&self.session.read().await.drive_id)
+    }
+
+    // graph api path of an item addressed by its path in the selected drive
+    pub(super) async fn item_path_url(&self, path: &str) -> String {
+        item_path(&self.current_drive_path().await, path)
+    }
+}
+
+// graph api path of an item addressed by its path in a drive
+pub fn item_path(drive_path: &str, path: &str) -> String {
+    let path = path.trim_end_matches('/');
+
+    if path.is_empty() {
+        format!("{}/root", drive_path)
+    } else {
+        format!(
+            "{}/root:{}:",
+            drive_path,
+            utf8_percent_encode(path, PATH_ENCODE_SET)
+        )
+    }
+}
+
+pub async fn parse_graph_response(response: Response) -> Result<Value> {
+    let status = response.status();
+
+    let content = response
+        .text()
+        .await
+        .context("failed to get graph response text")?;
+
+    if !status.is_success() {
+        let error = serde_json::from_str::<Value>(&content)
+            .ok()
+            .and_then(|value| value.get("error").cloned());
+
+        let (code, message) = error.map_or_else(
+            || (status.to_string(), content.clone()),
+            |error| {
+                (
+                    error
+                        .get("code")
+                        .and_then(Value::as_str)
+                        .unwrap_or_default()
+                        .to_string(),
+                    error
+                        .get("message")
+                        .and_then(Value::as_str)
+                        .unwrap_or_default()
+                        .to_string(),
+                )
+            },
+        );
+
+        return Err(anyhow!("{}: {}", code, message)).context("graph request failed");
+    }
+
+    if content.is_empty() {
+        return Ok(Value::Null);
+    }
+
+    serde_json::from_str(&content).context("failed to deserialize graph response")
+}
@@ -0,0 +1,230 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use super::{
+    graph::parse_graph_response,
+    utils::{split_item_path, validate_item_name, validate_root_path},
+    OneDriveClient,
+};
+use anyhow::{anyhow, Context, Result};
+use reqwest::{header, Method, StatusCode};
+use serde_json::{json, Value};
+use std::time::Duration;
+
+const COPY_MONITOR_MAX_POLLS: u32 = 30;
+
+pub enum CopyStatus {
+    Completed,
+    InProgress,
+}
+
+impl OneDriveClient {
+    pub async fn create_folder(&self, path: &str) -> Result<()> {
+        validate_root_path(path)?;
+
+        let (parent, name) = split_item_path(path)?;
+        validate_item_name(&name)?;
+
+        let body = json!({
+            "name": name,
+            "folder": {},
+            "@microsoft.graph.conflictBehavior": "fail",
+        });
+
+        let url = format!("{}/children", self.item_path_url(&parent).await);
+
+        self.send_item_request(Method::POST, &url, Some(body))
+            .await
+            .context("failed to create folder")?;
+
+        tracing::info!("created onedrive folder {}", path);
+
+        Ok(())
+    }
+
+    pub async fn move_item(&self, src: &str, dst_folder: &str) -> Result<()> {
+        validate_root_path(src)?;
+        validate_root_path(dst_folder)?;
+        split_item_path(src)?;
+
+        let (drive_id, folder_id) = self.get_item_ids(dst_folder).await?;
+
+        let body = json!({
+            "parentReference": {
+                "driveId": drive_id,
+                "id": folder_id,
+            },
+        });
+
+        let url = self.item_path_url(src).await;
+
+        self.send_item_request(Method::PATCH, &url, Some(body))
+            .await
+            .context("failed to move item")?;
+
+        tracing::info!("moved onedrive item {} to {}", src, dst_folder);
+
+        Ok(())
+    }
+
+    pub async fn copy_item(&self, src: &str, dst_folder: &str) -> Result<CopyStatus> {
+        validate_root_path(src)?;
+        validate_root_path(dst_folder)?;
+        split_item_path(src)?;
+
+        let (drive_id, folder_id) = self.get_item_ids(dst_folder).await?;
+
+        let body = json!({
+            "parentReference": {
+                "driveId": drive_id,
+                "id": folder_id,
+            },
+        });
+
+        let url = format!("{}/copy", self.item_path_url(src).await);
+
+        let response = self
+            .graph_request(Method::POST, &url)
+            .await?
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(body.to_string())
+            .send()
+            .await
+            .context("failed to send request for copying item")?;
+
+        if response.status() != StatusCode::ACCEPTED {
+            parse_graph_response(response)
+                .await
+                .context("failed to copy item")?;
+
+            return Err(anyhow!("copy request is not accepted"));
+        }
+
+        let monitor_url = response
+            .headers()
+            .get(header::LOCATION)
+            .ok_or_else(|| anyhow!("monitor url not found in copy response"))?
+            .to_str()
+            .context("header Location has invisible ASCII chars")?
+            .to_string();
+
+        tracing::info!("copying onedrive item {} to {}", src, dst_folder);
+
+        self.wait_for_copy(&monitor_url).await
+    }
+
+    async fn wait_for_copy(&self, monitor_url: &str) -> Result<CopyStatus> {
+        let http_client = self.client.read().await.client().clone();
+
+        for _ in 0..COPY_MONITOR_MAX_POLLS {
+            // monitor url doesn't need authorization
+            let response = http_client
+                .get(monitor_url)
+                .send()
+                .await
+                .context("failed to send request for copy status")?;
+
+            let status = parse_graph_response(response)
+                .await
+                .context("failed to get copy status")?;
+
+            match status.get("status").and_then(Value::as_str) {
+                Some("completed") => return Ok(CopyStatus::Completed),
+                Some("failed") => {
+                    return Err(anyhow!("{}", status)).context("onedrive failed to copy item")
+                }
+                _ => tokio::time::sleep(Duration::from_secs(2)).await,
+            }
+        }
+
+        Ok(CopyStatus::InProgress)
+    }
+
+    // onedrive moves deleted items to the recycle bin
+    pub async fn remove_item(&self, path: &str) -> Result<()> {
+        validate_root_path(path)?;
+        split_item_path(path)?;
+
+        let url = self.item_path_url(path).await;
+
+        self.send_item_request(Method::DELETE, &url, None)
+            .await
+            .context("failed to remove item")?;
+
+        tracing::info!("removed onedrive item {}", path);
+
+        Ok(())
+    }
+
+    pub async fn rename_item(&self, path: &str, new_name: &str) -> Result<()> {
+        validate_root_path(path)?;
+        split_item_path(path)?;
+        validate_item_name(new_name)?;
+
+        let body = json!({
+            "name": new_name,
+            "@microsoft.graph.conflictBehavior": "fail",
+        });
+
+        let url = self.item_path_url(path).await;
+
+        self.send_item_request(Method::PATCH, &url, Some(body))
+            .await
+            .context("failed to rename item")?;
+
+        tracing::info!("renamed onedrive item {} to {}", path, new_name);
+
+        Ok(())
+    }
+
+    // (drive id, item id)
+    async fn get_item_ids(&self, path: &str) -> Result<(String, String)> {
+        let url = self.item_path_url(path).await;
+
+        let item = self
+            .send_item_request(Method::GET, &url, None)
+            .await
+            .context(format!("failed to get item {}", path))?;
+
+        let item_id = item
+            .get("id")
+            .and_then(Value::as_str)
+            .ok_or_else(|| anyhow!("field id not found in drive item"))?
+            .to_string();
+
+        let drive_id = item
+            .get("parentReference")
+            .and_then(|parent| parent.get("driveId"))
+            .and_then(Value::as_str)
+            .ok_or_else(|| anyhow!("field parentReference.driveId not found in drive item"))?
+            .to_string();
+
+        Ok((drive_id, item_id))
+    }
+
+    async fn send_item_request(
+        &self,
+        method: Method,
+        path: &str,
+        body: Option<Value>,
+    ) -> Result<Value> {
+        let mut request = self.graph_request(method, path).await?;
+
+        if let Some(body) = body {
+            request = request
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(body.to_string());
+        }
+
+        let response = request
+            .send()
+            .await
+            .context("failed to send request for drive item")?;
+
+        parse_graph_response(response).await
+    }
+}
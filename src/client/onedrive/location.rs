@@ -0,0 +1,244 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use super::{graph::parse_graph_response, utils::get_drive_location, OneDriveClient};
+use anyhow::{anyhow, Context, Result};
+use onedrive_api::OneDrive as Client;
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+use reqwest::{Method, StatusCode};
+use serde_json::Value;
+use url::Url;
+
+const DRIVE_SELECT_QUERY: &str = "?$select=id,name,driveType";
+
+pub struct DriveInfo {
+    pub id: String,
+    pub name: String,
+}
+
+impl DriveInfo {
+    fn from_drive(drive: &Value, owner: Option<&str>) -> Result<Self> {
+        let get_field = |name: &str| {
+            drive
+                .get(name)
+                .and_then(Value::as_str)
+                .ok_or_else(|| anyhow!("field {} not found in drive", name))
+        };
+
+        let id = get_field("id")?.to_string();
+        let drive_name = get_field("name")?;
+
+        let name = owner.map_or_else(
+            || {
+                format!(
+                    "{} ({})",
+                    drive_name,
+                    get_field("driveType").unwrap_or_default()
+                )
+            },
+            |owner| format!("{} ({})", drive_name, owner),
+        );
+
+        Ok(Self { id, name })
+    }
+}
+
+impl OneDriveClient {
+    // None if the personal drive is used
+    pub async fn get_drive_info(&self) -> Option<DriveInfo> {
+        let session = self.session.read().await;
+
+        if session.drive_id.is_empty() {
+            return None;
+        }
+
+        Some(DriveInfo {
+            id: session.drive_id.clone(),
+            name: session.drive_name.clone(),
+        })
+    }
+
+    pub async fn get_drive_id(&self) -> Result<String> {
+        let drive = self
+            .send_drive_request(&format!("{}?$select=id", self.current_drive_path().await))
+            .await
+            .context("failed to get drive")?;
+
+        drive
+            .get("id")
+            .and_then(Value::as_str)
+            .map(ToString::to_string)
+            .ok_or_else(|| anyhow!("field id not found in drive"))
+    }
+
+    // site can be a site url, hostname:/server-relative-path, or a site id
+    pub async fn find_site_drive(&self, site: &str, library: Option<&str>) -> Result<DriveInfo> {
+        let site = self
+            .send_drive_request(&format!(
+                "/sites/{}?$select=id,displayName",
+                site_key(site)?
+            ))
+            .await
+            .context("failed to get sharepoint site")?;
+
+        let site_id = site
+            .get("id")
+            .and_then(Value::as_str)
+            .ok_or_else(|| anyhow!("field id not found in site"))?;
+
+        let site_name = site
+            .get("displayName")
+            .and_then(Value::as_str)
+            .unwrap_or(site_id);
+
+        let Some(library) = library else {
+            let drive = self
+                .send_drive_request(&format!("/sites/{}/drive{}", site_id, DRIVE_SELECT_QUERY))
+                .await
+                .context("failed to get default document library of site")?;
+
+            return DriveInfo::from_drive(&drive, Some(site_name));
+        };
+
+        let drives = self
+            .send_drive_request(&format!("/sites/{}/drives{}", site_id, DRIVE_SELECT_QUERY))
+            .await
+            .context("failed to list document libraries of site")?;
+
+        let drive = drives
+            .get("value")
+            .and_then(Value::as_array)
+            .ok_or_else(|| anyhow!("field value not found in drive list"))?
+            .iter()
+            .find(|drive| {
+                drive
+                    .get("name")
+                    .and_then(Value::as_str)
+                    .is_some_and(|name| name.eq_ignore_ascii_case(library))
+            })
+            .ok_or_else(|| {
+                anyhow!(
+                    "document library {} not found in site {}",
+                    library,
+                    site_name
+                )
+            })?;
+
+        DriveInfo::from_drive(drive, Some(site_name))
+    }
+
+    pub async fn find_group_drive(&self, group_id: &str) -> Result<DriveInfo> {
+        let drive = self
+            .send_drive_request(&format!(
+                "/groups/{}/drive{}",
+                utf8_percent_encode(group_id, NON_ALPHANUMERIC),
+                DRIVE_SELECT_QUERY
+            ))
+            .await
+            .context("failed to get group drive")?;
+
+        DriveInfo::from_drive(&drive, None)
+    }
+
+    pub async fn find_drive(&self, drive_id: &str) -> Result<DriveInfo> {
+        let drive = self
+            .send_drive_request(&format!(
+                "/drives/{}{}",
+                utf8_percent_encode(drive_id, NON_ALPHANUMERIC),
+                DRIVE_SELECT_QUERY
+            ))
+            .await
+            .context("failed to get drive")?;
+
+        DriveInfo::from_drive(&drive, None)
+    }
+
+    // None to use the personal drive
+    pub async fn set_drive(&self, drive: Option<DriveInfo>) -> Result<()> {
+        let mut session = self.session.write().await;
+
+        let DriveInfo { id, name } = drive.unwrap_or(DriveInfo {
+            id: String::new(),
+            name: String::new(),
+        });
+
+        session.drive_id = id;
+        session.drive_name = name;
+        session.save().await?;
+
+        *self.client.write().await = Client::new(
+            session.access_token.clone(),
+            get_drive_location(&session.drive_id),
+        );
+
+        tracing::info!(
+            "set onedrive drive of {} to {}",
+            session.username,
+            session.drive_id
+        );
+
+        Ok(())
+    }
+
+    // a directory is valid in the selected drive if it doesn't exist yet or is a folder
+    pub async fn validate_dir(&self, path: &str) -> Result<()> {
+        let url = self.item_path_url(path).await;
+
+        let response = self
+            .graph_request(Method::GET, &url)
+            .await?
+            .send()
+            .await
+            .context("failed to send request for directory")?;
+
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(());
+        }
+
+        let item = parse_graph_response(response)
+            .await
+            .context(format!("failed to get directory {}", path))?;
+
+        if item.get("folder").is_none() {
+            return Err(anyhow!("{} is not a directory in the selected drive", path));
+        }
+
+        Ok(())
+    }
+
+    async fn send_drive_request(&self, path: &str) -> Result<Value> {
+        let response = self
+            .graph_request(Method::GET, path)
+            .await?
+            .send()
+            .await
+            .context("failed to send request for drive")?;
+
+        parse_graph_response(response).await
+    }
+}
+
+// graph api addresses a site by hostname:/server-relative-path
+fn site_key(site: &str) -> Result<String> {
+    if !site.starts_with("https://") && !site.starts_with("http://") {
+        return Ok(site.to_string());
+    }
+
+    let url = Url::parse(site).context("failed to parse site url")?;
+
+    let host = url
+        .host_str()
+        .ok_or_else(|| anyhow!("site url has no host"))?;
+
+    let path = url.path().trim_end_matches('/');
+
+    if path.is_empty() {
+        Ok(host.to_string())
+    } else {
+        Ok(format!("{}:{}", host, path))
+    }
+}
@@ -5,38 +5,50 @@
 :license: MIT, see LICENSE for more details.
 */
 
+mod auth;
 mod dir;
 mod drive;
+mod graph;
 pub mod invalid_name;
+pub mod item;
+pub mod location;
+mod quota;
 mod session;
+pub mod share;
 mod upload;
 mod utils;
 
 use crate::{
     env::{Env, OneDriveEnv, ENV},
     message::TelegramMessage,
+    metrics::METRICS,
+    settings::{SettingsSession, TEMP_ROOT_PATH_KEY},
 };
 use anyhow::{anyhow, Context, Result};
-use onedrive_api::{
-    Auth, ClientCredential, DriveLocation, OneDrive as Client, Permission, Tenant, TokenResponse,
-};
+use auth::{Auth, DeviceCodeResponse, TokenResponse};
+use onedrive_api::{DriveLocation, OneDrive as Client};
 use path_slash::PathBufExt;
 use session::OneDriveSession;
 use std::path::Path;
 use tokio::sync::{mpsc::Receiver, RwLock};
+use utils::get_drive_location;
+pub use utils::split_item_path;
 
 pub struct OneDriveClient {
     client: RwLock<Client>,
     session: RwLock<OneDriveSession>,
     auth_provider: Auth,
-    client_secret: String,
+    graph_url: String,
+    pub use_device_code: bool,
     session_path: String,
     pub default_root_path: String,
     temp_root_path: RwLock<String>,
+    // temporary root path is kept across restarts
+    settings_session: SettingsSession,
 }
 
 impl OneDriveClient {
-    pub async fn new() -> Result<Self> {
+    pub async fn new(settings_session: SettingsSession) -> Result<Self> {
         let Env {
             onedrive:
                 OneDriveEnv {
@@ -44,7 +56,10 @@ impl OneDriveClient {
                     client_secret,
                     session_path,
                     root_path,
-                    ..
+                    tenant,
+                    authority_url,
+                    graph_url,
+                    use_device_code,
                 },
             server_uri,
             ..
@@ -58,22 +73,29 @@ impl OneDriveClient {
         );
         let auth_provider = Auth::new(
             client_id,
-            Permission::new_read()
-                .write(true)
-                .access_shared(true)
-                .offline_access(true),
-            Path::new(server_uri).join("auth").to_slash_lossy(),
-            Tenant::Common,
+            client_secret,
+            &Path::new(server_uri).join("auth").to_slash_lossy(),
+            authority_url,
+            tenant,
+            graph_url,
+            *use_device_code,
         );
 
+        let temp_root_path = settings_session
+            .get_value(TEMP_ROOT_PATH_KEY)
+            .await?
+            .unwrap_or_default();
+
         let onedrive_client = Self {
             client,
             session,
             auth_provider,
-            client_secret: client_secret.clone(),
+            graph_url: graph_url.clone(),
+            use_device_code: *use_device_code,
             session_path: session_path.clone(),
             default_root_path: root_path.to_string(),
-            temp_root_path: RwLock::new(String::new()),
+            temp_root_path: RwLock::new(temp_root_path),
+            settings_session,
         };
 
         let _ = onedrive_client.auto_login().await;
@@ -89,29 +111,13 @@ impl OneDriveClient {
     ) -> Result<()> {
         tracing::info!("logging in to onedrive");
 
-        if !should_add {
-            tracing::debug!("onedrive account should not be added");
-
-            if self.is_authorized().await {
-                tracing::debug!("onedrive account has been authorized");
-
-                return Ok(());
-            }
-
-            tracing::info!("onedrive account is not authorized, auto login");
-
-            if self.auto_login().await.is_ok() {
-                tracing::info!("onedrive auto login successful");
-
-                return Ok(());
-            }
-
-            tracing::info!("onedrive auto login failed, login manually");
+        if !should_add && self.try_existing_login().await {
+            return Ok(());
         }
 
         let response = format!(
             "Here are the authorization url of OneDrive:\n\n{}",
-            self.get_auth_url()
+            self.get_auth_url()?
         );
         message.respond(response.as_str()).await.context(response)?;
 
@@ -130,17 +136,90 @@ impl OneDriveClient {
 
         tracing::info!("onedrive authorizing");
 
-        let TokenResponse {
-            expires_in_secs,
-            access_token,
-            refresh_token,
-            ..
-        } = self
+        let token_response = self
             .auth_provider
-            .login_with_code(&code, &ClientCredential::Secret(self.client_secret.clone()))
+            .login_with_code(&code)
             .await
             .context("failed to get onedrive token response when login with code")?;
 
+        self.save_token_response(token_response).await
+    }
+
+    // device code flow doesn't need the auth server to receive the code
+    pub async fn login_with_device_code(
+        &self,
+        message: TelegramMessage,
+        should_add: bool,
+    ) -> Result<()> {
+        tracing::info!("logging in to onedrive with device code");
+
+        if !should_add && self.try_existing_login().await {
+            return Ok(());
+        }
+
+        let device_code = self.auth_provider.request_device_code().await?;
+
+        let DeviceCodeResponse {
+            user_code,
+            verification_uri,
+            expires_in,
+            ..
+        } = &device_code;
+
+        let response = format!(
+            "To authorize OneDrive, open\n\n{}\n\nand enter the code\n\n{}\n\nThe code expires in {} minutes.",
+            verification_uri,
+            user_code,
+            expires_in / 60
+        );
+        message.respond(response.as_str()).await.context(response)?;
+
+        tracing::info!("onedrive device code sent");
+
+        let token_response = self
+            .auth_provider
+            .login_with_device_code(&device_code)
+            .await?;
+
+        let response = "Device code accepted, authorizing...";
+        message.respond(response).await.context(response)?;
+
+        tracing::info!("onedrive authorizing");
+
+        self.save_token_response(token_response).await
+    }
+
+    // true if current account is authorized or can be logged in with the saved session
+    async fn try_existing_login(&self) -> bool {
+        tracing::debug!("onedrive account should not be added");
+
+        if self.is_authorized().await {
+            tracing::debug!("onedrive account has been authorized");
+
+            return true;
+        }
+
+        tracing::info!("onedrive account is not authorized, auto login");
+
+        if self.auto_login().await.is_ok() {
+            tracing::info!("onedrive auto login successful");
+
+            return true;
+        }
+
+        tracing::info!("onedrive auto login failed, login manually");
+
+        false
+    }
+
+    async fn save_token_response(
+        &self,
+        TokenResponse {
+            expires_in_secs,
+            access_token,
+            refresh_token,
+        }: TokenResponse,
+    ) -> Result<()> {
         let refresh_token = refresh_token.ok_or_else(|| {
             anyhow!("failed to receive onedrive refresh token when login with code")
         })?;
@@ -149,8 +228,9 @@ impl OneDriveClient {
 
         tracing::info!("onedrive authorized");
 
-        let session = OneDriveSession::new(
+        let mut session = OneDriveSession::new(
             &client,
+            &self.graph_url,
             expires_in_secs,
             &access_token,
             &refresh_token,
@@ -159,8 +239,16 @@ impl OneDriveClient {
         )
         .await?;
 
+        // keep the drive selected before logging in again
+        if let Ok(old_session) = session.get_user_session(&session.username).await {
+            session.drive_id = old_session.drive_id;
+            session.drive_name = old_session.drive_name;
+        }
+
         session.save().await?;
 
+        let client = Client::new(&access_token, get_drive_location(&session.drive_id));
+
         if let Some(username) = self.get_current_username().await? {
             if username == session.username {
                 self.session.write().await.overwrite(session);
@@ -183,7 +271,8 @@ impl OneDriveClient {
             .await?;
 
         let access_token = token_response.access_token;
-        *self.client.write().await = Client::new(&access_token, DriveLocation::me());
+        *self.client.write().await =
+            Client::new(&access_token, get_drive_location(&session.drive_id));
 
         session.refresh_token = token_response.refresh_token.ok_or_else(|| {
             anyhow!("failed to receive onedrive refresh token when login with refresh token")
@@ -202,20 +291,17 @@ impl OneDriveClient {
         refresh_token: &str,
     ) -> Result<TokenResponse> {
         self.auth_provider
-            .login_with_refresh_token(
-                refresh_token,
-                &ClientCredential::Secret(self.client_secret.clone()),
-            )
+            .login_with_refresh_token(refresh_token)
             .await
             .context("failed to get refresh token response when login with refresh token")
     }
 
-    pub fn get_auth_url(&self) -> String {
-        let auth_url = self.auth_provider.code_auth_url().to_string();
+    pub fn get_auth_url(&self) -> Result<String> {
+        let auth_url = self.auth_provider.code_auth_url()?.to_string();
 
         tracing::info!("onedrive auth url: {}", auth_url);
 
-        auth_url
+        Ok(auth_url)
     }
 
     pub async fn is_authorized(&self) -> bool {
@@ -225,7 +311,7 @@ impl OneDriveClient {
             self.refresh_access_token().await.ok();
         }
 
-        self.client.read().await.get_drive().await.is_ok()
+        self.get_drive_id().await.is_ok()
     }
 
     pub async fn set_current_user(&self) -> Result<()> {
@@ -236,7 +322,8 @@ impl OneDriveClient {
         let mut session = self.session.write().await;
         session.remove_user(username).await?;
 
-        *self.client.write().await = Client::new(&session.access_token, DriveLocation::me());
+        *self.client.write().await =
+            Client::new(&session.access_token, get_drive_location(&session.drive_id));
 
         Ok(())
     }
@@ -249,7 +336,8 @@ impl OneDriveClient {
 
             let token_response = self
                 .get_token_using_refresh_token(&session.refresh_token)
-                .await?;
+                .await
+                .inspect_err(|_| METRICS.add_token_refresh_failure())?;
 
             session.access_token = token_response.access_token;
             session.refresh_token = token_response.refresh_token.ok_or_else(|| {
@@ -259,8 +347,10 @@ impl OneDriveClient {
 
             session.save().await?;
 
-            *self.client.write().await =
-                Client::new(session.access_token.clone(), DriveLocation::me());
+            *self.client.write().await = Client::new(
+                session.access_token.clone(),
+                get_drive_location(&session.drive_id),
+            );
         }
 
         Ok(())
@@ -0,0 +1,115 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use super::{
+    graph::{drive_path, parse_graph_response},
+    OneDriveClient,
+};
+use crate::metrics::METRICS;
+use anyhow::{anyhow, Context, Result};
+use reqwest::Method;
+use serde_json::Value;
+
+const DRIVE_QUOTA_QUERY: &str = "?$select=quota";
+
+pub struct DriveQuota {
+    pub total: u64,
+    pub used: u64,
+    pub remaining: u64,
+}
+
+impl DriveQuota {
+    fn from_drive(drive: &Value) -> Result<Self> {
+        let quota = drive
+            .get("quota")
+            .ok_or_else(|| anyhow!("field quota not found in drive"))?;
+
+        let get_field = |name: &str| {
+            quota
+                .get(name)
+                .and_then(Value::as_u64)
+                .ok_or_else(|| anyhow!("field quota.{} not found in drive", name))
+        };
+
+        Ok(Self {
+            total: get_field("total")?,
+            used: get_field("used")?,
+            remaining: get_field("remaining")?,
+        })
+    }
+}
+
+impl OneDriveClient {
+    pub async fn get_quota(&self) -> Result<DriveQuota> {
+        let url = format!("{}{}", self.current_drive_path().await, DRIVE_QUOTA_QUERY);
+
+        let response = self
+            .graph_request(Method::GET, &url)
+            .await?
+            .send()
+            .await
+            .context("failed to send request for drive quota")?;
+
+        let drive = parse_graph_response(response)
+            .await
+            .context("failed to get drive quota")?;
+
+        DriveQuota::from_drive(&drive)
+    }
+
+    pub async fn get_user_quota(&self, username: &str) -> Result<DriveQuota> {
+        let (access_token, drive_id) = self.get_user_access_token(username).await?;
+
+        let url = format!("{}{}", drive_path(&drive_id), DRIVE_QUOTA_QUERY);
+
+        let response = self
+            .graph_request_with_token(Method::GET, &url, &access_token)
+            .await
+            .send()
+            .await
+            .context("failed to send request for drive quota")?;
+
+        let drive = parse_graph_response(response)
+            .await
+            .context("failed to get drive quota")
+            .context(username.to_string())?;
+
+        DriveQuota::from_drive(&drive)
+    }
+
+    // (access token, drive id)
+    pub(super) async fn get_user_access_token(&self, username: &str) -> Result<(String, String)> {
+        let is_current_user = self.session.read().await.username == username;
+
+        if is_current_user {
+            self.refresh_access_token().await?;
+
+            let session = self.session.read().await;
+
+            return Ok((session.access_token.clone(), session.drive_id.clone()));
+        }
+
+        let mut session = self.session.read().await.get_user_session(username).await?;
+
+        if session.is_expired() {
+            let token_response = self
+                .get_token_using_refresh_token(&session.refresh_token)
+                .await
+                .inspect_err(|_| METRICS.add_token_refresh_failure())?;
+
+            session.access_token = token_response.access_token;
+            session.refresh_token = token_response.refresh_token.ok_or_else(|| {
+                anyhow!("failed to receive onedrive refresh token when login with refresh token")
+            })?;
+            session.set_expiration_timestamp(token_response.expires_in_secs);
+
+            session.save().await?;
+        }
+
+        Ok((session.access_token, session.drive_id))
+    }
+}
@@ -15,8 +15,9 @@ use onedrive_api::OneDrive;
 use rand::{distributions::Alphanumeric, thread_rng, Rng};
 use reqwest::header;
 use sea_orm::{
-    sea_query::Expr, ColumnTrait, ConnectionTrait, DatabaseConnection, EntityName, EntityTrait,
-    ModelTrait, QueryFilter, QuerySelect, Schema, Set,
+    sea_query::{ColumnDef, Expr, Query, Table},
+    ColumnTrait, ConnectionTrait, DatabaseConnection, EntityName, EntityTrait, IdenStatic,
+    ModelTrait, QueryFilter, QuerySelect, Schema, Set, Statement,
 };
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
@@ -28,6 +29,8 @@ pub struct OneDriveSession {
     pub access_token: String,
     pub refresh_token: String,
     pub root_path: String,
+    pub drive_id: String,
+    pub drive_name: String,
     #[serde(skip)]
     connection: DatabaseConnection,
 }
@@ -35,13 +38,14 @@ pub struct OneDriveSession {
 impl OneDriveSession {
     pub async fn new(
         client: &OneDrive,
+        graph_url: &str,
         expires_in_secs: u64,
         access_token: &str,
         refresh_token: &str,
         session_path: &str,
         root_path: &str,
     ) -> Result<Self> {
-        let username = Self::get_username(access_token, client).await;
+        let username = Self::get_username(access_token, client, graph_url).await;
         let expiration_timestamp = Self::get_expiration_timestamp(expires_in_secs);
         let connection = Self::connect_db(session_path).await?;
 
@@ -51,6 +55,8 @@ impl OneDriveSession {
             access_token: access_token.to_string(),
             refresh_token: refresh_token.to_string(),
             root_path: root_path.to_string(),
+            drive_id: String::new(),
+            drive_name: String::new(),
             connection,
         })
     }
@@ -75,12 +81,12 @@ impl OneDriveSession {
         );
     }
 
-    async fn get_username(access_token: &str, client: &OneDrive) -> String {
+    async fn get_username(access_token: &str, client: &OneDrive, graph_url: &str) -> String {
         match Self::get_username_jwt(access_token) {
             Ok(username) => username,
             Err(e) => {
                 tracing::warn!("failed to get onedrive username from access token, fallback to use request: {}", e);
-                match Self::get_username_req(client).await {
+                match Self::get_username_req(client, graph_url).await {
                     Ok(username) => username,
                     Err(e) => {
                         tracing::warn!("failed to get onedrive username from request, fallback to default username: {}", e);
@@ -129,10 +135,10 @@ impl OneDriveSession {
     }
 
     // for personal account
-    async fn get_username_req(client: &OneDrive) -> Result<String> {
+    async fn get_username_req(client: &OneDrive, graph_url: &str) -> Result<String> {
         let http_client = client.client();
 
-        let url = "https://graph.microsoft.com/v1.0/me/";
+        let url = format!("{}/v1.0/me/", graph_url);
 
         let response = http_client
             .get(url)
@@ -172,6 +178,10 @@ impl OneDriveSession {
         Self::create_table_if_not_exists(&connection, session::Entity).await?;
         Self::create_table_if_not_exists(&connection, current_user::Entity).await?;
 
+        // added after the table was released
+        Self::add_column_if_not_exists(&connection, session::Column::DriveId).await?;
+        Self::add_column_if_not_exists(&connection, session::Column::DriveName).await?;
+
         Ok(connection)
     }
 
@@ -181,20 +191,30 @@ impl OneDriveSession {
         Ok(self)
     }
 
-    async fn is_table_exists<E>(connection: &DatabaseConnection) -> bool
+    async fn is_table_exists<E>(connection: &DatabaseConnection, entity: E) -> Result<bool>
     where
-        E: EntityTrait,
+        E: EntityTrait + EntityName,
     {
-        let result = E::find().all(connection).await;
+        // columns may be missing in tables created by older versions, so entity can't be queried directly
+        let backend = connection.get_database_backend();
+
+        let result = connection
+            .query_one(Statement::from_sql_and_values(
+                backend,
+                "SELECT name FROM sqlite_master WHERE type = 'table' AND name = ?",
+                [entity.table_name().into()],
+            ))
+            .await
+            .context(format!("failed to query table {}", entity.table_name()))?;
 
-        result.is_ok()
+        Ok(result.is_some())
     }
 
     async fn create_table_if_not_exists<E>(connection: &DatabaseConnection, entity: E) -> Result<()>
     where
         E: EntityTrait + EntityName,
     {
-        if Self::is_table_exists::<E>(connection).await {
+        if Self::is_table_exists(connection, entity).await? {
             tracing::debug!(
                 "onedrive session database table {} already exists",
                 entity.table_name()
@@ -218,6 +238,44 @@ impl OneDriveSession {
         Ok(())
     }
 
+    async fn add_column_if_not_exists(
+        connection: &DatabaseConnection,
+        column: session::Column,
+    ) -> Result<()> {
+        let backend = connection.get_database_backend();
+
+        let select_statement = Query::select()
+            .column(column)
+            .from(session::Entity)
+            .limit(1)
+            .to_owned();
+
+        if connection
+            .query_one(backend.build(&select_statement))
+            .await
+            .is_ok()
+        {
+            return Ok(());
+        }
+
+        tracing::debug!(
+            "onedrive session database column {} not exists, add it",
+            column.as_str()
+        );
+
+        let alter_statement = Table::alter()
+            .table(session::Entity)
+            .add_column(ColumnDef::new(column).string().not_null().default(""))
+            .to_owned();
+
+        connection
+            .execute(backend.build(&alter_statement))
+            .await
+            .context(format!("failed to add column {}", column.as_str()))?;
+
+        Ok(())
+    }
+
     pub async fn load(path: &str) -> Result<Self> {
         tracing::debug!("load onedrive session");
 
@@ -246,6 +304,8 @@ impl OneDriveSession {
                 access_token: Set(self.access_token.to_string()),
                 refresh_token: Set(self.refresh_token.to_string()),
                 root_path: Set(self.root_path.to_string()),
+                drive_id: Set(self.drive_id.to_string()),
+                drive_name: Set(self.drive_name.to_string()),
             };
 
             session::Entity::insert(insert_item)
@@ -265,6 +325,8 @@ impl OneDriveSession {
             access_token,
             refresh_token,
             root_path,
+            drive_id,
+            drive_name,
             ..
         }: Self,
     ) {
@@ -273,6 +335,8 @@ impl OneDriveSession {
         self.access_token = access_token;
         self.refresh_token = refresh_token;
         self.root_path = root_path;
+        self.drive_id = drive_id;
+        self.drive_name = drive_name;
     }
 
     async fn user_exists(&self) -> Result<bool> {
@@ -304,6 +368,8 @@ impl OneDriveSession {
                 Expr::value(&self.refresh_token),
             )
             .col_expr(session::Column::RootPath, Expr::value(&self.root_path))
+            .col_expr(session::Column::DriveId, Expr::value(&self.drive_id))
+            .col_expr(session::Column::DriveName, Expr::value(&self.drive_name))
             .exec(&self.connection)
             .await
             .context("failed to update onedrive session")?;
@@ -492,6 +558,20 @@ impl OneDriveSession {
         Ok(())
     }
 
+    pub async fn get_user_session(&self, username: &str) -> Result<Self> {
+        let model = session::Entity::find()
+            .filter(session::Column::Username.eq(username))
+            .one(&self.connection)
+            .await
+            .context("failed to query onedrive session")?
+            .ok_or_else(|| anyhow!("onedrive session not found"))?;
+
+        let mut session = Self::from(model);
+        session.connection = self.connection.clone();
+
+        Ok(session)
+    }
+
     pub fn is_expired(&self) -> bool {
         let is_expired = self.expiration_timestamp < get_current_timestamp() + 60;
 
@@ -509,6 +589,8 @@ impl From<session::Model> for OneDriveSession {
             access_token: model.access_token,
             refresh_token: model.refresh_token,
             root_path: model.root_path,
+            drive_id: model.drive_id,
+            drive_name: model.drive_name,
             connection: DatabaseConnection::default(),
         }
     }
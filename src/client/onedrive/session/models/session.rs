@@ -19,6 +19,9 @@ pub struct Model {
     pub access_token: String,
     pub refresh_token: String,
     pub root_path: String,
+    // empty for the personal drive of the user
+    pub drive_id: String,
+    pub drive_name: String,
 }
 
 #[derive(Clone, Debug, EnumIter, DeriveRelation)]
@@ -0,0 +1,156 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use super::{
+    graph::{drive_path, item_path, parse_graph_response},
+    OneDriveClient,
+};
+use anyhow::{anyhow, Context, Result};
+use chrono::{TimeDelta, Utc};
+use reqwest::{header, Method};
+use serde_json::{json, Value};
+use std::{fmt::Display, str::FromStr};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ShareLinkType {
+    View,
+    Edit,
+}
+
+impl FromStr for ShareLinkType {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "view" => Ok(Self::View),
+            "edit" => Ok(Self::Edit),
+            _ => Err(anyhow!("share link type should be one of view and edit")),
+        }
+    }
+}
+
+impl Display for ShareLinkType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::View => write!(f, "view"),
+            Self::Edit => write!(f, "edit"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ShareScope {
+    Anonymous,
+    Organization,
+}
+
+impl FromStr for ShareScope {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "anonymous" => Ok(Self::Anonymous),
+            "organization" => Ok(Self::Organization),
+            _ => Err(anyhow!(
+                "share scope should be one of anonymous and organization"
+            )),
+        }
+    }
+}
+
+impl Display for ShareScope {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Anonymous => write!(f, "anonymous"),
+            Self::Organization => write!(f, "organization"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ShareOption {
+    pub link_type: ShareLinkType,
+    pub scope: ShareScope,
+    pub expiration_days: Option<u32>,
+}
+
+impl Default for ShareOption {
+    fn default() -> Self {
+        Self {
+            link_type: ShareLinkType::View,
+            scope: ShareScope::Anonymous,
+            expiration_days: None,
+        }
+    }
+}
+
+impl Display for ShareOption {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} link, {} scope", self.link_type, self.scope)?;
+
+        match self.expiration_days {
+            Some(days) => write!(f, ", expires in {} days", days),
+            None => write!(f, ", never expires"),
+        }
+    }
+}
+
+impl OneDriveClient {
+    // the item is looked up in the drive of the account if set, otherwise in the current one
+    pub async fn create_share_link(
+        &self,
+        username: Option<&str>,
+        path: &str,
+        option: &ShareOption,
+    ) -> Result<String> {
+        let mut body = json!({
+            "type": option.link_type.to_string(),
+            "scope": option.scope.to_string(),
+        });
+
+        if let Some(days) = option.expiration_days {
+            let expiration = Utc::now()
+                + TimeDelta::try_days(i64::from(days))
+                    .ok_or_else(|| anyhow!("share link expiration days out of range"))?;
+
+            body["expirationDateTime"] = Value::String(expiration.to_rfc3339());
+        }
+
+        let request = if let Some(username) = username {
+            let (access_token, drive_id) = self.get_user_access_token(username).await?;
+
+            let url = format!("{}/createLink", item_path(&drive_path(&drive_id), path));
+
+            self.graph_request_with_token(Method::POST, &url, &access_token)
+                .await
+        } else {
+            let url = format!("{}/createLink", self.item_path_url(path).await);
+
+            self.graph_request(Method::POST, &url).await?
+        };
+
+        let response = request
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(body.to_string())
+            .send()
+            .await
+            .context("failed to send request for share link")?;
+
+        let link = parse_graph_response(response)
+            .await
+            .context("failed to create share link")?
+            .get("link")
+            .and_then(|link| link.get("webUrl"))
+            .and_then(Value::as_str)
+            .ok_or_else(|| anyhow!("field link.webUrl not found in share link response"))?
+            .to_string();
+
+        tracing::info!("created share link for {}: {}", path, link);
+
+        Ok(link)
+    }
+}
@@ -5,41 +5,173 @@
 :license: MIT, see LICENSE for more details.
 */
 
-use super::OneDriveClient;
-use anyhow::{anyhow, Context, Result};
-use onedrive_api::{
-    option::DriveItemPutOption, ConflictBehavior, ItemLocation, UploadSession, UploadSessionMeta,
-};
+use super::{graph::parse_graph_response, OneDriveClient};
+use crate::storage::{ConflictBehavior, StorageBackend, UploadTarget, UploadedItem};
+use anyhow::{anyhow, Context, Error, Result};
+use bytes::Bytes;
+use onedrive_api::{resource::DriveItem, UploadSession};
 use path_slash::PathBufExt;
-use std::path::Path;
+use reqwest::{header, Method};
+use serde_json::{json, Value};
+use std::{ops::Range, path::Path};
 
 impl OneDriveClient {
+    // upload session is created through graph url of the configured cloud
     pub async fn multipart_upload_session_builder(
         &self,
         root_path: &str,
         filename: &str,
-    ) -> Result<(UploadSession, UploadSessionMeta)> {
+        conflict: ConflictBehavior,
+    ) -> Result<UploadTarget> {
         let file_path_obj = Path::new(root_path).join(filename);
         let file_path = file_path_obj.to_slash_lossy();
 
-        let item_location = ItemLocation::from_path(&file_path)
-            .ok_or_else(|| anyhow!("file path does not start with /"))?;
+        if !file_path.starts_with('/') {
+            return Err(anyhow!("file path does not start with /"));
+        }
 
-        self.refresh_access_token().await?;
+        let body = json!({
+            "item": {
+                "@microsoft.graph.conflictBehavior": conflict.to_string(),
+            },
+        });
 
-        let session = self
-            .client
-            .read()
+        let url = format!(
+            "{}/createUploadSession",
+            self.item_path_url(&file_path).await
+        );
+
+        let response = self
+            .graph_request(Method::POST, &url)
+            .await?
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(body.to_string())
+            .send()
             .await
-            .new_upload_session_with_option(
-                item_location,
-                DriveItemPutOption::new().conflict_behavior(ConflictBehavior::Rename),
-            )
+            .context("failed to send request for creating upload session")?;
+
+        let upload_session = parse_graph_response(response)
             .await
             .context("failed to create upload session")?;
 
+        let upload_url = upload_session
+            .get("uploadUrl")
+            .and_then(Value::as_str)
+            .ok_or_else(|| anyhow!("field uploadUrl not found in upload session"))?
+            .to_string();
+
+        // all task should be new, so this should always be 0
+        let current_length = upload_session
+            .get("nextExpectedRanges")
+            .and_then(Value::as_array)
+            .and_then(|ranges| ranges.first())
+            .and_then(Value::as_str)
+            .and_then(|range| range.split('-').next())
+            .and_then(|start| start.parse::<u64>().ok())
+            .unwrap_or_default();
+
         tracing::debug!("built upload session for {}", filename);
 
-        Ok(session)
+        Ok(UploadTarget {
+            upload_url,
+            current_length,
+        })
+    }
+}
+
+impl StorageBackend for OneDriveClient {
+    async fn create_upload_session(
+        &self,
+        root_path: &str,
+        filename: &str,
+        _total_length: u64,
+        conflict: ConflictBehavior,
+    ) -> Result<UploadTarget> {
+        self.multipart_upload_session_builder(root_path, filename, conflict)
+            .await
+    }
+
+    async fn upload_part(
+        &self,
+        upload_url: &str,
+        buffer: &Bytes,
+        current_length: u64,
+        total_length: u64,
+    ) -> Result<Option<UploadedItem>> {
+        // upload url doesn't need authorization
+        let http_client = self.client.read().await.client().clone();
+
+        let result = UploadSession::from_upload_url(upload_url)
+            .upload_part(
+                buffer.clone(),
+                Range {
+                    start: current_length,
+                    end: current_length + buffer.len() as u64,
+                },
+                total_length,
+                &http_client,
+            )
+            .await;
+
+        match result {
+            Ok(drive_item) => drive_item.map(uploaded_item_from_drive_item).transpose(),
+            Err(e) => {
+                // 416: Requested Range Not Satisfiable, probably because the fragment has already been received
+                if e.status_code().map(|status_code| status_code.as_u16()) == Some(416) {
+                    return Ok(None);
+                }
+
+                Err(Error::from(e)).context("failed to upload part")
+            }
+        }
+    }
+
+    async fn get_resume_offset(&self, upload_url: &str) -> Result<u64> {
+        let http_client = self.client.read().await.client().clone();
+
+        let upload_session_meta = UploadSession::from_upload_url(upload_url)
+            .get_meta(&http_client)
+            .await
+            .context("failed to get upload session meta")?;
+
+        let current_length = upload_session_meta
+            .next_expected_ranges
+            .first()
+            .map_or(0, |range| range.start);
+
+        Ok(current_length)
+    }
+
+    async fn get_free_space(&self) -> Result<Option<u64>> {
+        let quota = self.get_quota().await?;
+
+        Ok(Some(quota.remaining))
+    }
+
+    async fn cancel_upload(&self, upload_url: &str) -> Result<()> {
+        let http_client = self.client.read().await.client().clone();
+
+        UploadSession::from_upload_url(upload_url)
+            .delete(&http_client)
+            .await
+            .context("failed to delete upload session")?;
+
+        Ok(())
+    }
+
+    async fn validate_dir(&self, root_path: &str) -> Result<()> {
+        Self::validate_dir(self, root_path).await
     }
 }
+
+fn uploaded_item_from_drive_item(drive_item: DriveItem) -> Result<UploadedItem> {
+    let name = drive_item
+        .name
+        .ok_or_else(|| anyhow!("drive item name not found"))?;
+
+    let id = drive_item.id.map(|id| id.as_str().to_string());
+
+    let size = drive_item.size.unwrap_or_default().max(0) as u64;
+
+    Ok(UploadedItem { name, id, size })
+}
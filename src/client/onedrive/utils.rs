@@ -5,8 +5,20 @@
 :license: MIT, see LICENSE for more details.
 */
 
-use super::invalid_name::INVALID_FOLDER_DIR;
+use super::invalid_name::{
+    INVALID_COMPONENT, INVALID_FOLDER_DIR, INVALID_NAME, INVALID_NAME_PREFIX,
+};
 use anyhow::{anyhow, Result};
+use onedrive_api::{resource::DriveId, DriveLocation};
+
+// personal drive of the user if drive id is empty
+pub fn get_drive_location(drive_id: &str) -> DriveLocation {
+    if drive_id.is_empty() {
+        DriveLocation::me()
+    } else {
+        DriveLocation::from_id(DriveId(drive_id.to_string()))
+    }
+}
 
 pub fn validate_root_path(path: &str) -> Result<()> {
     if path == INVALID_FOLDER_DIR || path.starts_with(&format!("{}/", INVALID_FOLDER_DIR)) {
@@ -18,3 +30,51 @@ pub fn validate_root_path(path: &str) -> Result<()> {
 
     Ok(())
 }
+
+pub fn validate_item_name(name: &str) -> Result<()> {
+    if name.is_empty() {
+        return Err(anyhow!("item name should not be empty"));
+    }
+
+    if INVALID_NAME.contains(&name) {
+        return Err(anyhow!(format!(
+            "item name should not be {} according restrictions",
+            name
+        )));
+    }
+
+    if name.starts_with(INVALID_NAME_PREFIX) {
+        return Err(anyhow!(format!(
+            "item name should not start with {} according restrictions",
+            INVALID_NAME_PREFIX
+        )));
+    }
+
+    for component in INVALID_COMPONENT {
+        if name.contains(component) {
+            return Err(anyhow!(format!(
+                "item name should not contain {} according restrictions",
+                component
+            )));
+        }
+    }
+
+    Ok(())
+}
+
+// split an absolute item path into its parent path and name
+pub fn split_item_path(path: &str) -> Result<(String, String)> {
+    let path = path.trim_end_matches('/');
+
+    let (parent, name) = path
+        .rsplit_once('/')
+        .ok_or_else(|| anyhow!("item path should start with /"))?;
+
+    if name.is_empty() {
+        return Err(anyhow!("root directory is not allowed"));
+    }
+
+    let parent = if parent.is_empty() { "/" } else { parent };
+
+    Ok((parent.to_string(), name.to_string()))
+}
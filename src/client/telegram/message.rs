@@ -5,24 +5,30 @@
 :license: MIT, see LICENSE for more details.
 */
 
-use super::TelegramClient;
+use super::{ChatMessageQueue, TelegramClient};
 use crate::{
     error::ResultExt,
     message::{ChatEntity, QueuedMessage, QueuedMessageType, TelegramMessage},
+    metrics::METRICS,
 };
 use anyhow::{anyhow, Context, Result};
 use grammers_client::{
     client::messages::MessageIter,
+    grammers_tl_types as tl,
     types::{Chat, InputMessage, PackedChat},
-    Update,
+    InvocationError, Update,
 };
-use rand::{rngs::StdRng, Rng, SeedableRng};
 use std::{
     collections::{HashMap, VecDeque},
-    time::Duration,
+    time::{Duration, Instant},
 };
 use tokio::sync::mpsc;
 
+// messages are sent as soon as their chats are ready, checked this often
+const QUEUE_SWEEP_INTERVAL: Duration = Duration::from_millis(200);
+const GROUP_MESSAGE_INTERVAL: Duration = Duration::from_secs(3);
+const PRIVATE_CHAT_MESSAGE_INTERVAL: Duration = Duration::from_secs(1);
+
 impl TelegramClient {
     pub async fn get_message<C>(&self, chat: C, message_id: i32) -> Result<TelegramMessage>
     where
@@ -72,6 +78,45 @@ impl TelegramClient {
         self.raw().iter_messages(chat)
     }
 
+    // messages of a forum topic are replies to its first message
+    pub async fn get_latest_topic_message_id<C: Into<PackedChat>>(
+        &self,
+        chat: C,
+        topic_id: i32,
+    ) -> Result<Option<i32>> {
+        let request = tl::functions::messages::GetReplies {
+            peer: chat.into().to_input_peer(),
+            msg_id: topic_id,
+            offset_id: 0,
+            offset_date: 0,
+            add_offset: 0,
+            limit: 1,
+            max_id: 0,
+            min_id: 0,
+            hash: 0,
+        };
+
+        let messages = match self
+            .raw()
+            .invoke(&request)
+            .await
+            .context("failed to get latest topic message")?
+        {
+            tl::enums::messages::Messages::Messages(messages) => messages.messages,
+            tl::enums::messages::Messages::Slice(messages) => messages.messages,
+            tl::enums::messages::Messages::ChannelMessages(messages) => messages.messages,
+            tl::enums::messages::Messages::NotModified(_) => Vec::new(),
+        };
+
+        let latest_message_id = messages.first().map(|message| match message {
+            tl::enums::Message::Empty(message) => message.id,
+            tl::enums::Message::Message(message) => message.id,
+            tl::enums::Message::Service(message) => message.id,
+        });
+
+        Ok(latest_message_id)
+    }
+
     pub async fn delete_messages<C: Into<PackedChat>>(
         &self,
         chat: C,
@@ -157,139 +202,202 @@ impl TelegramClient {
         let chat_message_queue = self.chat_message_queue();
         let telegram_client = self.clone();
 
-        let mut rng = {
-            let rng = rand::thread_rng();
-            StdRng::from_rng(rng)
-                .context("failed to create rng")
-                .unwrap_or_trace()
-        };
-
         tokio::spawn(async move {
             loop {
-                let mut chat_message_queue = chat_message_queue.lock().await;
-
-                let chat_ids = chat_message_queue.keys().copied().collect::<Vec<i64>>();
-                for chat_id in chat_ids {
-                    if let Some(QueuedMessage {
-                        message_type,
-                        input_message,
-                        chat,
-                        tx,
-                    }) = chat_message_queue.pop_front(chat_id)
-                    {
-                        let message_result = match message_type {
-                            QueuedMessageType::Respond => {
-                                let result = telegram_client
-                                    .raw()
-                                    .send_message(chat, input_message)
-                                    .await
-                                    .context("failed to respond message");
-
-                                match result {
-                                    Ok(message_raw) => Ok(Some(TelegramMessage::new(
-                                        telegram_client.clone(),
-                                        message_raw,
-                                    ))),
-                                    Err(e) => Err(e),
-                                }
-                            }
-                            QueuedMessageType::Reply(message_id) => {
-                                let result = telegram_client
-                                    .raw()
-                                    .send_message(chat, input_message.reply_to(Some(message_id)))
-                                    .await
-                                    .context("failed to respond message");
-
-                                match result {
-                                    Ok(message_raw) => Ok(Some(TelegramMessage::new(
-                                        telegram_client.clone(),
-                                        message_raw,
-                                    ))),
-                                    Err(e) => Err(e),
-                                }
-                            }
-                            QueuedMessageType::Edit(message_id) => {
-                                let result = telegram_client
-                                    .raw()
-                                    .edit_message(chat, message_id, input_message)
-                                    .await
-                                    .context("failed to respond message");
-
-                                match result {
-                                    Ok(()) => Ok(None),
-                                    Err(e) => Err(e),
-                                }
-                            }
-                        };
-
-                        tx.send(message_result)
-                            .await
-                            .context("failed to send message result to rx")
-                            .trace();
+                // at most one message of each chat in a sweep
+                let queued_messages = {
+                    let mut chat_message_queue = chat_message_queue.lock().await;
+
+                    let now = Instant::now();
+
+                    let chat_ids = chat_message_queue.keys().copied().collect::<Vec<i64>>();
+                    let mut queued_messages = Vec::new();
+                    for chat_id in chat_ids {
+                        if let Some(queued_message) = chat_message_queue.pop_front(chat_id, now) {
+                            queued_messages.push(queued_message);
+                        }
+
+                        // kept until the chat is ready, or the wait would be forgotten
+                        if chat_message_queue
+                            .get(&chat_id)
+                            .is_some_and(|messages| messages.is_idle(now))
+                        {
+                            chat_message_queue.remove(&chat_id);
+                        }
                     }
 
-                    if chat_message_queue.get(&chat_id).unwrap().is_empty() {
-                        chat_message_queue.remove(&chat_id);
-                    }
-                }
+                    queued_messages
+                };
 
-                drop(chat_message_queue);
+                // the queue is not locked while sending, so new messages are not blocked
+                for queued_message in queued_messages {
+                    telegram_client
+                        .send_queued_message(queued_message, &chat_message_queue)
+                        .await;
+                }
 
-                let millis = rng.gen_range(2700..3500);
-                tokio::time::sleep(Duration::from_millis(millis)).await;
+                tokio::time::sleep(QUEUE_SWEEP_INTERVAL).await;
             }
         });
     }
+
+    async fn send_queued_message(
+        &self,
+        queued_message: QueuedMessage,
+        chat_message_queue: &ChatMessageQueue,
+    ) {
+        let QueuedMessage {
+            message_type,
+            input_message,
+            chat,
+            tx,
+        } = &queued_message;
+
+        let message_result = match message_type {
+            QueuedMessageType::Respond => self
+                .raw()
+                .send_message(*chat, input_message.clone())
+                .await
+                .context("failed to respond message")
+                .map(|message_raw| Some(TelegramMessage::new(self.clone(), message_raw))),
+            QueuedMessageType::Reply(message_id) => self
+                .raw()
+                .send_message(*chat, input_message.clone().reply_to(Some(*message_id)))
+                .await
+                .context("failed to respond message")
+                .map(|message_raw| Some(TelegramMessage::new(self.clone(), message_raw))),
+            QueuedMessageType::Edit(message_id) => self
+                .raw()
+                .edit_message(*chat, *message_id, input_message.clone())
+                .await
+                .context("failed to respond message")
+                .map(|()| None),
+        };
+
+        let wait = message_result.as_ref().err().and_then(get_flood_wait);
+
+        let mut chat_message_queue = chat_message_queue.lock().await;
+        let messages = chat_message_queue
+            .entry(chat.id)
+            .or_insert_with(MessageVecDeque::new);
+
+        if let Some(wait) = wait {
+            METRICS.add_flood_wait();
+
+            tracing::warn!(
+                "flood wait in chat {} for {}s, message rescheduled",
+                chat.id,
+                wait.as_secs()
+            );
+
+            // tried again once the chat is ready
+            messages.delay(wait);
+            messages.push_front(queued_message);
+
+            return;
+        }
+
+        // telegram allows about 20 messages per minute in a group and 1 per second in a private chat
+        messages.delay(if chat.is_user() {
+            PRIVATE_CHAT_MESSAGE_INTERVAL
+        } else {
+            GROUP_MESSAGE_INTERVAL
+        });
+        drop(chat_message_queue);
+
+        tx.send(message_result)
+            .await
+            .context("failed to send message result to rx")
+            .trace();
+    }
+}
+
+// time that telegram asks to wait before sending in the chat again
+fn get_flood_wait(e: &anyhow::Error) -> Option<Duration> {
+    match e.downcast_ref::<InvocationError>() {
+        Some(InvocationError::Rpc(rpc_error))
+            if matches!(
+                rpc_error.name.as_str(),
+                "FLOOD_WAIT" | "FLOOD_PREMIUM_WAIT" | "SLOWMODE_WAIT"
+            ) =>
+        {
+            Some(Duration::from_secs(u64::from(
+                rpc_error.value.unwrap_or(1).max(1),
+            )))
+        }
+        _ => None,
+    }
 }
 
 pub struct MessageVecDeque {
-    deque: VecDeque<QueuedMessage>,
-    // message id -> index in deque, only for edit messages
-    key_map: HashMap<i32, usize>,
+    // responses and replies, they are sent before edits
+    replies: VecDeque<QueuedMessage>,
+    // mostly progress, only the latest edit of a message is kept
+    edits: VecDeque<QueuedMessage>,
+    // no message is sent in the chat before it
+    ready_at: Instant,
 }
 
 impl MessageVecDeque {
     pub fn new() -> Self {
         Self {
-            deque: VecDeque::new(),
-            key_map: HashMap::new(),
+            replies: VecDeque::new(),
+            edits: VecDeque::new(),
+            ready_at: Instant::now(),
         }
     }
 
     fn push_back(&mut self, queued_message: QueuedMessage) {
         match queued_message.message_type {
             QueuedMessageType::Respond | QueuedMessageType::Reply(_) => {
-                self.deque.push_back(queued_message);
+                self.replies.push_back(queued_message);
             }
             QueuedMessageType::Edit(message_id) => {
-                if let Some(index) = self.key_map.get(&message_id) {
+                if let Some(index) = self.find_edit(message_id) {
                     // override the outdated edit message
-                    self.deque[*index] = queued_message;
+                    self.edits[index] = queued_message;
                 } else {
-                    self.key_map.insert(message_id, self.deque.len());
-                    self.deque.push_back(queued_message);
+                    self.edits.push_back(queued_message);
                 }
             }
         }
     }
 
-    fn pop_front(&mut self) -> Option<QueuedMessage> {
-        self.deque.pop_front().map(|queued_message| {
-            if let QueuedMessageType::Edit(message_id) = queued_message.message_type {
-                self.key_map.remove(&message_id);
+    // for rescheduled messages
+    fn push_front(&mut self, queued_message: QueuedMessage) {
+        match queued_message.message_type {
+            QueuedMessageType::Respond | QueuedMessageType::Reply(_) => {
+                self.replies.push_front(queued_message);
             }
-
-            // decrease the index of all edit messages
-            for index in self.key_map.values_mut() {
-                *index -= 1;
+            QueuedMessageType::Edit(message_id) => {
+                // a newer edit of the message is queued while waiting
+                if self.find_edit(message_id).is_none() {
+                    self.edits.push_front(queued_message);
+                }
             }
+        }
+    }
 
-            queued_message
+    fn pop_front(&mut self, now: Instant) -> Option<QueuedMessage> {
+        if now < self.ready_at {
+            return None;
+        }
+
+        self.replies.pop_front().or_else(|| self.edits.pop_front())
+    }
+
+    fn find_edit(&self, message_id: i32) -> Option<usize> {
+        self.edits.iter().position(|queued_message| {
+            matches!(queued_message.message_type, QueuedMessageType::Edit(id) if id == message_id)
         })
     }
 
-    fn is_empty(&self) -> bool {
-        self.deque.is_empty()
+    fn delay(&mut self, duration: Duration) {
+        self.ready_at = self.ready_at.max(Instant::now() + duration);
+    }
+
+    fn is_idle(&self, now: Instant) -> bool {
+        self.replies.is_empty() && self.edits.is_empty() && now >= self.ready_at
     }
 }
 
@@ -298,7 +406,7 @@ pub type ChatMessageVecDeque = HashMap<i64, MessageVecDeque>;
 trait ChatMessageHashMapExt {
     fn push_back(&mut self, queued_message: QueuedMessage);
 
-    fn pop_front(&mut self, chat_id: i64) -> Option<QueuedMessage>;
+    fn pop_front(&mut self, chat_id: i64, now: Instant) -> Option<QueuedMessage>;
 }
 
 impl ChatMessageHashMapExt for ChatMessageVecDeque {
@@ -308,7 +416,7 @@ impl ChatMessageHashMapExt for ChatMessageVecDeque {
             .push_back(queued_message);
     }
 
-    fn pop_front(&mut self, chat_id: i64) -> Option<QueuedMessage> {
-        self.get_mut(&chat_id)?.pop_front()
+    fn pop_front(&mut self, chat_id: i64, now: Instant) -> Option<QueuedMessage> {
+        self.get_mut(&chat_id)?.pop_front(now)
     }
 }
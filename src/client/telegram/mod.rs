@@ -7,6 +7,7 @@
 
 mod file;
 mod message;
+mod qr_login;
 
 use crate::{
     env::{Env, TelegramBotEnv, TelegramUserEnv, ENV},
@@ -156,6 +157,10 @@ impl TelegramClient {
                 ..
             } = ENV.get().unwrap();
 
+            let phone_number = phone_number
+                .as_ref()
+                .ok_or_else(|| anyhow!("tg_user_phone is required to login with code"))?;
+
             let client = self.raw();
 
             let response = "Sending telegram login code...\nThis may take a while.";
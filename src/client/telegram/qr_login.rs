@@ -0,0 +1,144 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+// login by scanning a qr code with a logged-in telegram app, https://core.telegram.org/api/qr-login
+
+use super::TelegramClient;
+use crate::{
+    env::{Env, TelegramUserEnv, ENV},
+    message::TelegramMessage,
+    utils::get_current_timestamp,
+};
+use anyhow::{anyhow, Context, Result};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD as base64, Engine};
+use grammers_client::{
+    grammers_tl_types as tl, types::PasswordToken, InputMessage, InvocationError,
+};
+use image::{DynamicImage, ImageFormat, Luma};
+use qrcode::QrCode;
+use std::{io::Cursor, time::Duration};
+
+// the token is accepted from the phone app, so it can only be polled
+const POLL_INTERVAL_SECS: u64 = 3;
+// qr codes are resent until then
+const QR_LOGIN_TIMEOUT_SECS: i64 = 300;
+
+impl TelegramClient {
+    pub async fn login_with_qr(&self, message: TelegramMessage, telegram_bot: &Self) -> Result<()> {
+        let Env {
+            telegram_user:
+                TelegramUserEnv {
+                    api_id,
+                    api_hash,
+                    password,
+                    session_path,
+                    ..
+                },
+            ..
+        } = ENV.get().unwrap();
+
+        let client = self.raw();
+
+        let request = tl::functions::auth::ExportLoginToken {
+            api_id: *api_id,
+            api_hash: api_hash.clone(),
+            except_ids: Vec::new(),
+        };
+
+        let deadline = get_current_timestamp() + QR_LOGIN_TIMEOUT_SECS;
+        // expiration timestamp of the qr code sent
+        let mut expires = 0;
+
+        loop {
+            if get_current_timestamp() > deadline {
+                return Err(anyhow!("telegram qr code login timed out"));
+            }
+
+            match client.invoke(&request).await {
+                Ok(tl::enums::auth::LoginToken::Token(token)) => {
+                    // a new qr code is sent only after the previous one expires
+                    if get_current_timestamp() >= expires {
+                        send_qr_code(&message, telegram_bot, &token.token).await?;
+
+                        expires = i64::from(token.expires);
+                    }
+                }
+                Ok(tl::enums::auth::LoginToken::Success(_)) => break,
+                Ok(tl::enums::auth::LoginToken::MigrateTo(_)) => {
+                    return Err(anyhow!(
+                        "telegram account is in another data center, please login with phone number"
+                    ));
+                }
+                Err(InvocationError::Rpc(rpc_error))
+                    if rpc_error.name == "SESSION_PASSWORD_NEEDED" =>
+                {
+                    let password = password
+                        .as_ref()
+                        .ok_or_else(|| anyhow!("password for telegram user 2FA required"))?;
+
+                    let tl::enums::account::Password::Password(password_info) = client
+                        .invoke(&tl::functions::account::GetPassword {})
+                        .await
+                        .context("failed to get telegram user 2FA info")?;
+
+                    client
+                        .check_password(PasswordToken::new(password_info), password)
+                        .await
+                        .context("failed to pass telegram user 2FA")?;
+
+                    break;
+                }
+                Err(e) => return Err(e).context("failed to export telegram login token"),
+            }
+
+            tokio::time::sleep(Duration::from_secs(POLL_INTERVAL_SECS)).await;
+        }
+
+        client
+            .session()
+            .save_to_file(session_path)
+            .context("failed to save session for telegram user client")?;
+
+        Ok(())
+    }
+}
+
+async fn send_qr_code(
+    message: &TelegramMessage,
+    telegram_bot: &TelegramClient,
+    token: &[u8],
+) -> Result<()> {
+    let url = format!("tg://login?token={}", base64.encode(token));
+
+    let image = QrCode::new(url.as_bytes())
+        .context("failed to encode telegram login token into qr code")?
+        .render::<Luma<u8>>()
+        .min_dimensions(400, 400)
+        .build();
+
+    let mut buffer = Vec::new();
+    DynamicImage::ImageLuma8(image)
+        .write_to(&mut Cursor::new(&mut buffer), ImageFormat::Png)
+        .context("failed to write qr code into png")?;
+
+    let size = buffer.len();
+    let uploaded = telegram_bot
+        .upload_stream(&mut Cursor::new(buffer), size, "login.png".to_string())
+        .await
+        .context("qr code")?;
+
+    let response =
+        "Scan the QR code in Telegram app to login:\nSettings > Devices > Link Desktop Device";
+    message
+        .respond(InputMessage::text(response).photo(uploaded))
+        .await
+        .context(response)?;
+
+    tracing::info!("telegram login qr code sent");
+
+    Ok(())
+}
@@ -0,0 +1,60 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use super::utils::parse_value;
+use crate::{client::onedrive::share::ShareOption, tasker::ProgressMode};
+use anyhow::{anyhow, Result};
+use std::collections::HashMap;
+
+// settings of a chat from [chats.$id] in the config file
+pub struct ChatEnv {
+    // share link option for completed uploads
+    pub share: Option<ShareOption>,
+    pub s3_bucket: Option<String>,
+    pub s3_prefix: Option<String>,
+    pub progress: Option<ProgressMode>,
+}
+
+impl ChatEnv {
+    pub fn new(chat_id: i64, values: &HashMap<String, String>, path: &str) -> Result<Self> {
+        let mut share = None;
+        let mut s3_bucket = None;
+        let mut s3_prefix = None;
+        let mut progress = None;
+
+        for (key, value) in values {
+            let name = format!("chats.{}.{}", chat_id, key);
+
+            match key.as_str() {
+                "share_type" => {
+                    share.get_or_insert_with(ShareOption::default).link_type =
+                        parse_value(&name, value, path)?;
+                }
+                "share_scope" => {
+                    share.get_or_insert_with(ShareOption::default).scope =
+                        parse_value(&name, value, path)?;
+                }
+                "share_expiration_days" => {
+                    share
+                        .get_or_insert_with(ShareOption::default)
+                        .expiration_days = Some(parse_value(&name, value, path)?);
+                }
+                "s3_bucket" => s3_bucket = Some(value.clone()),
+                "s3_prefix" => s3_prefix = Some(value.clone()),
+                "progress" => progress = Some(parse_value(&name, value, path)?),
+                _ => return Err(anyhow!("unknown key {} in {}", name, path)),
+            }
+        }
+
+        Ok(Self {
+            share,
+            s3_bucket,
+            s3_prefix,
+            progress,
+        })
+    }
+}
@@ -0,0 +1,192 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use super::var::CONFIG_PATH;
+use anyhow::{anyhow, Context, Result};
+use std::{collections::HashMap, env, fs, path::Path, sync::OnceLock};
+use toml::{Table, Value};
+
+pub static CONFIG: OnceLock<Config> = OnceLock::new();
+
+// env names that can be set in the config file, besides [chats]
+const KEYS: &[&str] = &[
+    "tg_bot_token",
+    "tg_api_id",
+    "tg_api_hash",
+    "tg_user_phone",
+    "tg_user_password",
+    "tg_user_name",
+    "od_client_id",
+    "od_client_secret",
+    "od_cloud",
+    "od_tenant",
+    "od_authority_url",
+    "od_graph_url",
+    "od_device_code",
+    "od_root_path",
+    "remote_root_path",
+    "storage",
+    "local_root",
+    "webdav_url",
+    "webdav_username",
+    "webdav_password",
+    "s3_endpoint",
+    "s3_region",
+    "s3_bucket",
+    "s3_prefix",
+    "s3_access_key",
+    "s3_secret_key",
+    "webhook_urls",
+    "webhook_secret",
+    "trace_level",
+    "port",
+    "server_uri",
+    "reverse_proxy",
+    "dashboard_token",
+    "api_token",
+    "metrics_token",
+    "auto_delete",
+    "delete_flag",
+    "worker_num",
+    "memory_limit",
+];
+
+// keys are the same as env names, values are overridden by env
+#[derive(Default)]
+pub struct Config {
+    pub path: String,
+    values: HashMap<String, String>,
+    // chat id -> values of [chats.$id]
+    pub chats: HashMap<i64, HashMap<String, String>>,
+}
+
+impl Config {
+    pub fn load() -> Result<Self> {
+        // the default config file is optional
+        let path = match env::var("config_path") {
+            Ok(path) => path,
+            Err(_) if Path::new(CONFIG_PATH).exists() => CONFIG_PATH.to_string(),
+            Err(_) => return Ok(Self::default()),
+        };
+
+        let content = fs::read_to_string(&path)
+            .context("failed to read config file")
+            .context(path.clone())?;
+
+        Self::parse(path, &content)
+    }
+
+    pub(super) fn parse(path: String, content: &str) -> Result<Self> {
+        let table = content
+            .parse::<Table>()
+            .context("failed to parse config file")
+            .context(path.clone())?;
+
+        let mut values = HashMap::new();
+        let mut chats = HashMap::new();
+
+        for (key, value) in table {
+            if key == "chats" {
+                let Value::Table(chat_tables) = value else {
+                    return Err(anyhow!("chats in {} should be a table", path));
+                };
+
+                for (chat_id, chat_table) in chat_tables {
+                    let name = format!("chats.{}", chat_id);
+
+                    let id = chat_id
+                        .parse::<i64>()
+                        .map_err(|_| anyhow!("{} in {} should be a chat id", name, path))?;
+
+                    let Value::Table(chat_table) = chat_table else {
+                        return Err(anyhow!("{} in {} should be a table", name, path));
+                    };
+
+                    let chat_values = chat_table
+                        .into_iter()
+                        .map(|(key, value)| {
+                            let value = to_env_value(&format!("{}.{}", name, key), value, &path)?;
+
+                            Ok((key, value))
+                        })
+                        .collect::<Result<_>>()?;
+
+                    chats.insert(id, chat_values);
+                }
+
+                continue;
+            }
+
+            // typos would silently fall back to the default
+            if !KEYS.contains(&key.as_str()) {
+                return Err(anyhow!("unknown key {} in {}", key, path));
+            }
+
+            let value = to_env_value(&key, value, &path)?;
+            values.insert(key, value);
+        }
+
+        Ok(Self {
+            path,
+            values,
+            chats,
+        })
+    }
+
+    pub fn get(&self, name: &str) -> Option<&String> {
+        self.values.get(name)
+    }
+}
+
+// values are parsed the same as env
+fn to_env_value(name: &str, value: Value, path: &str) -> Result<String> {
+    match value {
+        Value::String(s) => Ok(s),
+        Value::Integer(_) | Value::Float(_) | Value::Boolean(_) => Ok(value.to_string()),
+        // like tg_user_name = ["alice", "bob"]
+        Value::Array(array) => array
+            .into_iter()
+            .map(|value| match value {
+                Value::String(s) => Ok(s),
+                Value::Integer(_) => Ok(value.to_string()),
+                _ => Err(anyhow!(
+                    "items of {} in {} should be strings or integers",
+                    name,
+                    path
+                )),
+            })
+            .collect::<Result<Vec<_>>>()
+            .map(|items| items.join(",")),
+        _ => Err(anyhow!(
+            "{} in {} should be a string, number, boolean or array",
+            name,
+            path
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unknown_keys_are_rejected() {
+        let config = Config::parse(
+            "config.toml".to_string(),
+            "worker_num = 3\n[chats.-100]\nprogress = \"off\"\n",
+        )
+        .unwrap();
+        assert_eq!(config.get("worker_num"), Some(&"3".to_string()));
+        assert!(config.chats.contains_key(&-100));
+
+        let result = Config::parse("config.toml".to_string(), "worker_nums = 3\n");
+        assert_eq!(
+            result.err().map(|e| e.to_string()),
+            Some("unknown key worker_nums in config.toml".to_string())
+        );
+    }
+}
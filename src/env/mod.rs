@@ -5,20 +5,28 @@
 :license: MIT, see LICENSE for more details.
 */
 
+mod chat;
+mod config;
 mod onedrive;
+mod storage;
 mod telegram_bot;
 mod telegram_user;
 mod utils;
 mod var;
+mod webhook;
 
 use anyhow::Context;
+pub use chat::ChatEnv;
+use config::{Config, CONFIG};
 pub use onedrive::OneDriveEnv;
-use std::{fs, sync::OnceLock};
+use std::{collections::HashMap, fs, sync::OnceLock};
+pub use storage::{S3Env, StorageEnv, WebDavEnv};
 pub use telegram_bot::TelegramBotEnv;
 pub use telegram_user::TelegramUserEnv;
-use utils::{get_env_value, get_env_value_option, get_env_value_option_legacy};
+use utils::{get_env_value, get_env_value_option, get_env_value_option_legacy, unwrap_or_exit};
 pub use var::LOGS_PATH;
 use var::SESSION_DIR;
+pub use webhook::WebhookEnv;
 
 use crate::error::ResultExt;
 
@@ -28,13 +36,28 @@ pub struct Env {
     pub telegram_bot: TelegramBotEnv,
     pub telegram_user: TelegramUserEnv,
     pub onedrive: OneDriveEnv,
+    pub storage: StorageEnv,
+    pub webhook: WebhookEnv,
+    // chat id -> settings from the config file
+    pub chats: HashMap<i64, ChatEnv>,
     pub trace_level: String,
     pub port: u16,
     pub server_uri: String,
     pub use_reverse_proxy: bool,
+    // web dashboard is served with the auth server if set
+    pub dashboard_token: Option<String>,
+    // http api is served with the auth server if set
+    pub api_token: Option<String>,
+    // prometheus metrics are served with the auth server if set
+    pub metrics_token: Option<String>,
     pub should_auto_delete: bool,
     pub tasker_session_path: String,
+    pub settings_session_path: String,
+    pub acl_session_path: String,
+    pub usage_session_path: String,
     pub task_handler_num: u8,
+    // MiB of buffers shared by transfers of all tasks
+    pub memory_limit: u64,
 }
 
 impl Env {
@@ -44,26 +67,46 @@ impl Env {
         let telegram_bot = TelegramBotEnv::new();
         let telegram_user = TelegramUserEnv::new();
         let onedrive = OneDriveEnv::new();
+        let storage = StorageEnv::new();
+        let webhook = WebhookEnv::new();
+        let chats = Self::parse_chats();
         let trace_level = get_env_value_option("trace_level", "info".to_string());
         let port = get_env_value_option("port", 8080);
         let server_uri = get_env_value("server_uri").unwrap_or_trace();
         let use_reverse_proxy = get_env_value_option("reverse_proxy", false);
+        let dashboard_token = get_env_value("dashboard_token").ok();
+        let api_token = get_env_value("api_token").ok();
+        let metrics_token = get_env_value("metrics_token").ok();
         let should_auto_delete =
             get_env_value_option_legacy(&["auto_delete", "delete_flag"], false);
         let tasker_session_path = var::TASKER_SESSION_PATH.to_string();
+        let settings_session_path = var::SETTINGS_SESSION_PATH.to_string();
+        let acl_session_path = var::ACL_SESSION_PATH.to_string();
+        let usage_session_path = var::USAGE_SESSION_PATH.to_string();
         let task_handler_num = get_env_value_option("worker_num", 5);
+        let memory_limit = get_env_value_option("memory_limit", 256);
 
         Self {
             telegram_bot,
             telegram_user,
             onedrive,
+            storage,
+            webhook,
+            chats,
             trace_level,
             port,
             server_uri,
             use_reverse_proxy,
+            dashboard_token,
+            api_token,
+            metrics_token,
             should_auto_delete,
             tasker_session_path,
+            settings_session_path,
+            acl_session_path,
+            usage_session_path,
             task_handler_num,
+            memory_limit,
         }
     }
 
@@ -71,5 +114,21 @@ impl Env {
         fs::create_dir_all(SESSION_DIR)
             .context("failed to create session dir")
             .unwrap_or_trace();
+
+        CONFIG.get_or_init(|| unwrap_or_exit(Config::load()));
+    }
+
+    fn parse_chats() -> HashMap<i64, ChatEnv> {
+        let config = CONFIG.get().unwrap();
+
+        config
+            .chats
+            .iter()
+            .map(|(chat_id, values)| {
+                let chat = unwrap_or_exit(ChatEnv::new(*chat_id, values, &config.path));
+
+                (*chat_id, chat)
+            })
+            .collect()
     }
 }
@@ -8,15 +8,66 @@
 use crate::error::ResultExt;
 
 use super::{
-    utils::{get_env_value, get_env_value_option_legacy},
+    utils::{get_env_value, get_env_value_option, get_env_value_option_legacy},
     var::OD_SESSION_PATH,
 };
+use anyhow::{anyhow, Result};
+use std::str::FromStr;
 
 pub struct OneDriveEnv {
     pub client_id: String,
     pub client_secret: String,
     pub root_path: String,
     pub session_path: String,
+    // common, organizations, consumers or a tenant id
+    pub tenant: String,
+    pub authority_url: String,
+    pub graph_url: String,
+    pub use_device_code: bool,
+}
+
+// https://learn.microsoft.com/en-us/graph/deployments
+#[derive(Clone, Copy)]
+pub enum OneDriveCloud {
+    Global,
+    China,
+    UsGov,
+    UsGovDod,
+}
+
+impl OneDriveCloud {
+    const fn authority_url(self) -> &'static str {
+        match self {
+            Self::Global => "https://login.microsoftonline.com",
+            Self::China => "https://login.chinacloudapi.cn",
+            Self::UsGov | Self::UsGovDod => "https://login.microsoftonline.us",
+        }
+    }
+
+    const fn graph_url(self) -> &'static str {
+        match self {
+            Self::Global => "https://graph.microsoft.com",
+            Self::China => "https://microsoftgraph.chinacloudapi.cn",
+            Self::UsGov => "https://graph.microsoft.us",
+            Self::UsGovDod => "https://dod-graph.microsoft.us",
+        }
+    }
+}
+
+impl FromStr for OneDriveCloud {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "global" => Ok(Self::Global),
+            "china" => Ok(Self::China),
+            "us_gov" => Ok(Self::UsGov),
+            "us_gov_dod" => Ok(Self::UsGovDod),
+            _ => Err(anyhow!(
+                "onedrive cloud should be one of global, china, us_gov and us_gov_dod"
+            )),
+        }
+    }
 }
 
 impl OneDriveEnv {
@@ -26,12 +77,27 @@ impl OneDriveEnv {
         let root_path =
             get_env_value_option_legacy(&["od_root_path", "remote_root_path"], "/".to_string());
         let session_path = OD_SESSION_PATH.to_string();
+        let tenant = get_env_value_option("od_tenant", "common".to_string());
+
+        let cloud = get_env_value_option("od_cloud", OneDriveCloud::Global);
+        let authority_url =
+            get_env_value_option("od_authority_url", cloud.authority_url().to_string())
+                .trim_end_matches('/')
+                .to_string();
+        let graph_url = get_env_value_option("od_graph_url", cloud.graph_url().to_string())
+            .trim_end_matches('/')
+            .to_string();
+        let use_device_code = get_env_value_option("od_device_code", false);
 
         Self {
             client_id,
             client_secret,
             root_path,
             session_path,
+            tenant,
+            authority_url,
+            graph_url,
+            use_device_code,
         }
     }
 }
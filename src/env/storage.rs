@@ -0,0 +1,60 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use super::utils::{get_env_value, get_env_value_option};
+use crate::{error::ResultExt, storage::StorageType};
+
+pub struct StorageEnv {
+    // storage used at startup
+    pub default_storage: StorageType,
+    pub local_root: Option<String>,
+    pub webdav: Option<WebDavEnv>,
+    pub s3: Option<S3Env>,
+}
+
+pub struct WebDavEnv {
+    pub url: String,
+    pub username: Option<String>,
+    pub password: Option<String>,
+}
+
+pub struct S3Env {
+    pub endpoint: String,
+    pub region: String,
+    pub access_key: String,
+    pub secret_key: String,
+    // default bucket and prefix, can be changed per chat
+    pub bucket: String,
+    pub prefix: String,
+}
+
+impl StorageEnv {
+    pub fn new() -> Self {
+        let default_storage = get_env_value_option("storage", StorageType::OneDrive);
+        let local_root = get_env_value("local_root").ok();
+        let webdav = get_env_value("webdav_url").ok().map(|url| WebDavEnv {
+            url,
+            username: get_env_value("webdav_username").ok(),
+            password: get_env_value("webdav_password").ok(),
+        });
+        let s3 = get_env_value("s3_endpoint").ok().map(|endpoint| S3Env {
+            endpoint,
+            region: get_env_value_option("s3_region", "us-east-1".to_string()),
+            access_key: get_env_value("s3_access_key").unwrap_or_trace(),
+            secret_key: get_env_value("s3_secret_key").unwrap_or_trace(),
+            bucket: get_env_value("s3_bucket").unwrap_or_trace(),
+            prefix: get_env_value_option("s3_prefix", String::new()),
+        });
+
+        Self {
+            default_storage,
+            local_root,
+            webdav,
+            s3,
+        }
+    }
+}
@@ -16,7 +16,8 @@ pub struct TelegramUserEnv {
     pub api_id: i32,
     pub api_hash: String,
     pub users: Vec<String>,
-    pub phone_number: String,
+    // login with qr code if not set
+    pub phone_number: Option<String>,
     pub password: Option<String>,
     pub session_path: String,
     pub params: grammers_client::InitParams,
@@ -27,7 +28,7 @@ impl TelegramUserEnv {
         let api_id = get_env_value("tg_api_id").unwrap_or_trace();
         let api_hash = get_env_value("tg_api_hash").unwrap_or_trace();
         let users = Self::parse_users();
-        let phone_number = get_env_value("tg_user_phone").unwrap_or_trace();
+        let phone_number = get_env_value("tg_user_phone").ok();
         let password = get_env_value("tg_user_password").ok();
         let session_path = TG_USER_SESSION_PATH.to_string();
         let params = grammers_client::InitParams {
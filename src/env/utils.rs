@@ -5,46 +5,167 @@
 :license: MIT, see LICENSE for more details.
 */
 
-use anyhow::{Context, Result};
-use std::{env, str::FromStr};
+use super::config::CONFIG;
+use anyhow::{anyhow, Context, Result};
+use std::{env, fmt::Display, fs, str::FromStr};
+
+// env var, then env var with _FILE suffix that points to a file, then the config file
+// (value, source)
+fn get_raw_value(name: &str) -> Option<(String, String)> {
+    if let Ok(value) = env::var(name) {
+        return Some((value, "env".to_string()));
+    }
+
+    let file_name = format!("{}_FILE", name);
+    if let Ok(path) = env::var(&file_name) {
+        // a missing secret file is never ignored
+        let value = fs::read_to_string(&path)
+            .context(format!("failed to read {}", path))
+            .context(file_name);
+
+        // files usually end with a newline
+        let value = unwrap_or_exit(value)
+            .trim_end_matches(['\r', '\n'])
+            .to_string();
+
+        return Some((value, path));
+    }
+
+    let config = CONFIG.get_or_init(Default::default);
+    config
+        .get(name)
+        .map(|value| (value.clone(), config.path.clone()))
+}
+
+pub fn parse_value<T>(name: &str, value: &str, source: &str) -> Result<T>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    // values are not shown since they may be secrets
+    value
+        .parse::<T>()
+        .map_err(|e| anyhow!("invalid value of {} from {}: {}", name, source, e))
+}
 
 pub fn get_env_value<T>(name: &str) -> Result<T>
 where
     T: FromStr,
-    T::Err: std::error::Error + Send + Sync + 'static,
+    T::Err: Display,
 {
-    let value_s = env::var(name)
-        .context("failed to get env value")
+    let (value_s, source) = get_raw_value(name)
+        .ok_or_else(|| anyhow!("failed to get env value"))
         .context(name.to_string())?;
 
-    let value = value_s
-        .parse::<T>()
-        .context("failed to parse env value")
-        .context(format!("{}={}", name, value_s))?;
-
-    Ok(value)
+    parse_value(name, &value_s, &source)
 }
 
+// invalid values are not replaced by the default
 pub fn get_env_value_option<T>(name: &str, default: T) -> T
 where
     T: FromStr,
+    T::Err: Display,
 {
-    match env::var(name) {
-        Ok(value_s) => value_s.parse::<T>().unwrap_or(default),
-        Err(_) => default,
-    }
+    get_env_value_option_legacy(&[name], default)
 }
 
 // to be compatible with the python version
 pub fn get_env_value_option_legacy<T>(names: &[&str], default: T) -> T
 where
     T: FromStr,
+    T::Err: Display,
+{
+    unwrap_or_exit(try_get_env_value_option(names, default))
+}
+
+fn try_get_env_value_option<T>(names: &[&str], default: T) -> Result<T>
+where
+    T: FromStr,
+    T::Err: Display,
 {
     for name in names {
-        if let Ok(value_s) = env::var(name) {
-            return value_s.parse::<T>().unwrap_or(default);
+        if let Some((value_s, source)) = get_raw_value(name) {
+            return parse_value(name, &value_s, &source);
         }
     }
 
-    default
+    Ok(default)
+}
+
+pub fn unwrap_or_exit<T>(result: Result<T>) -> T {
+    result.unwrap_or_else(|e| {
+        // tracing is not registered until env is loaded, errors are printed to stdout then
+        tracing_subscriber::fmt().try_init().ok();
+        tracing::error!("{:?}", e);
+
+        std::process::exit(1);
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::env::config::Config;
+
+    // tests share the config, so each test uses its own keys
+    fn init_config() {
+        CONFIG.get_or_init(|| {
+            Config::parse(
+                "config.toml".to_string(),
+                r#"
+api_token = "config"
+metrics_token = "config"
+dashboard_token = "config"
+worker_num = "many"
+remote_root_path = "/legacy"
+"#,
+            )
+            .unwrap()
+        });
+    }
+
+    #[test]
+    fn raw_value_precedence() {
+        init_config();
+
+        let path = env::temp_dir().join("telegram-onedrive-test-token");
+        fs::write(&path, "file\n").unwrap();
+
+        env::set_var("api_token", "env");
+        env::set_var("api_token_FILE", &path);
+        env::set_var("metrics_token_FILE", &path);
+
+        assert_eq!(get_env_value::<String>("api_token").unwrap(), "env");
+        assert_eq!(get_env_value::<String>("metrics_token").unwrap(), "file");
+        assert_eq!(
+            get_env_value::<String>("dashboard_token").unwrap(),
+            "config"
+        );
+        assert!(get_env_value::<String>("webhook_secret").is_err());
+        assert_eq!(
+            get_env_value_option("s3_region", "default".to_string()),
+            "default"
+        );
+        assert_eq!(
+            get_env_value_option_legacy(&["od_root_path", "remote_root_path"], "/".to_string()),
+            "/legacy"
+        );
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn invalid_option_is_not_defaulted() {
+        init_config();
+
+        let e = try_get_env_value_option::<u8>(&["worker_num"], 5).unwrap_err();
+        assert!(e
+            .to_string()
+            .starts_with("invalid value of worker_num from config.toml"));
+
+        env::set_var("port", "http");
+        let e = try_get_env_value_option::<u16>(&["port"], 8080).unwrap_err();
+        assert!(e.to_string().starts_with("invalid value of port from env"));
+        assert!(!e.to_string().contains("http"));
+    }
 }
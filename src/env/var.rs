@@ -10,11 +10,17 @@ use std::time::Duration;
 
 pub const LOGS_PATH: &str = "./logs";
 
+pub const CONFIG_PATH: &str = "./config.toml";
+
 pub const SESSION_DIR: &str = "./session";
 pub const TG_BOT_SESSION_PATH: &str = "./session/tg-bot.session";
 pub const TG_USER_SESSION_PATH: &str = "./session/tg-user.session";
 pub const OD_SESSION_PATH: &str = "./session/od.session";
 pub const TASKER_SESSION_PATH: &str = "./session/tasker.session";
+pub const WEBHOOK_SESSION_PATH: &str = "./session/webhook.session";
+pub const SETTINGS_SESSION_PATH: &str = "./session/settings.session";
+pub const ACL_SESSION_PATH: &str = "./session/acl.session";
+pub const USAGE_SESSION_PATH: &str = "./session/usage.session";
 
 pub const RECONNECTION_POLICY: FixedReconnect = FixedReconnect {
     attempts: 5,
@@ -0,0 +1,37 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use super::{utils::get_env_value, var::WEBHOOK_SESSION_PATH};
+
+pub struct WebhookEnv {
+    // task events are posted to each of them, void if webhooks are disabled
+    pub urls: Vec<String>,
+    // payloads are signed with hmac-sha256 if set
+    pub secret: Option<String>,
+    pub session_path: String,
+}
+
+impl WebhookEnv {
+    pub fn new() -> Self {
+        let urls = get_env_value::<String>("webhook_urls")
+            .ok()
+            .map_or_else(Vec::new, |urls| {
+                urls.split(',')
+                    .map(|url| url.trim().to_string())
+                    .filter(|url| !url.is_empty())
+                    .collect()
+            });
+        let secret = get_env_value("webhook_secret").ok();
+        let session_path = WEBHOOK_SESSION_PATH.to_string();
+
+        Self {
+            urls,
+            secret,
+            session_path,
+        }
+    }
+}
@@ -0,0 +1,85 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use super::{
+    docs::{format_help, format_unknown_command_help},
+    utils::text::cmd_parser,
+};
+use crate::{message::TelegramMessage, settings::API_CHAT_KEY, state::AppState};
+use anyhow::{anyhow, Context, Result};
+use grammers_client::InputMessage;
+use proc_macros::{check_chat_type, require_role};
+
+pub const PATTERN: &str = "/api";
+
+#[require_role(admin)]
+#[check_chat_type]
+pub async fn handler(message: TelegramMessage, state: AppState) -> Result<()> {
+    let cmd = cmd_parser(message.text());
+
+    if cmd.len() == 1 {
+        // /api
+        show_api_chat(message, state).await?;
+    } else if cmd.len() == 2 && cmd[1] == "help" {
+        // /api help
+        message
+            .respond(InputMessage::html(format_help(PATTERN)))
+            .await
+            .context("help")?;
+    } else if cmd.len() == 2 && cmd[1] == "on" {
+        // /api on
+        set_api_chat(message, state).await?;
+    } else if cmd.len() == 2 && cmd[1] == "off" {
+        // /api off
+        unset_api_chat(message, state).await?;
+    } else {
+        return Err(anyhow!("command error")).context(format_unknown_command_help(PATTERN));
+    }
+
+    Ok(())
+}
+
+async fn show_api_chat(message: TelegramMessage, state: AppState) -> Result<()> {
+    let api_chat = *state.api_chat.lock().await;
+
+    let response = match api_chat {
+        Some(chat) if chat.id == message.chat().id() => {
+            "Tasks submitted through API are announced in this chat."
+        }
+        Some(_) => "Tasks submitted through API are announced in another chat.",
+        None => "No chat is set for tasks submitted through API.",
+    };
+    message.respond(response).await.context(response)?;
+
+    Ok(())
+}
+
+async fn set_api_chat(message: TelegramMessage, state: AppState) -> Result<()> {
+    let chat = message.chat().pack();
+
+    state
+        .settings
+        .session
+        .set_value(API_CHAT_KEY, &chat.to_hex())
+        .await?;
+    *state.api_chat.lock().await = Some(chat);
+
+    let response = "Tasks submitted through API will be announced in this chat.";
+    message.respond(response).await.context(response)?;
+
+    Ok(())
+}
+
+async fn unset_api_chat(message: TelegramMessage, state: AppState) -> Result<()> {
+    state.settings.session.delete_value(API_CHAT_KEY).await?;
+    *state.api_chat.lock().await = None;
+
+    let response = "Tasks can't be submitted through API until a chat is set.";
+    message.respond(response).await.context(response)?;
+
+    Ok(())
+}
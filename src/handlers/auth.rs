@@ -5,21 +5,21 @@
 :license: MIT, see LICENSE for more details.
 */
 
-use crate::{auth_server, message::TelegramMessage, state::AppState};
+use crate::{auth_server, env::ENV, message::TelegramMessage, state::AppState};
 use anyhow::{Context, Result};
-use proc_macros::{check_in_group, check_senders};
+use proc_macros::{check_chat_type, require_role};
 use tokio::sync::mpsc::Receiver;
 
 pub const PATTERN: &str = "/auth";
 
-#[check_senders]
-#[check_in_group]
+#[require_role(admin)]
+#[check_chat_type]
 pub async fn handler(message: TelegramMessage, state: AppState) -> Result<()> {
     let (rx_tg, rx_od, _server_abort_handle) = auth_server::spawn().await?;
 
     login_to_telegram(message.clone(), state.clone(), rx_tg).await?;
 
-    authorize_onedrive(message, state.clone(), false, rx_od).await?;
+    authorize_onedrive(message, state.clone(), false, Some(rx_od)).await?;
 
     let onedrive = &state.onedrive;
     onedrive.set_current_user().await?;
@@ -34,7 +34,14 @@ pub async fn login_to_telegram(
 ) -> Result<()> {
     let telegram_user = &state.telegram_user;
 
-    telegram_user.login(message.clone(), rx).await?;
+    // login with qr code if phone number is not given
+    if ENV.get().unwrap().telegram_user.phone_number.is_some() {
+        telegram_user.login(message.clone(), rx).await?;
+    } else if !telegram_user.is_authorized().await? {
+        telegram_user
+            .login_with_qr(message.clone(), &state.telegram_bot)
+            .await?;
+    }
 
     let response = "Login to Telegram successful!";
     message.respond(response).await.context(response)?;
@@ -42,15 +49,26 @@ pub async fn login_to_telegram(
     Ok(())
 }
 
+// the auth server is spawned if it's needed and rx is not given
 pub async fn authorize_onedrive(
     message: TelegramMessage,
     state: AppState,
     should_add: bool,
-    rx: Receiver<String>,
+    rx: Option<Receiver<String>>,
 ) -> Result<()> {
     let onedrive = &state.onedrive;
 
-    onedrive.login(message.clone(), should_add, rx).await?;
+    if onedrive.use_device_code {
+        onedrive
+            .login_with_device_code(message.clone(), should_add)
+            .await?;
+    } else if let Some(rx) = rx {
+        onedrive.login(message.clone(), should_add, rx).await?;
+    } else {
+        let (_, rx, _server_abort_handle) = auth_server::spawn().await?;
+
+        onedrive.login(message.clone(), should_add, rx).await?;
+    }
 
     let response = "OneDrive authorization successful!";
     message.respond(response).await.context(response)?;
@@ -7,19 +7,20 @@
 
 use crate::{message::TelegramMessage, state::AppState};
 use anyhow::{Context, Result};
-use proc_macros::{check_in_group, check_senders};
-use std::sync::atomic::Ordering;
+use proc_macros::{check_chat_type, require_role};
 
 pub const PATTERN: &str = "/autoDelete";
 
-#[check_senders]
-#[check_in_group]
+#[require_role(admin)]
+#[check_chat_type]
 pub async fn handler(message: TelegramMessage, state: AppState) -> Result<()> {
-    let should_auto_delete = state.should_auto_delete.load(Ordering::Acquire);
+    let should_auto_delete = state.settings.get().await.auto_delete;
 
+    // the same as /settings auto_delete
     state
-        .should_auto_delete
-        .store(!should_auto_delete, Ordering::Release);
+        .settings
+        .set("auto_delete", &(!should_auto_delete).to_string())
+        .await?;
 
     if should_auto_delete {
         let response = "Bot won't auto delete message.";
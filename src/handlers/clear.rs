@@ -6,23 +6,52 @@
 */
 
 use crate::{
+    error::ResultExt,
     message::{ChatEntity, TelegramMessage},
     state::AppState,
+    storage::StorageBackend,
+    tasker::TaskStatus,
 };
 use anyhow::{Context, Result};
-use proc_macros::{check_in_group, check_senders, check_tg_login};
+use grammers_client::types::Chat;
+use proc_macros::{check_chat_type, check_tg_login, require_role};
 
 pub const PATTERN: &str = "/clear";
 
 #[check_tg_login]
-#[check_senders]
-#[check_in_group]
+#[require_role(admin)]
+#[check_chat_type]
 pub async fn handler(message: TelegramMessage, state: AppState) -> Result<()> {
     let telegram_user = &state.telegram_user;
     let task_session = &state.task_session;
 
+    // running tasks cancel their uploads once aborted, the others are only in the db
+    let idle_tasks = task_session
+        .get_tasks()
+        .await?
+        .into_iter()
+        .filter(|task| matches!(task.status, TaskStatus::Waiting | TaskStatus::Paused))
+        .collect::<Vec<_>>();
+
     task_session.clear().await?;
 
+    for task in idle_tasks {
+        state
+            .storage(&task.storage)?
+            .cancel_upload(&task.upload_url)
+            .await
+            .trace();
+    }
+
+    // bots can only delete messages by ids, which are shared by all private chats of the bot
+    if let Chat::User(_) = message.chat() {
+        let response =
+            "Tasks are cleared. To clear history of a private chat, use Clear History of Telegram.";
+        message.respond(response).await.context(response)?;
+
+        return Ok(());
+    }
+
     let chat = telegram_user
         .get_chat(&ChatEntity::from(message.chat()))
         .await?;
@@ -0,0 +1,52 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use super::{
+    docs::{format_help, format_unknown_command_help},
+    utils::{text::cmd_parser, validate_root_path},
+};
+use crate::{client::onedrive::item::CopyStatus, message::TelegramMessage, state::AppState};
+use anyhow::{anyhow, Context, Result};
+use grammers_client::InputMessage;
+use proc_macros::{check_chat_type, check_od_login, require_role};
+
+pub const PATTERN: &str = "/cp";
+
+#[check_od_login]
+#[require_role(uploader)]
+#[check_chat_type]
+pub async fn handler(message: TelegramMessage, state: AppState) -> Result<()> {
+    let cmd = cmd_parser(message.text());
+
+    if cmd.len() == 2 && cmd[1] == "help" {
+        // /cp help
+        message
+            .respond(InputMessage::html(format_help(PATTERN)))
+            .await
+            .context("help")?;
+    } else if cmd.len() == 3 {
+        // /cp $src $dst
+        let src = &cmd[1];
+        let dst = &cmd[2];
+
+        validate_root_path(src).await?;
+        validate_root_path(dst).await?;
+
+        let response = match state.onedrive.copy_item(src, dst).await? {
+            CopyStatus::Completed => format!("Copied {} to {}", src, dst),
+            CopyStatus::InProgress => format!(
+                "Copying {} to {}\nOneDrive is still working on it in the background.",
+                src, dst
+            ),
+        };
+        message.respond(response.as_str()).await.context(response)?;
+    } else {
+        return Err(anyhow!("command error")).context(format_unknown_command_help(PATTERN));
+    }
+
+    Ok(())
+}
@@ -7,30 +7,34 @@
 
 use super::{
     docs::{format_help, format_unknown_command_help},
-    utils::{text::cmd_parser, validate_root_path},
+    utils::{join_topic_dir, text::cmd_parser, validate_root_path},
 };
-use crate::{client::OneDriveClient, message::TelegramMessage, state::AppState};
+use crate::{message::TelegramMessage, state::AppState, storage::StorageType};
 use anyhow::{anyhow, Context, Result};
 use grammers_client::InputMessage;
-use proc_macros::{check_in_group, check_od_login, check_senders};
+use proc_macros::{check_chat_type, check_storage_login, require_role};
 
 pub const PATTERN: &str = "/dir";
 
-#[check_od_login]
-#[check_senders]
-#[check_in_group]
+#[check_storage_login]
+#[require_role(uploader)]
+#[check_chat_type]
 pub async fn handler(message: TelegramMessage, state: AppState) -> Result<()> {
-    let onedrive = &state.onedrive;
+    // each storage has its own directory
+    let storage = state.current_storage_type().await;
 
     let cmd = cmd_parser(message.text());
 
     if cmd.len() == 1 {
         // /dir
-        show_dir(onedrive, message).await?;
+        show_dir(&state, &storage, message).await?;
     } else if cmd.len() == 2 {
         if cmd[1] == "reset" {
             // /dir reset
-            reset_dir(onedrive, message).await?;
+            reset_dir(&state, &storage, message).await?;
+        } else if cmd[1] == "topic" {
+            // /dir topic
+            show_topic_dir(&state, &storage, message).await?;
         } else if cmd[1] == "help" {
             // /dir help
             message
@@ -40,17 +44,26 @@ pub async fn handler(message: TelegramMessage, state: AppState) -> Result<()> {
         } else {
             // dir $root_path
             let root_path = &cmd[1];
-            set_dir(onedrive, message, root_path).await?;
+            set_dir(&state, &storage, message, root_path).await?;
         }
     } else if cmd.len() == 3 {
         if cmd[1] == "temp" {
             if cmd[2] == "cancel" {
                 // /dir temp cancel
-                cancel_temp_dir(onedrive, message).await?;
+                cancel_temp_dir(&state, &storage, message).await?;
             } else {
                 // /dir temp $path
                 let temp_root_path = &cmd[2];
-                set_temp_dir(onedrive, message, temp_root_path).await?;
+                set_temp_dir(&state, &storage, message, temp_root_path).await?;
+            }
+        } else if cmd[1] == "topic" {
+            if cmd[2] == "reset" {
+                // /dir topic reset
+                reset_topic_dir(message, &state).await?;
+            } else {
+                // /dir topic $path
+                let topic_dir = &cmd[2];
+                set_topic_dir(&state, &storage, message, topic_dir).await?;
             }
         } else {
             return Err(anyhow!("sub command error")).context(format_unknown_command_help(PATTERN));
@@ -62,9 +75,9 @@ pub async fn handler(message: TelegramMessage, state: AppState) -> Result<()> {
     Ok(())
 }
 
-async fn show_dir(onedrive: &OneDriveClient, message: TelegramMessage) -> Result<()> {
-    let root_path = onedrive.get_root_path(false).await?;
-    let is_temp = onedrive.does_temp_root_path_exist().await;
+async fn show_dir(state: &AppState, storage: &StorageType, message: TelegramMessage) -> Result<()> {
+    let root_path = state.get_root_path(storage, false).await?;
+    let is_temp = state.does_temp_root_path_exist(storage).await?;
 
     let response = if is_temp {
         format!("Current directory is {}, and it's temporary.", root_path)
@@ -76,23 +89,31 @@ async fn show_dir(onedrive: &OneDriveClient, message: TelegramMessage) -> Result
     Ok(())
 }
 
-async fn reset_dir(onedrive: &OneDriveClient, message: TelegramMessage) -> Result<()> {
-    onedrive.reset_root_path().await?;
+async fn reset_dir(
+    state: &AppState,
+    storage: &StorageType,
+    message: TelegramMessage,
+) -> Result<()> {
+    state.reset_root_path(storage).await?;
 
-    let response = format!("Directory reset to default {}", onedrive.default_root_path);
+    let response = format!(
+        "Directory reset to default {}",
+        state.onedrive.default_root_path
+    );
     message.respond(response.as_str()).await.context(response)?;
 
     Ok(())
 }
 
 async fn set_dir(
-    onedrive: &OneDriveClient,
+    state: &AppState,
+    storage: &StorageType,
     message: TelegramMessage,
     root_path: &str,
 ) -> Result<()> {
     validate_root_path(root_path).await?;
 
-    onedrive.set_root_path(root_path).await?;
+    state.set_root_path(storage, root_path).await?;
 
     let response = format!("Directory set to {}", root_path);
     message.respond(response.as_str()).await.context(response)?;
@@ -100,12 +121,16 @@ async fn set_dir(
     Ok(())
 }
 
-async fn cancel_temp_dir(onedrive: &OneDriveClient, message: TelegramMessage) -> Result<()> {
-    onedrive.clear_temp_root_path().await?;
+async fn cancel_temp_dir(
+    state: &AppState,
+    storage: &StorageType,
+    message: TelegramMessage,
+) -> Result<()> {
+    state.clear_temp_root_path(storage).await?;
 
     let response = format!(
         "Temporary directory canceled.\nCurrent directory is {}",
-        onedrive.get_root_path(false).await?
+        state.get_root_path(storage, false).await?
     );
     message.respond(response.as_str()).await.context(response)?;
 
@@ -113,16 +138,74 @@ async fn cancel_temp_dir(onedrive: &OneDriveClient, message: TelegramMessage) ->
 }
 
 async fn set_temp_dir(
-    onedrive: &OneDriveClient,
+    state: &AppState,
+    storage: &StorageType,
     message: TelegramMessage,
     temp_root_path: &str,
 ) -> Result<()> {
     validate_root_path(temp_root_path).await?;
 
-    onedrive.set_temp_root_path(temp_root_path).await?;
+    state.set_temp_root_path(storage, temp_root_path).await?;
 
     let response = format!("Temporary directory set to {}", temp_root_path);
     message.respond(response.as_str()).await.context(response)?;
 
     Ok(())
 }
+
+fn get_topic_id(message: &TelegramMessage) -> Result<i32> {
+    message
+        .topic_id()
+        .ok_or_else(|| anyhow!("this command should be sent in a forum topic other than General"))
+}
+
+async fn show_topic_dir(
+    state: &AppState,
+    storage: &StorageType,
+    message: TelegramMessage,
+) -> Result<()> {
+    let topic_id = get_topic_id(&message)?;
+
+    let response = match state.get_topic_dir(message.chat().id(), topic_id).await {
+        Some(topic_dir) => format!(
+            "Directory of this topic is {}",
+            join_topic_dir(&state.get_root_path(storage, false).await?, &topic_dir)
+        ),
+        None => "This topic uses the current directory.".to_string(),
+    };
+    message.respond(response.as_str()).await.context(response)?;
+
+    Ok(())
+}
+
+async fn set_topic_dir(
+    state: &AppState,
+    storage: &StorageType,
+    message: TelegramMessage,
+    topic_dir: &str,
+) -> Result<()> {
+    let topic_id = get_topic_id(&message)?;
+
+    state
+        .set_topic_dir(message.chat().id(), topic_id, topic_dir)
+        .await?;
+
+    let response = format!(
+        "Directory of this topic set to {}",
+        join_topic_dir(&state.get_root_path(storage, false).await?, topic_dir)
+    );
+    message.respond(response.as_str()).await.context(response)?;
+
+    Ok(())
+}
+
+async fn reset_topic_dir(message: TelegramMessage, state: &AppState) -> Result<()> {
+    let topic_id = get_topic_id(&message)?;
+
+    state.reset_topic_dir(message.chat().id(), topic_id).await?;
+
+    let response = "This topic uses the current directory again.";
+    message.respond(response).await.context(response)?;
+
+    Ok(())
+}
@@ -49,40 +49,156 @@ To show command help.
 
 const HELP_DRIVE: &str = "\
 <pre><code>/drive</code></pre>
-To list all OneDrive accounts.
+To list all OneDrive accounts and other storages.
 <pre><code>/drive add</code></pre>
 To add a OneDrive account.
 <pre><code>/drive $index</code></pre>
-To change the OneDrive account.
+To change the OneDrive account or storage.
 <pre><code>/drive logout</code></pre>
 To logout current OneDrive account.
 <pre><code>/drive logout $index</code></pre>
 To logout specified OneDrive account.
+<pre><code>/drive location</code></pre>
+To show the drive that current OneDrive account uploads to.
+<pre><code>/drive site $site $library</code></pre>
+To upload to a document library of a SharePoint site, $site can be the site url, and $library is optional.
+<pre><code>/drive group $group_id</code></pre>
+To upload to the drive of a Microsoft 365 group.
+<pre><code>/drive id $drive_id</code></pre>
+To upload to the drive with the specified id.
+<pre><code>/drive personal</code></pre>
+To upload to the personal drive of current OneDrive account.
 <pre><code>/drive help</code></pre>
 To show command help.
 ";
 
+const HELP_QUOTA: &str = "\
+<pre><code>/quota</code></pre>
+To show storage usage of all OneDrive accounts.
+<pre><code>/quota help</code></pre>
+To show command help.
+";
+
+const HELP_S3: &str = "\
+<pre><code>/s3</code></pre>
+To show S3 bucket and prefix of this chat.
+<pre><code>/s3 $bucket $prefix</code></pre>
+To set S3 bucket and prefix of this chat, $prefix is optional.
+<pre><code>/s3 reset</code></pre>
+To reset S3 bucket and prefix to default.
+<pre><code>/s3 help</code></pre>
+To show command help.
+";
+
+const HELP_API: &str = "\
+<pre><code>/api</code></pre>
+To show whether tasks submitted through HTTP API are announced in this chat.
+<pre><code>/api on</code></pre>
+To announce tasks submitted through HTTP API in this chat.
+<pre><code>/api off</code></pre>
+To stop accepting tasks from HTTP API.
+<pre><code>/api help</code></pre>
+To show command help.
+";
+
+const HELP_SETTINGS: &str = "\
+<pre><code>/settings</code></pre>
+To show runtime settings.
+<pre><code>/settings $key $value</code></pre>
+To change a setting, it takes effect immediately and is kept after restarting.
+<pre><code>/settings reset $key</code></pre>
+To reset a setting to default.
+<pre><code>/settings help</code></pre>
+To show command help.
+";
+
+const HELP_USERS: &str = "\
+<pre><code>/users</code></pre>
+To show users and their roles.
+<pre><code>/users add $id $role</code></pre>
+To set the role of a user by numeric user id, $role is admin, uploader or viewer. Reply to a message of the user to omit $id.
+<pre><code>/users remove $id</code></pre>
+To remove a user.
+<pre><code>/users help</code></pre>
+To show command help.
+";
+
+const HELP_USAGE: &str = "\
+<pre><code>/usage</code></pre>
+To show bytes transferred by each user today and this month, and their limits.
+<pre><code>/usage limit $id $kind $value</code></pre>
+To set a limit of a user, $kind is running, queued, daily or monthly, $value is a number of tasks, a size like 10G for daily and monthly, or none to remove the limit.
+<pre><code>/usage limit default $kind $value</code></pre>
+To set a limit of everyone, used when a user doesn't have their own.
+<pre><code>/usage help</code></pre>
+To show command help.
+";
+
 const HELP_DIR: &str = "\
 <pre><code>/dir</code></pre>
-To show current OneDrive directory.
+To show directory of the current storage.
 <pre><code>/dir $path</code></pre>
-To set OneDrive directory.
+To set directory of the current storage.
 <pre><code>/dir temp $path</code></pre>
-To set temporary OneDrive directory.
+To set temporary directory of the current storage.
 <pre><code>/dir temp cancel</code></pre>
-To restore OneDrive directory to the previous one.
+To restore directory of the current storage to the previous one.
 <pre><code>/dir reset</code></pre>
-To reset OneDrive directory to default.
+To reset directory of the current storage to default.
+<pre><code>/dir topic</code></pre>
+To show directory of the current forum topic.
+<pre><code>/dir topic $path</code></pre>
+To set directory of the current forum topic, relative to current directory unless it starts with /.
+<pre><code>/dir topic reset</code></pre>
+To make the current forum topic use current directory.
 <pre><code>/dir help</code></pre>
 To show command help.
 ";
 
+const HELP_FILES: &str = "\
+<pre><code>/mkdir $path</code></pre>
+To create a OneDrive directory.
+<pre><code>/mv $path $dir</code></pre>
+To move a OneDrive item into another directory.
+<pre><code>/cp $path $dir</code></pre>
+To copy a OneDrive item into another directory.
+<pre><code>/rm $path</code></pre>
+To move a OneDrive item to the recycle bin, confirm with /rm confirm.
+<pre><code>/rename $path $name</code></pre>
+To rename a OneDrive item.
+";
+
+const HELP_SHARE: &str = "\
+<pre><code>/share</code></pre>
+To show share link option of this chat.
+<pre><code>/share on $type $scope $days</code></pre>
+To create a share link for each completed upload, $type is view or edit, $scope is anonymous or organization, $days is optional expiration days.
+<pre><code>/share off</code></pre>
+To stop creating share links.
+<pre><code>/share $path</code></pre>
+To create a share link for an existing OneDrive item.
+<pre><code>/share help</code></pre>
+To show command help.
+";
+
+const HELP_PROGRESS: &str = "\
+<pre><code>/progress</code></pre>
+To show where progress of this chat is shown.
+<pre><code>/progress aggregate</code></pre>
+To show progress of all tasks in a progress message.
+<pre><code>/progress indicator</code></pre>
+To show progress of each task in its indicator.
+<pre><code>/progress help</code></pre>
+To show command help.
+";
+
 const INSTRUCTION: &str = "\
 - To transfer files, forward or upload to me.
 - To transfer restricted content, right click the content, copy the message link, and send to me.
 - Tap the file name on the Progress message to locate the job.
 - To upload files through url, the headers of the file response must includes Content-Length.
-- To cancel a job, delete the responded message.
+- To cancel a job, press Cancel under the responded message, or delete it.
+- To pause, resume or retry a job, press the buttons under the responded message.
 - To cancel batch or links tasks, delete the message you sent.
 - Support files with extension .t2o as scripts.
 
@@ -101,8 +217,23 @@ pub fn format_help(name: &str) -> String {
     match name {
         "/help" => {
             format!(
-                "{}{}{}{}{}{}\n{}",
-                HELP_BASE, HELP_LINKS, HELP_URL, HELP_LOGS, HELP_DRIVE, HELP_DIR, INSTRUCTION
+                "{}{}{}{}{}{}{}{}{}{}{}{}{}{}{}\n{}",
+                HELP_BASE,
+                HELP_LINKS,
+                HELP_URL,
+                HELP_LOGS,
+                HELP_DRIVE,
+                HELP_QUOTA,
+                HELP_S3,
+                HELP_API,
+                HELP_SETTINGS,
+                HELP_USERS,
+                HELP_USAGE,
+                HELP_DIR,
+                HELP_FILES,
+                HELP_SHARE,
+                HELP_PROGRESS,
+                INSTRUCTION
             )
         }
         "/start" => GREETING.to_string(),
@@ -110,7 +241,16 @@ pub fn format_help(name: &str) -> String {
         "/url" => HELP_URL.to_string(),
         "/logs" => HELP_LOGS.to_string(),
         "/drive" => HELP_DRIVE.to_string(),
+        "/quota" => HELP_QUOTA.to_string(),
         "/dir" => HELP_DIR.to_string(),
+        "/mkdir" | "/mv" | "/cp" | "/rm" | "/rename" => HELP_FILES.to_string(),
+        "/share" => HELP_SHARE.to_string(),
+        "/progress" => HELP_PROGRESS.to_string(),
+        "/s3" => HELP_S3.to_string(),
+        "/api" => HELP_API.to_string(),
+        "/settings" => HELP_SETTINGS.to_string(),
+        "/users" => HELP_USERS.to_string(),
+        "/usage" => HELP_USAGE.to_string(),
         _ => String::new(),
     }
 }
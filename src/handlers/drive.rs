@@ -10,17 +10,20 @@ use super::{
     utils::text::cmd_parser,
 };
 use crate::{
-    auth_server, client::OneDriveClient, handlers::auth::authorize_onedrive,
-    message::TelegramMessage, state::AppState,
+    client::{onedrive::location::DriveInfo, OneDriveClient},
+    handlers::auth::authorize_onedrive,
+    message::TelegramMessage,
+    state::AppState,
+    storage::StorageType,
 };
 use anyhow::{anyhow, Context, Result};
 use grammers_client::InputMessage;
-use proc_macros::{check_in_group, check_senders};
+use proc_macros::{check_chat_type, require_role};
 
 pub const PATTERN: &str = "/drive";
 
-#[check_senders]
-#[check_in_group]
+#[require_role(admin)]
+#[check_chat_type]
 pub async fn handler(message: TelegramMessage, state: AppState) -> Result<()> {
     let onedrive = &state.onedrive;
 
@@ -28,7 +31,7 @@ pub async fn handler(message: TelegramMessage, state: AppState) -> Result<()> {
 
     if cmd.len() == 1 {
         // /drive
-        show_drive(onedrive, message).await?;
+        show_drive(&state, message).await?;
     } else if cmd.len() == 2 {
         if cmd[1] == "add" {
             // /drive add
@@ -36,6 +39,12 @@ pub async fn handler(message: TelegramMessage, state: AppState) -> Result<()> {
         } else if cmd[1] == "logout" {
             // /drive logout
             logout_current_drive(onedrive, message).await?;
+        } else if cmd[1] == "location" {
+            // /drive location
+            show_drive_location(onedrive, message).await?;
+        } else if cmd[1] == "personal" {
+            // /drive personal
+            set_drive_location(onedrive, message, None).await?;
         } else if cmd[1] == "help" {
             // /drive help
             message
@@ -49,7 +58,7 @@ pub async fn handler(message: TelegramMessage, state: AppState) -> Result<()> {
                 .context("account index should be integer")?
                 - 1;
 
-            set_drive(onedrive, message, index).await?;
+            set_drive(&state, message, index).await?;
         }
     } else if cmd.len() == 3 {
         if cmd[1] == "logout" {
@@ -60,9 +69,30 @@ pub async fn handler(message: TelegramMessage, state: AppState) -> Result<()> {
                 - 1;
 
             logout_drive(onedrive, message, index).await?;
+        } else if cmd[1] == "site" {
+            // /drive site $site
+            let drive = onedrive.find_site_drive(&cmd[2], None).await?;
+
+            set_drive_location(onedrive, message, Some(drive)).await?;
+        } else if cmd[1] == "group" {
+            // /drive group $group_id
+            let drive = onedrive.find_group_drive(&cmd[2]).await?;
+
+            set_drive_location(onedrive, message, Some(drive)).await?;
+        } else if cmd[1] == "id" {
+            // /drive id $drive_id
+            let drive = onedrive.find_drive(&cmd[2]).await?;
+
+            set_drive_location(onedrive, message, Some(drive)).await?;
         } else {
             return Err(anyhow!("sub command error")).context(format_unknown_command_help(PATTERN));
         }
+    } else if cmd.len() > 3 && cmd[1] == "site" {
+        // /drive site $site $library
+        let library = cmd[3..].join(" ");
+        let drive = onedrive.find_site_drive(&cmd[2], Some(&library)).await?;
+
+        set_drive_location(onedrive, message, Some(drive)).await?;
     } else {
         return Err(anyhow!("command error")).context(format_unknown_command_help(PATTERN));
     }
@@ -70,26 +100,68 @@ pub async fn handler(message: TelegramMessage, state: AppState) -> Result<()> {
     Ok(())
 }
 
-async fn show_drive(onedrive: &OneDriveClient, message: TelegramMessage) -> Result<()> {
+// storages other than onedrive accounts, listed after the accounts
+fn get_extra_storages(state: &AppState) -> Vec<(StorageType, String)> {
+    let mut extra_storages = Vec::new();
+
+    if let Some(local_storage) = &state.local_storage {
+        extra_storages.push((
+            StorageType::Local,
+            format!("Local directory {}", local_storage.root.display()),
+        ));
+    }
+
+    if let Some(webdav_storage) = &state.webdav_storage {
+        extra_storages.push((
+            StorageType::WebDav,
+            format!("WebDAV {}", webdav_storage.url),
+        ));
+    }
+
+    if let Some(s3_storage) = &state.s3_storage {
+        extra_storages.push((StorageType::S3, format!("S3 {}", s3_storage.endpoint)));
+    }
+
+    extra_storages
+}
+
+async fn show_drive(state: &AppState, message: TelegramMessage) -> Result<()> {
+    let onedrive = &state.onedrive;
+
     let usernames = onedrive.get_usernames().await?;
-    if let Some(current_username) = onedrive.get_current_username().await? {
-        if !usernames.is_empty() {
-            let response = {
-                let mut response = format!("Current account is {}", current_username);
-
-                if usernames.len() > 1 {
-                    response.insert(0, '\n');
-                    for i in (1..=usernames.len()).rev() {
-                        response.insert_str(0, &format!("{}. {}\n", i, usernames[i - 1]));
-                    }
-                }
-
-                response
-            };
-            message.respond(response.as_str()).await.context(response)?;
-
-            return Ok(());
+    let extra_storages = get_extra_storages(state);
+
+    let current_storage = state.current_storage_type().await;
+
+    let current = if current_storage == StorageType::OneDrive {
+        onedrive
+            .get_current_username()
+            .await?
+            .filter(|_| !usernames.is_empty())
+            .map(|current_username| format!("Current account is {}", current_username))
+    } else {
+        extra_storages
+            .iter()
+            .find(|(storage_type, _)| *storage_type == current_storage)
+            .map(|(_, name)| format!("Current storage is {}", name))
+    };
+
+    if let Some(mut response) = current {
+        let names = usernames
+            .iter()
+            .chain(extra_storages.iter().map(|(_, name)| name))
+            .collect::<Vec<_>>();
+
+        if names.len() > 1 {
+            response.insert(0, '\n');
+            for i in (1..=names.len()).rev() {
+                response.insert_str(0, &format!("{}. {}\n", i, names[i - 1]));
+            }
         }
+
+        message.respond(response.as_str()).await.context(response)?;
+
+        return Ok(());
     }
 
     let response = "No account found.";
@@ -99,8 +171,7 @@ async fn show_drive(onedrive: &OneDriveClient, message: TelegramMessage) -> Resu
 }
 
 async fn add_drive(message: TelegramMessage, state: AppState) -> Result<()> {
-    let (_, rx, _server_abort_handle) = auth_server::spawn().await?;
-    authorize_onedrive(message, state, true, rx).await?;
+    authorize_onedrive(message, state, true, None).await?;
 
     Ok(())
 }
@@ -131,25 +202,39 @@ async fn logout_current_drive(onedrive: &OneDriveClient, message: TelegramMessag
     Ok(())
 }
 
-async fn set_drive(
-    onedrive: &OneDriveClient,
-    message: TelegramMessage,
-    index: usize,
-) -> Result<()> {
+async fn set_drive(state: &AppState, message: TelegramMessage, index: usize) -> Result<()> {
+    let onedrive = &state.onedrive;
+
+    let usernames = onedrive.get_usernames().await?;
+
+    let Some(selected_username) = usernames.get(index) else {
+        let (storage_type, name) = get_extra_storages(state)
+            .into_iter()
+            .nth(index - usernames.len())
+            .ok_or_else(|| anyhow!("account index out of range"))?;
+
+        *state.current_storage.write().await = storage_type;
+
+        let response = format!("Changed storage to\n{}", name);
+        message.respond(response.as_str()).await.context(response)?;
+
+        return Ok(());
+    };
+
     let current_username = onedrive
         .get_current_username()
         .await?
         .ok_or_else(|| anyhow!("no onedrive account is logged in"))?;
 
-    let usernames = onedrive.get_usernames().await?;
-
-    let selected_username = usernames
-        .get(index)
-        .ok_or_else(|| anyhow!("account index out of range"))?;
-
     onedrive.change_account(selected_username).await?;
 
-    if current_username == *selected_username {
+    let was_onedrive = state.current_storage_type().await == StorageType::OneDrive;
+    *state.current_storage.write().await = StorageType::OneDrive;
+
+    if !was_onedrive {
+        let response = format!("Changed storage to\n{}", selected_username);
+        message.respond(response.as_str()).await.context(response)?;
+    } else if current_username == *selected_username {
         let response = "Same account, nothing to change.";
         message.respond(response).await.context(response)?;
     } else {
@@ -193,3 +278,47 @@ async fn logout_drive(
 
     Ok(())
 }
+
+async fn show_drive_location(onedrive: &OneDriveClient, message: TelegramMessage) -> Result<()> {
+    let current_username = onedrive
+        .get_current_username()
+        .await?
+        .ok_or_else(|| anyhow!("no onedrive account is logged in"))?;
+
+    let response = onedrive.get_drive_info().await.map_or_else(
+        || format!("{} uploads to its personal drive.", current_username),
+        |drive| {
+            format!(
+                "{} uploads to drive {}
+
+Drive id: {}",
+                current_username, drive.name, drive.id
+            )
+        },
+    );
+    message.respond(response.as_str()).await.context(response)?;
+
+    Ok(())
+}
+
+async fn set_drive_location(
+    onedrive: &OneDriveClient,
+    message: TelegramMessage,
+    drive: Option<DriveInfo>,
+) -> Result<()> {
+    let current_username = onedrive
+        .get_current_username()
+        .await?
+        .ok_or_else(|| anyhow!("no onedrive account is logged in"))?;
+
+    let response = drive.as_ref().map_or_else(
+        || format!("{} now uploads to its personal drive.", current_username),
+        |drive| format!("{} now uploads to drive {}", current_username, drive.name),
+    );
+
+    onedrive.set_drive(drive).await?;
+
+    message.respond(response.as_str()).await.context(response)?;
+
+    Ok(())
+}
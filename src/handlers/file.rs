@@ -5,33 +5,34 @@
 :license: MIT, see LICENSE for more details.
 */
 
-use std::sync::atomic::Ordering;
-
-use super::utils::upload::upload_thumb;
+use super::utils::{
+    preflight::{check_drive_space, check_user_limits},
+    upload::upload_thumb,
+};
 use crate::{
-    handlers::utils::{get_tg_file_size, message::format_message_link, preprocess_tg_file_name},
-    message::{ChatEntity, TelegramMessage},
+    handlers::utils::{
+        get_tg_file_size, get_upload_root_path, message::format_message_link,
+        preprocess_tg_file_name,
+    },
+    listener::{with_task_buttons, TaskButtons},
+    message::TelegramMessage,
     state::AppState,
+    storage::{StorageBackend, UploadTarget},
     tasker::{CmdType, InsertTask},
 };
 use anyhow::{anyhow, Context, Result};
 use grammers_client::{types::Media, InputMessage};
-use proc_macros::{check_in_group, check_od_login, check_senders, check_tg_login};
+use proc_macros::{check_chat_type, check_storage_login, check_tg_login, require_role};
 
-#[check_od_login]
+#[check_storage_login]
 #[check_tg_login]
-#[check_senders]
-#[check_in_group]
+#[require_role(uploader)]
+#[check_chat_type]
 pub async fn handler(message: TelegramMessage, state: AppState) -> Result<()> {
-    let telegram_user = &state.telegram_user;
-    let onedrive = &state.onedrive;
-    let task_session = &state.task_session;
+    let chat_user = state.get_chat_user(message.chat()).await?;
+    let chat_client = state.chat_client(&chat_user.pack());
 
-    let chat_user = telegram_user
-        .get_chat(&ChatEntity::from(message.chat()))
-        .await?;
-
-    let message_user = telegram_user.get_message(&chat_user, message.id()).await?;
+    let message_user = chat_client.get_message(&chat_user, message.id()).await?;
 
     let media = message_user
         .media()
@@ -41,6 +42,13 @@ pub async fn handler(message: TelegramMessage, state: AppState) -> Result<()> {
 
     let total_length = get_tg_file_size(&media);
 
+    let storage = state.current_storage_type().await;
+
+    let user_id = message.sender_user_id();
+
+    check_user_limits(&state, user_id, total_length).await?;
+    check_drive_space(&state, &storage, total_length).await?;
+
     let message_id = message.id();
 
     let cmd_type = match media {
@@ -51,9 +59,9 @@ pub async fn handler(message: TelegramMessage, state: AppState) -> Result<()> {
     };
 
     let uploaded = match media {
-        Media::Photo(file) => upload_thumb(state.clone(), file.thumbs()).await?,
-        Media::Document(file) => upload_thumb(state.clone(), file.thumbs()).await?,
-        Media::Sticker(file) => upload_thumb(state.clone(), file.document.thumbs()).await?,
+        Media::Photo(file) => upload_thumb(&state, chat_client, file.thumbs()).await?,
+        Media::Document(file) => upload_thumb(&state, chat_client, file.thumbs()).await?,
+        Media::Sticker(file) => upload_thumb(&state, chat_client, file.document.thumbs()).await?,
         _ => Err(anyhow!(
             "media type is not one of photo, document and sticker",
         ))?,
@@ -62,46 +70,54 @@ pub async fn handler(message: TelegramMessage, state: AppState) -> Result<()> {
     // in case if cancellation happens before inserting the task
     let _aborters = state.task_session.task_aborters.lock().await;
 
-    let response = format_message_link(chat_user.id(), message_id, &filename);
+    let response = format_message_link(&chat_user.pack(), message_id, &filename);
     let message_indicator_id = match uploaded {
         Some(uploaded) => message
-            .respond(InputMessage::html(&response).photo(uploaded))
+            .respond(with_task_buttons(
+                InputMessage::html(&response).photo(uploaded),
+                TaskButtons::Running,
+            ))
             .await
             .context("message with thumb")
             .context(response)?
             .id(),
         None => message
-            .respond(InputMessage::html(&response))
+            .respond(with_task_buttons(
+                InputMessage::html(&response),
+                TaskButtons::Running,
+            ))
             .await
             .context("message without thumn")
             .context(response)?
             .id(),
     };
 
-    let root_path = onedrive.get_root_path(true).await?;
+    let root_path =
+        get_upload_root_path(&state, &storage, message.chat().id(), message.topic_id()).await?;
 
-    let (upload_session, upload_session_meta) = onedrive
-        .multipart_upload_session_builder(&root_path, &filename)
-        .await?;
+    let settings = state.settings.get().await;
 
-    // all task should be new, so this should always be 0
-    let current_length = upload_session_meta
-        .next_expected_ranges
-        .first()
-        .map_or(0, |range| range.start);
+    let UploadTarget {
+        upload_url,
+        current_length,
+    } = state
+        .storage(&storage)?
+        .create_upload_session(&root_path, &filename, total_length, settings.conflict)
+        .await?;
 
     let chat_bot_hex = message.chat().pack().to_hex();
     let chat_user_hex = chat_user.pack().to_hex();
 
-    let auto_delete = state.should_auto_delete.load(Ordering::Acquire);
+    let auto_delete = settings.auto_delete;
 
-    task_session
+    state
         .insert_task(InsertTask {
             cmd_type,
             filename: filename.clone(),
             root_path,
             url: None,
-            upload_url: upload_session.upload_url().to_string(),
+            storage,
+            upload_url,
             current_length,
             total_length,
             chat_id: chat_user.id(),
@@ -111,7 +127,9 @@ pub async fn handler(message: TelegramMessage, state: AppState) -> Result<()> {
             message_id,
             message_indicator_id,
             message_origin_id: None,
+            topic_id: message.topic_id(),
             auto_delete,
+            user_id,
         })
         .await?;
 
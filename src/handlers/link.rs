@@ -5,35 +5,49 @@
 :license: MIT, see LICENSE for more details.
 */
 
-use std::sync::atomic::Ordering;
-
-use super::utils::{message::get_message_from_link, upload::upload_thumb};
+use super::utils::{
+    message::get_message_from_link,
+    preflight::{check_drive_space, check_user_limits},
+    upload::upload_thumb,
+};
 use crate::{
-    handlers::utils::{get_tg_file_size, message::format_message_link, preprocess_tg_file_name},
-    message::{ChatEntity, TelegramMessage},
+    handlers::utils::{
+        get_tg_file_size, get_upload_root_path, message::format_message_link,
+        preprocess_tg_file_name,
+    },
+    listener::{with_task_buttons, TaskButtons},
+    message::TelegramMessage,
     state::AppState,
+    storage::{StorageBackend, UploadTarget},
     tasker::{CmdType, InsertTask},
 };
 use anyhow::{anyhow, Context, Result};
 use grammers_client::{types::Media, InputMessage};
-use proc_macros::{check_in_group, check_od_login, check_senders, check_tg_login};
+use proc_macros::{check_chat_type, check_storage_login, check_tg_login, require_role};
 
-#[check_od_login]
+#[check_storage_login]
 #[check_tg_login]
-#[check_senders]
-#[check_in_group]
+#[require_role(uploader)]
+#[check_chat_type]
 pub async fn handler(message: TelegramMessage, state: AppState) -> Result<()> {
-    let telegram_user = &state.telegram_user;
-    let onedrive = &state.onedrive;
-    let task_session = &state.task_session;
-
     let link = message.text();
 
-    let message_origin = get_message_from_link(telegram_user, &link).await?;
+    insert_link_task(message, state, &link).await?;
 
-    let chat_user = telegram_user
-        .get_chat(&ChatEntity::from(message.chat()))
-        .await?;
+    Ok(())
+}
+
+// returns id of the inserted task
+pub async fn insert_link_task(
+    message: TelegramMessage,
+    state: AppState,
+    link: &str,
+) -> Result<i64> {
+    let telegram_user = &state.telegram_user;
+
+    let message_origin = get_message_from_link(telegram_user, link).await?;
+
+    let chat_user = state.get_chat_user(message.chat()).await?;
 
     let media = message_origin
         .media()
@@ -43,6 +57,13 @@ pub async fn handler(message: TelegramMessage, state: AppState) -> Result<()> {
 
     let total_length = get_tg_file_size(&media);
 
+    let storage = state.current_storage_type().await;
+
+    let user_id = message.sender_user_id();
+
+    check_user_limits(&state, user_id, total_length).await?;
+    check_drive_space(&state, &storage, total_length).await?;
+
     let cmd_type = match media {
         Media::Photo(_) | Media::Document(_) | Media::Sticker(_) => CmdType::Link,
         _ => Err(anyhow!(
@@ -52,9 +73,9 @@ pub async fn handler(message: TelegramMessage, state: AppState) -> Result<()> {
 
     // send its file name and thumb if exists so that information of uploading successful can be showed
     let uploaded = match media {
-        Media::Photo(file) => upload_thumb(state.clone(), file.thumbs()).await?,
-        Media::Document(file) => upload_thumb(state.clone(), file.thumbs()).await?,
-        Media::Sticker(file) => upload_thumb(state.clone(), file.document.thumbs()).await?,
+        Media::Photo(file) => upload_thumb(&state, telegram_user, file.thumbs()).await?,
+        Media::Document(file) => upload_thumb(&state, telegram_user, file.thumbs()).await?,
+        Media::Sticker(file) => upload_thumb(&state, telegram_user, file.document.thumbs()).await?,
         _ => Err(anyhow!(
             "media type is not one of photo, document and sticker",
         ))?,
@@ -66,48 +87,56 @@ pub async fn handler(message: TelegramMessage, state: AppState) -> Result<()> {
     let response = format!(
         "{}\n\n{}",
         link,
-        format_message_link(chat_user.id(), message.id(), &filename)
+        format_message_link(&chat_user.pack(), message.id(), &filename)
     );
     let message_indicator_id = match uploaded {
         Some(uploaded) => message
-            .respond(InputMessage::html(&response).photo(uploaded))
+            .respond(with_task_buttons(
+                InputMessage::html(&response).photo(uploaded),
+                TaskButtons::Running,
+            ))
             .await
             .context("linked message with thumb")
             .context(response)?
             .id(),
         None => message
-            .respond(InputMessage::html(&response))
+            .respond(with_task_buttons(
+                InputMessage::html(&response),
+                TaskButtons::Running,
+            ))
             .await
             .context("linked message without thumn")
             .context(response)?
             .id(),
     };
 
-    let root_path = onedrive.get_root_path(true).await?;
+    let root_path =
+        get_upload_root_path(&state, &storage, message.chat().id(), message.topic_id()).await?;
 
-    let (upload_session, upload_session_meta) = onedrive
-        .multipart_upload_session_builder(&root_path, &filename)
-        .await?;
+    let settings = state.settings.get().await;
 
-    // all task should be new, so this should always be 0
-    let current_length = upload_session_meta
-        .next_expected_ranges
-        .first()
-        .map_or(0, |range| range.start);
+    let UploadTarget {
+        upload_url,
+        current_length,
+    } = state
+        .storage(&storage)?
+        .create_upload_session(&root_path, &filename, total_length, settings.conflict)
+        .await?;
 
     let chat_bot_hex = message.chat().pack().to_hex();
     let chat_user_hex = chat_user.pack().to_hex();
     let chat_origin_hex = message_origin.chat().pack().to_hex();
 
-    let auto_delete = state.should_auto_delete.load(Ordering::Acquire);
+    let auto_delete = settings.auto_delete;
 
-    task_session
+    let id = state
         .insert_task(InsertTask {
             cmd_type,
             filename: filename.clone(),
             root_path,
             url: None,
-            upload_url: upload_session.upload_url().to_string(),
+            storage,
+            upload_url,
             current_length,
             total_length,
             chat_id: chat_user.id(),
@@ -117,11 +146,13 @@ pub async fn handler(message: TelegramMessage, state: AppState) -> Result<()> {
             message_id: message.id(),
             message_indicator_id,
             message_origin_id: Some(message_origin.id()),
+            topic_id: message.topic_id(),
             auto_delete,
+            user_id,
         })
         .await?;
 
     tracing::info!("inserted link task: {} size: {}", filename, total_length);
 
-    Ok(())
+    Ok(id)
 }
@@ -14,21 +14,21 @@ use super::{
     },
 };
 use crate::{
-    error::ResultExt,
-    message::{ChatEntity, MessageInfo, TelegramMessage},
+    error::{ErrorExt, ResultUnwrapExt},
+    message::{MessageInfo, TelegramMessage},
     state::AppState,
     tasker::BatchAborter,
 };
 use anyhow::{anyhow, Context, Result};
 use grammers_client::InputMessage;
-use proc_macros::{check_in_group, check_od_login, check_senders, check_tg_login};
+use proc_macros::{check_chat_type, check_storage_login, check_tg_login, require_role};
 
 pub const PATTERN: &str = "/links";
 
-#[check_od_login]
+#[check_storage_login]
 #[check_tg_login]
-#[check_senders]
-#[check_in_group]
+#[require_role(uploader)]
+#[check_chat_type]
 pub async fn handler(message: TelegramMessage, state: AppState) -> Result<()> {
     let cmd = cmd_parser(message.text());
 
@@ -50,11 +50,7 @@ pub async fn handler(message: TelegramMessage, state: AppState) -> Result<()> {
             id: head_message_id,
         } = get_message_info(link_head)?;
 
-        let telegram_user = &state.telegram_user;
-
-        let chat_user = telegram_user
-            .get_chat(&ChatEntity::from(message.chat()))
-            .await?;
+        let chat_user = state.get_chat_user(message.chat()).await?;
 
         let mut batch_aborters = state.task_session.batch_aborters.lock().await;
         // /links may be in a batch
@@ -80,11 +76,12 @@ pub async fn handler(message: TelegramMessage, state: AppState) -> Result<()> {
                 let mut message_clone = message.clone();
                 message_clone.override_text(message_link.clone());
 
-                if link::handler(message_clone, state.clone()).await.is_err() {
-                    message
-                        .reply(format!("message {} not found", message_link))
+                if let Err(e) = link::handler(message_clone, state.clone()).await {
+                    e.context(format!("failed to transfer message {}", message_link))
+                        .send(message.clone())
                         .await
-                        .unwrap_or_trace();
+                        .unwrap_both()
+                        .trace();
 
                     continue;
                 }
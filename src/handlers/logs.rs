@@ -12,13 +12,13 @@ use super::{
 use crate::{client::TelegramClient, env::LOGS_PATH, message::TelegramMessage, state::AppState};
 use anyhow::{Context, Result};
 use grammers_client::InputMessage;
-use proc_macros::{check_in_group, check_senders};
+use proc_macros::{check_chat_type, require_role};
 use tokio::fs;
 
 pub const PATTERN: &str = "/logs";
 
-#[check_senders]
-#[check_in_group]
+#[require_role(admin)]
+#[check_chat_type]
 pub async fn handler(message: TelegramMessage, state: AppState) -> Result<()> {
     {
         let metadata = fs::metadata(LOGS_PATH).await;
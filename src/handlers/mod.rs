@@ -5,10 +5,12 @@
 :license: MIT, see LICENSE for more details.
 */
 
+pub mod api;
 pub mod auth;
 pub mod auto_delete;
 // pub mod batch;
 pub mod clear;
+pub mod cp;
 pub mod dir;
 mod docs;
 pub mod drive;
@@ -17,7 +19,18 @@ pub mod help;
 pub mod link;
 pub mod links;
 pub mod logs;
+pub mod mkdir;
+pub mod mv;
+pub mod progress;
+pub mod quota;
+pub mod rename;
+pub mod rm;
+pub mod s3;
+pub mod settings;
+pub mod share;
 pub mod start;
 pub mod url;
-mod utils;
+pub mod usage;
+pub mod users;
+pub mod utils;
 pub mod version;
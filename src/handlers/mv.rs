@@ -0,0 +1,48 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use super::{
+    docs::{format_help, format_unknown_command_help},
+    utils::{text::cmd_parser, validate_root_path},
+};
+use crate::{message::TelegramMessage, state::AppState};
+use anyhow::{anyhow, Context, Result};
+use grammers_client::InputMessage;
+use proc_macros::{check_chat_type, check_od_login, require_role};
+
+pub const PATTERN: &str = "/mv";
+
+#[check_od_login]
+#[require_role(uploader)]
+#[check_chat_type]
+pub async fn handler(message: TelegramMessage, state: AppState) -> Result<()> {
+    let cmd = cmd_parser(message.text());
+
+    if cmd.len() == 2 && cmd[1] == "help" {
+        // /mv help
+        message
+            .respond(InputMessage::html(format_help(PATTERN)))
+            .await
+            .context("help")?;
+    } else if cmd.len() == 3 {
+        // /mv $src $dst
+        let src = &cmd[1];
+        let dst = &cmd[2];
+
+        validate_root_path(src).await?;
+        validate_root_path(dst).await?;
+
+        state.onedrive.move_item(src, dst).await?;
+
+        let response = format!("Moved {} to {}", src, dst);
+        message.respond(response.as_str()).await.context(response)?;
+    } else {
+        return Err(anyhow!("command error")).context(format_unknown_command_help(PATTERN));
+    }
+
+    Ok(())
+}
@@ -0,0 +1,76 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use super::{
+    docs::{format_help, format_unknown_command_help},
+    utils::text::cmd_parser,
+};
+use crate::{message::TelegramMessage, state::AppState, tasker::ProgressMode};
+use anyhow::{anyhow, Context, Result};
+use grammers_client::InputMessage;
+use proc_macros::{check_chat_type, require_role};
+
+pub const PATTERN: &str = "/progress";
+
+#[require_role(uploader)]
+#[check_chat_type]
+pub async fn handler(message: TelegramMessage, state: AppState) -> Result<()> {
+    let cmd = cmd_parser(message.text());
+
+    if cmd.len() == 1 {
+        // /progress
+        show_progress_mode(message, state).await?;
+    } else if cmd.len() == 2 && cmd[1] == "help" {
+        // /progress help
+        message
+            .respond(InputMessage::html(format_help(PATTERN)))
+            .await
+            .context("help")?;
+    } else if cmd.len() == 2 {
+        // /progress $mode
+        let mode = cmd[1].parse()?;
+
+        set_progress_mode(message, state, mode).await?;
+    } else {
+        return Err(anyhow!("command error")).context(format_unknown_command_help(PATTERN));
+    }
+
+    Ok(())
+}
+
+async fn show_progress_mode(message: TelegramMessage, state: AppState) -> Result<()> {
+    let mode = state.get_progress_mode(message.chat().id()).await;
+    let progress_style = state.settings.get().await.progress_style;
+
+    let response = format!(
+        "Progress mode of this chat is {}, and progress style is {}.",
+        mode, progress_style
+    );
+    message.respond(response.as_str()).await.context(response)?;
+
+    Ok(())
+}
+
+async fn set_progress_mode(
+    message: TelegramMessage,
+    state: AppState,
+    mode: ProgressMode,
+) -> Result<()> {
+    state
+        .progress_modes
+        .lock()
+        .await
+        .insert(message.chat().id(), mode);
+
+    let response = match mode {
+        ProgressMode::Aggregate => "Progress of tasks will be shown in a progress message.",
+        ProgressMode::Indicator => "Progress of each task will be shown in its indicator.",
+    };
+    message.respond(response).await.context(response)?;
+
+    Ok(())
+}
@@ -0,0 +1,81 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use super::{
+    docs::{format_help, format_unknown_command_help},
+    utils::text::cmd_parser,
+};
+use crate::{message::TelegramMessage, state::AppState};
+use anyhow::{anyhow, Context, Result};
+use grammers_client::InputMessage;
+use proc_macros::{check_chat_type, check_od_login, require_role};
+
+pub const PATTERN: &str = "/quota";
+
+#[check_od_login]
+#[require_role(viewer)]
+#[check_chat_type]
+pub async fn handler(message: TelegramMessage, state: AppState) -> Result<()> {
+    let cmd = cmd_parser(message.text());
+
+    if cmd.len() == 1 {
+        // /quota
+        show_quota(message, state).await?;
+    } else if cmd.len() == 2 && cmd[1] == "help" {
+        // /quota help
+        message
+            .respond(InputMessage::html(format_help(PATTERN)))
+            .await
+            .context("help")?;
+    } else {
+        return Err(anyhow!("command error")).context(format_unknown_command_help(PATTERN));
+    }
+
+    Ok(())
+}
+
+async fn show_quota(message: TelegramMessage, state: AppState) -> Result<()> {
+    let onedrive = &state.onedrive;
+
+    let usernames = onedrive.get_usernames().await?;
+    let current_username = onedrive.get_current_username().await?;
+
+    if usernames.is_empty() {
+        let response = "No account found.";
+        message.respond(response).await.context(response)?;
+
+        return Ok(());
+    }
+
+    let mut response = "Storage:".to_string();
+
+    for (i, username) in usernames.iter().enumerate() {
+        response.push_str(&format!("\n\n{}. {}", i + 1, username));
+
+        if current_username.as_ref() == Some(username) {
+            response.push_str(" (current)");
+        }
+
+        match onedrive.get_user_quota(username).await {
+            Ok(quota) => response.push_str(&format!(
+                "\nUsed {:.2}GB, remaining {:.2}GB, total {:.2}GB",
+                quota.used as f64 / 1024.0 / 1024.0 / 1024.0,
+                quota.remaining as f64 / 1024.0 / 1024.0 / 1024.0,
+                quota.total as f64 / 1024.0 / 1024.0 / 1024.0
+            )),
+            Err(e) => {
+                response.push_str("\nFailed to get quota.");
+
+                tracing::warn!("failed to get quota for {}: {:?}", username, e);
+            }
+        }
+    }
+
+    message.respond(response.as_str()).await.context(response)?;
+
+    Ok(())
+}
@@ -0,0 +1,109 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use super::{
+    docs::{format_help, format_unknown_command_help},
+    utils::{text::cmd_parser, validate_root_path},
+};
+use crate::{client::onedrive::split_item_path, message::TelegramMessage, state::AppState};
+use anyhow::{anyhow, Context, Result};
+use grammers_client::InputMessage;
+use proc_macros::{check_chat_type, check_od_login, require_role};
+
+pub const PATTERN: &str = "/rm";
+
+#[check_od_login]
+#[require_role(uploader)]
+#[check_chat_type]
+pub async fn handler(message: TelegramMessage, state: AppState) -> Result<()> {
+    let cmd = cmd_parser(message.text());
+
+    if cmd.len() == 2 {
+        if cmd[1] == "help" {
+            // /rm help
+            message
+                .respond(InputMessage::html(format_help(PATTERN)))
+                .await
+                .context("help")?;
+        } else if cmd[1] == "confirm" {
+            // /rm confirm
+            confirm_remove(message, state).await?;
+        } else if cmd[1] == "cancel" {
+            // /rm cancel
+            cancel_remove(message, state).await?;
+        } else {
+            // /rm $path
+            let path = &cmd[1];
+
+            request_remove(message, state, path).await?;
+        }
+    } else {
+        return Err(anyhow!("command error")).context(format_unknown_command_help(PATTERN));
+    }
+
+    Ok(())
+}
+
+// each sender confirms their own removal, so that others in a group can't confirm or replace it
+fn removal_key(message: &TelegramMessage) -> (i64, i64) {
+    let chat_id = message.chat().id();
+
+    // channel posts have no sender, they act as the chat
+    let sender_id = message.sender().map_or(chat_id, |sender| sender.id());
+
+    (chat_id, sender_id)
+}
+
+async fn request_remove(message: TelegramMessage, state: AppState, path: &str) -> Result<()> {
+    validate_root_path(path).await?;
+    // the drive root is rejected before asking for confirmation
+    split_item_path(path)?;
+
+    state
+        .pending_removals
+        .lock()
+        .await
+        .insert(removal_key(&message), path.to_string());
+
+    let response = format!(
+        "{} will be moved to the recycle bin.\nSend /rm confirm to continue, or /rm cancel to abort.",
+        path
+    );
+    message.respond(response.as_str()).await.context(response)?;
+
+    Ok(())
+}
+
+async fn confirm_remove(message: TelegramMessage, state: AppState) -> Result<()> {
+    let path = state
+        .pending_removals
+        .lock()
+        .await
+        .remove(&removal_key(&message))
+        .ok_or_else(|| anyhow!("no item is waiting to be removed"))?;
+
+    state.onedrive.remove_item(&path).await?;
+
+    let response = format!("{} moved to the recycle bin.", path);
+    message.respond(response.as_str()).await.context(response)?;
+
+    Ok(())
+}
+
+async fn cancel_remove(message: TelegramMessage, state: AppState) -> Result<()> {
+    let path = state
+        .pending_removals
+        .lock()
+        .await
+        .remove(&removal_key(&message))
+        .ok_or_else(|| anyhow!("no item is waiting to be removed"))?;
+
+    let response = format!("Removal of {} canceled.", path);
+    message.respond(response.as_str()).await.context(response)?;
+
+    Ok(())
+}
@@ -0,0 +1,90 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use super::{
+    docs::{format_help, format_unknown_command_help},
+    utils::text::cmd_parser,
+};
+use crate::{message::TelegramMessage, state::AppState, storage::S3Target};
+use anyhow::{anyhow, Context, Result};
+use grammers_client::InputMessage;
+use proc_macros::{check_chat_type, require_role};
+
+pub const PATTERN: &str = "/s3";
+
+#[require_role(admin)]
+#[check_chat_type]
+pub async fn handler(message: TelegramMessage, state: AppState) -> Result<()> {
+    let cmd = cmd_parser(message.text());
+
+    if cmd.len() == 1 {
+        // /s3
+        show_s3_target(message, state).await?;
+    } else if cmd.len() == 2 && cmd[1] == "help" {
+        // /s3 help
+        message
+            .respond(InputMessage::html(format_help(PATTERN)))
+            .await
+            .context("help")?;
+    } else if cmd.len() == 2 && cmd[1] == "reset" {
+        // /s3 reset
+        reset_s3_target(message, state).await?;
+    } else if cmd.len() == 2 || cmd.len() == 3 {
+        // /s3 $bucket [$prefix]
+        let target = S3Target {
+            bucket: cmd[1].clone(),
+            prefix: cmd.get(2).cloned().unwrap_or_default(),
+        };
+
+        set_s3_target(message, state, target).await?;
+    } else {
+        return Err(anyhow!("command error")).context(format_unknown_command_help(PATTERN));
+    }
+
+    Ok(())
+}
+
+async fn show_s3_target(message: TelegramMessage, state: AppState) -> Result<()> {
+    let target = state.get_s3_target(message.chat().id()).await?;
+
+    let response = format!("S3 files are uploaded to {}.", target);
+    message.respond(response.as_str()).await.context(response)?;
+
+    Ok(())
+}
+
+async fn set_s3_target(message: TelegramMessage, state: AppState, target: S3Target) -> Result<()> {
+    // make sure s3 is configured
+    state.get_s3_target(message.chat().id()).await?;
+
+    if target.bucket.contains('/') {
+        return Err(anyhow!("bucket name should not contain /"));
+    }
+
+    let response = format!("S3 files will be uploaded to {}.", target);
+
+    state
+        .s3_targets
+        .lock()
+        .await
+        .insert(message.chat().id(), target);
+
+    message.respond(response.as_str()).await.context(response)?;
+
+    Ok(())
+}
+
+async fn reset_s3_target(message: TelegramMessage, state: AppState) -> Result<()> {
+    state.s3_targets.lock().await.remove(&message.chat().id());
+
+    let target = state.get_s3_target(message.chat().id()).await?;
+
+    let response = format!("S3 files will be uploaded to default {}.", target);
+    message.respond(response.as_str()).await.context(response)?;
+
+    Ok(())
+}
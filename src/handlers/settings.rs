@@ -0,0 +1,92 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use super::{
+    docs::{format_help, format_unknown_command_help},
+    utils::text::cmd_parser,
+};
+use crate::{message::TelegramMessage, settings::SETTING_KEYS, state::AppState};
+use anyhow::{anyhow, Context, Result};
+use grammers_client::InputMessage;
+use proc_macros::{check_chat_type, require_role};
+
+pub const PATTERN: &str = "/settings";
+
+#[require_role(admin)]
+#[check_chat_type]
+pub async fn handler(message: TelegramMessage, state: AppState) -> Result<()> {
+    let cmd = cmd_parser(message.text());
+
+    if cmd.len() == 1 {
+        // /settings
+        show_settings(message, state).await?;
+    } else if cmd.len() == 2 && cmd[1] == "help" {
+        // /settings help
+        message
+            .respond(InputMessage::html(format_help(PATTERN)))
+            .await
+            .context("help")?;
+    } else if cmd.len() == 3 && cmd[1] == "reset" {
+        // /settings reset $key
+        reset_setting(message, state, &cmd[2]).await?;
+    } else if cmd.len() == 3 {
+        // /settings $key $value
+        set_setting(message, state, &cmd[1], &cmd[2]).await?;
+    } else {
+        return Err(anyhow!("command error")).context(format_unknown_command_help(PATTERN));
+    }
+
+    Ok(())
+}
+
+async fn show_settings(message: TelegramMessage, state: AppState) -> Result<()> {
+    let settings = state.settings.get().await;
+
+    let mut response = "Settings:".to_string();
+
+    for (key, description) in SETTING_KEYS {
+        response += &format!(
+            "\n\n<code>{}</code>: {}\n{}",
+            key,
+            settings.get(key)?,
+            description
+        );
+    }
+
+    message
+        .respond(InputMessage::html(&response))
+        .await
+        .context(response)?;
+
+    Ok(())
+}
+
+async fn set_setting(
+    message: TelegramMessage,
+    state: AppState,
+    key: &str,
+    value: &str,
+) -> Result<()> {
+    state.settings.set(key, value).await?;
+    // more tasks may be allowed to run now
+    state.task_session.notify_tasker();
+
+    let response = format!("{} set to {}", key, value);
+    message.respond(response.as_str()).await.context(response)?;
+
+    Ok(())
+}
+
+async fn reset_setting(message: TelegramMessage, state: AppState, key: &str) -> Result<()> {
+    state.settings.reset(key).await?;
+    state.task_session.notify_tasker();
+
+    let response = format!("{} reset to {}", key, state.settings.get().await.get(key)?);
+    message.respond(response.as_str()).await.context(response)?;
+
+    Ok(())
+}
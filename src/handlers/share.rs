@@ -0,0 +1,145 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use super::{
+    docs::{format_help, format_unknown_command_help},
+    utils::{text::cmd_parser, validate_root_path},
+};
+use crate::{client::onedrive::share::ShareOption, message::TelegramMessage, state::AppState};
+use anyhow::{anyhow, Context, Result};
+use grammers_client::InputMessage;
+use proc_macros::{check_chat_type, check_od_login, require_role};
+
+pub const PATTERN: &str = "/share";
+
+#[check_od_login]
+#[require_role(uploader)]
+#[check_chat_type]
+pub async fn handler(message: TelegramMessage, state: AppState) -> Result<()> {
+    let cmd = cmd_parser(message.text());
+
+    if cmd.len() == 1 {
+        // /share
+        show_share_option(message, state).await?;
+    } else if cmd.len() == 2 && cmd[1] == "help" {
+        // /share help
+        message
+            .respond(InputMessage::html(format_help(PATTERN)))
+            .await
+            .context("help")?;
+    } else if cmd.len() == 2 && cmd[1] == "off" {
+        // /share off
+        disable_share(message, state).await?;
+    } else if cmd[1] == "on" && cmd.len() <= 5 {
+        // /share on [$type] [$scope] [$days]
+        let option = parse_share_option(&cmd[2..])?;
+
+        enable_share(message, state, option).await?;
+    } else if cmd.len() == 2 {
+        // /share $path
+        let path = &cmd[1];
+
+        share_item(message, state, path).await?;
+    } else {
+        return Err(anyhow!("command error")).context(format_unknown_command_help(PATTERN));
+    }
+
+    Ok(())
+}
+
+fn parse_share_option(args: &[String]) -> Result<ShareOption> {
+    let mut option = ShareOption::default();
+
+    if let Some(link_type) = args.first() {
+        option.link_type = link_type.parse()?;
+    }
+
+    if let Some(scope) = args.get(1) {
+        option.scope = scope.parse()?;
+    }
+
+    if let Some(days) = args.get(2) {
+        let days = days
+            .parse::<u32>()
+            .context("expiration days should be positive integer")?;
+
+        if days == 0 {
+            return Err(anyhow!("expiration days should be positive integer"));
+        }
+
+        option.expiration_days = Some(days);
+    }
+
+    Ok(option)
+}
+
+async fn show_share_option(message: TelegramMessage, state: AppState) -> Result<()> {
+    let share_options = state.share_options.lock().await;
+
+    let response = share_options.get(&message.chat().id()).map_or_else(
+        || "Share link is disabled.".to_string(),
+        |option| format!("Share link is enabled with {}.", option),
+    );
+    drop(share_options);
+
+    message.respond(response.as_str()).await.context(response)?;
+
+    Ok(())
+}
+
+async fn enable_share(
+    message: TelegramMessage,
+    state: AppState,
+    option: ShareOption,
+) -> Result<()> {
+    let response = format!("Share link enabled with {}.", option);
+
+    state
+        .share_options
+        .lock()
+        .await
+        .insert(message.chat().id(), option);
+
+    message.respond(response.as_str()).await.context(response)?;
+
+    Ok(())
+}
+
+async fn disable_share(message: TelegramMessage, state: AppState) -> Result<()> {
+    state
+        .share_options
+        .lock()
+        .await
+        .remove(&message.chat().id());
+
+    let response = "Share link disabled.";
+    message.respond(response).await.context(response)?;
+
+    Ok(())
+}
+
+async fn share_item(message: TelegramMessage, state: AppState, path: &str) -> Result<()> {
+    validate_root_path(path).await?;
+
+    let option = state
+        .share_options
+        .lock()
+        .await
+        .get(&message.chat().id())
+        .cloned()
+        .unwrap_or_default();
+
+    let link = state
+        .onedrive
+        .create_share_link(None, path, &option)
+        .await?;
+
+    let response = format!("Share link of {}:\n{}", path, link);
+    message.respond(response.as_str()).await.context(response)?;
+
+    Ok(())
+}
@@ -5,33 +5,34 @@
 :license: MIT, see LICENSE for more details.
 */
 
-use std::sync::atomic::Ordering;
-
 use super::{
     docs::{format_help, format_unknown_command_help},
     utils::{
-        get_filename,
+        get_filename, get_upload_root_path,
+        preflight::{check_drive_space, check_user_limits},
         text::{cmd_parser, TextExt},
     },
 };
 use crate::{
     handlers::utils::message::format_message_link,
-    message::{ChatEntity, TelegramMessage},
+    listener::{with_task_buttons, TaskButtons},
+    message::TelegramMessage,
     state::AppState,
+    storage::{StorageBackend, UploadTarget},
     tasker::{CmdType, InsertTask},
     utils::get_http_client,
 };
 use anyhow::{anyhow, Context, Result};
 use grammers_client::InputMessage;
-use proc_macros::{check_in_group, check_od_login, check_senders, check_tg_login};
+use proc_macros::{check_chat_type, check_storage_login, check_tg_login, require_role};
 use reqwest::header;
 
 pub const PATTERN: &str = "/url";
 
-#[check_od_login]
+#[check_storage_login]
 #[check_tg_login]
-#[check_senders]
-#[check_in_group]
+#[require_role(uploader)]
+#[check_chat_type]
 pub async fn handler(message: TelegramMessage, state: AppState) -> Result<()> {
     let cmd = cmd_parser(message.text());
 
@@ -46,102 +47,118 @@ pub async fn handler(message: TelegramMessage, state: AppState) -> Result<()> {
             Ok(())
         } else {
             // /url $url
-            let telegram_user = &state.telegram_user;
-            let onedrive = &state.onedrive;
-            let task_session = &state.task_session;
-
-            let url = cmd[1].url_encode();
-
-            if url.starts_with("http://") || url.starts_with("https://") {
-                let http_client = get_http_client()?;
-
-                let response = http_client
-                    .head(&url)
-                    .send()
-                    .await
-                    .context("failed to send head request for /url")?;
-
-                let filename = get_filename(
-                    response.url().as_ref(),
-                    &response,
-                    &onedrive.get_root_path(false).await?,
-                )?;
-
-                let total_length = match response.headers().get(header::CONTENT_LENGTH) {
-                    Some(content_length) => content_length
-                        .to_str()
-                        .context("header Content-Length has invisible ASCII chars")?
-                        .parse::<u64>()
-                        .context("failed to parse header Content-Length to u64")?,
-                    None => return Err(anyhow!(
-                        "Content-Length not found in response headers.\nStatus code:\n{}\nResponse headers:\n{:#?}",
-                        response.status(),
-                        response.headers()
-                    )),
-                };
-
-                let chat_user = telegram_user
-                    .get_chat(&ChatEntity::from(message.chat()))
-                    .await?;
-
-                let response = format!(
-                    "{}\n\n{}",
-                    url,
-                    format_message_link(chat_user.id(), message.id(), &filename)
-                );
-                let message_indicator_id = message
-                    .respond(InputMessage::html(&response))
-                    .await
-                    .context(response)?
-                    .id();
-
-                let root_path = onedrive.get_root_path(true).await?;
-
-                let (upload_session, upload_session_meta) = onedrive
-                    .multipart_upload_session_builder(&root_path, &filename)
-                    .await?;
-
-                let current_length = upload_session_meta
-                    .next_expected_ranges
-                    .first()
-                    .map_or(0, |range| range.start);
-
-                let chat_bot_hex = message.chat().pack().to_hex();
-                let chat_user_hex = chat_user.pack().to_hex();
-
-                let auto_delete = state.should_auto_delete.load(Ordering::Acquire);
-
-                // in case if cancellation happens before inserting the task
-                let _aborters = state.task_session.task_aborters.lock().await;
-
-                task_session
-                    .insert_task(InsertTask {
-                        cmd_type: CmdType::Url,
-                        filename: filename.clone(),
-                        root_path,
-                        url: Some(url),
-                        upload_url: upload_session.upload_url().to_string(),
-                        current_length,
-                        total_length,
-                        chat_id: message.chat().id(),
-                        chat_bot_hex,
-                        chat_user_hex,
-                        chat_origin_hex: None,
-                        message_id: message.id(),
-                        message_indicator_id,
-                        message_origin_id: None,
-                        auto_delete,
-                    })
-                    .await?;
-
-                tracing::info!("inserted url task: {} size: {}", filename, total_length);
-
-                Ok(())
-            } else {
-                Err(anyhow!("not an http url"))
-            }
+            insert_url_task(message, state, &cmd[1]).await?;
+
+            Ok(())
         }
     } else {
         Err(anyhow!(format_unknown_command_help(PATTERN)))
     }
 }
+
+// returns id of the inserted task
+pub async fn insert_url_task(message: TelegramMessage, state: AppState, url: &str) -> Result<i64> {
+    let url = url.url_encode();
+
+    if !url.starts_with("http://") && !url.starts_with("https://") {
+        return Err(anyhow!("not an http url"));
+    }
+
+    let storage = state.current_storage_type().await;
+
+    let http_client = get_http_client()?;
+
+    let response = http_client
+        .head(&url)
+        .send()
+        .await
+        .context("failed to send head request for /url")?;
+
+    let filename = get_filename(
+        response.url().as_ref(),
+        &response,
+        &state.get_root_path(&storage, false).await?,
+    )?;
+
+    let total_length = match response.headers().get(header::CONTENT_LENGTH) {
+        Some(content_length) => content_length
+            .to_str()
+            .context("header Content-Length has invisible ASCII chars")?
+            .parse::<u64>()
+            .context("failed to parse header Content-Length to u64")?,
+        None => return Err(anyhow!(
+            "Content-Length not found in response headers.\nStatus code:\n{}\nResponse headers:\n{:#?}",
+            response.status(),
+            response.headers()
+        )),
+    };
+
+    let user_id = message.sender_user_id();
+
+    check_user_limits(&state, user_id, total_length).await?;
+    check_drive_space(&state, &storage, total_length).await?;
+
+    let chat_user = state.get_chat_user(message.chat()).await?;
+
+    let response = format!(
+        "{}\n\n{}",
+        url,
+        format_message_link(&chat_user.pack(), message.id(), &filename)
+    );
+    let message_indicator_id = message
+        .respond(with_task_buttons(
+            InputMessage::html(&response),
+            TaskButtons::Running,
+        ))
+        .await
+        .context(response)?
+        .id();
+
+    let root_path =
+        get_upload_root_path(&state, &storage, message.chat().id(), message.topic_id()).await?;
+
+    let settings = state.settings.get().await;
+
+    let UploadTarget {
+        upload_url,
+        current_length,
+    } = state
+        .storage(&storage)?
+        .create_upload_session(&root_path, &filename, total_length, settings.conflict)
+        .await?;
+
+    let chat_bot_hex = message.chat().pack().to_hex();
+    let chat_user_hex = chat_user.pack().to_hex();
+
+    let auto_delete = settings.auto_delete;
+
+    // in case if cancellation happens before inserting the task
+    let _aborters = state.task_session.task_aborters.lock().await;
+
+    let id = state
+        .insert_task(InsertTask {
+            cmd_type: CmdType::Url,
+            filename: filename.clone(),
+            root_path,
+            url: Some(url),
+            storage,
+            upload_url,
+            current_length,
+            total_length,
+            chat_id: message.chat().id(),
+            chat_bot_hex,
+            chat_user_hex,
+            chat_origin_hex: None,
+            message_id: message.id(),
+            message_indicator_id,
+            message_origin_id: None,
+            topic_id: message.topic_id(),
+            auto_delete,
+            user_id,
+        })
+        .await?;
+
+    tracing::info!("inserted url task: {} size: {}", filename, total_length);
+
+    Ok(id)
+}
@@ -0,0 +1,178 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use super::{
+    docs::{format_help, format_unknown_command_help},
+    utils::text::cmd_parser,
+};
+use crate::{
+    acl::{check_role, Role},
+    message::TelegramMessage,
+    state::AppState,
+    tasker::TaskStatus,
+    usage::{
+        day_period, format_bytes, month_period, parse_bytes, LimitKind, Limits, DEFAULT_USER_ID,
+    },
+};
+use anyhow::{anyhow, Context, Result};
+use grammers_client::InputMessage;
+use proc_macros::{check_chat_type, require_role};
+use std::collections::{BTreeMap, BTreeSet};
+
+pub const PATTERN: &str = "/usage";
+
+#[require_role(viewer)]
+#[check_chat_type]
+pub async fn handler(message: TelegramMessage, state: AppState) -> Result<()> {
+    let cmd = cmd_parser(message.text());
+
+    if cmd.len() == 1 {
+        // /usage
+        show_usage(message, state).await?;
+    } else if cmd.len() == 2 && cmd[1] == "help" {
+        // /usage help
+        message
+            .respond(InputMessage::html(format_help(PATTERN)))
+            .await
+            .context("help")?;
+    } else if cmd.len() == 5 && cmd[1] == "limit" {
+        // /usage limit $id $kind $value
+        // /usage limit default $kind $value
+        if !check_role(&message, &state, Role::Admin).await? {
+            return Ok(());
+        }
+
+        let user_id = if cmd[2] == "default" {
+            DEFAULT_USER_ID
+        } else {
+            cmd[2]
+                .parse()
+                .map_err(|_| anyhow!("user id should be a number, like 123456789"))?
+        };
+        let kind = cmd[3].parse()?;
+
+        set_limit(message, state, user_id, kind, &cmd[4]).await?;
+    } else {
+        return Err(anyhow!("command error")).context(format_unknown_command_help(PATTERN));
+    }
+
+    Ok(())
+}
+
+async fn show_usage(message: TelegramMessage, state: AppState) -> Result<()> {
+    let usage_session = &state.usage_session;
+
+    let day_usage = usage_session
+        .get_period_records(&day_period())
+        .await?
+        .into_iter()
+        .map(|record| (record.user_id, record.bytes.max(0) as u64))
+        .collect::<BTreeMap<_, _>>();
+    let month_usage = usage_session
+        .get_period_records(&month_period())
+        .await?
+        .into_iter()
+        .map(|record| (record.user_id, record.bytes.max(0) as u64))
+        .collect::<BTreeMap<_, _>>();
+
+    // users who transferred this month or have their own limits
+    let user_ids = month_usage
+        .keys()
+        .copied()
+        .chain(
+            usage_session
+                .get_limit_entries()
+                .await?
+                .into_iter()
+                .map(|entry| entry.user_id)
+                .filter(|user_id| *user_id != DEFAULT_USER_ID),
+        )
+        .collect::<BTreeSet<_>>();
+
+    let default_limits = usage_session.get_limits(DEFAULT_USER_ID).await?;
+
+    let mut response = format!(
+        "Usage, days and months are in UTC.\n\nDefault limits: {}",
+        format_limits(&default_limits)
+    );
+
+    if user_ids.is_empty() {
+        response += "\n\nNo usage this month.";
+    }
+
+    for user_id in user_ids {
+        let unfinished_tasks = state
+            .task_session
+            .get_user_unfinished_tasks(user_id)
+            .await?;
+        let running_number = unfinished_tasks
+            .iter()
+            .filter(|task| matches!(task.status, TaskStatus::Fetched | TaskStatus::Started))
+            .count();
+
+        let limits = usage_session.get_limits(user_id).await?;
+
+        response += &format!(
+            "\n\n<code>{}</code>\nToday {}, this month {}, {} running, {} unfinished\nLimits: {}",
+            user_id,
+            format_bytes(day_usage.get(&user_id).copied().unwrap_or_default()),
+            format_bytes(month_usage.get(&user_id).copied().unwrap_or_default()),
+            running_number,
+            unfinished_tasks.len(),
+            format_limits(&limits)
+        );
+    }
+
+    message
+        .respond(InputMessage::html(&response))
+        .await
+        .context(response)?;
+
+    Ok(())
+}
+
+async fn set_limit(
+    message: TelegramMessage,
+    state: AppState,
+    user_id: i64,
+    kind: LimitKind,
+    value: &str,
+) -> Result<()> {
+    let limit = match value {
+        "none" => None,
+        _ if kind.is_bytes() => Some(parse_bytes(value)?),
+        _ => Some(
+            value
+                .parse()
+                .map_err(|_| anyhow!("{} limit should be a number", kind))?,
+        ),
+    };
+
+    state.usage_session.set_limit(user_id, kind, limit).await?;
+
+    let target = if user_id == DEFAULT_USER_ID {
+        "everyone".to_string()
+    } else {
+        format!("user {}", user_id)
+    };
+
+    let response = match limit {
+        Some(_) => format!("The {} limit of {} is set to {}.", kind, target, value),
+        None => format!("The {} limit of {} is removed.", kind, target),
+    };
+    message.respond(response.as_str()).await.context(response)?;
+
+    Ok(())
+}
+
+fn format_limits(limits: &Limits) -> String {
+    if limits.is_empty() {
+        "none".to_string()
+    } else {
+        limits.to_string()
+    }
+}
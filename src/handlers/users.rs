@@ -0,0 +1,167 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use super::{
+    docs::{format_help, format_unknown_command_help},
+    utils::text::cmd_parser,
+};
+use crate::{
+    acl::{self, Role},
+    env::ENV,
+    message::TelegramMessage,
+    state::AppState,
+};
+use anyhow::{anyhow, Context, Result};
+use grammers_client::InputMessage;
+use proc_macros::{check_chat_type, require_role};
+
+pub const PATTERN: &str = "/users";
+
+#[require_role(admin)]
+#[check_chat_type]
+pub async fn handler(message: TelegramMessage, state: AppState) -> Result<()> {
+    let cmd = cmd_parser(message.text());
+
+    if cmd.len() == 1 {
+        // /users
+        show_users(message, state).await?;
+    } else if cmd.len() == 2 && cmd[1] == "help" {
+        // /users help
+        message
+            .respond(InputMessage::html(format_help(PATTERN)))
+            .await
+            .context("help")?;
+    } else if cmd.len() == 3 && cmd[1] == "add" {
+        // /users add $role, replying to a message of the user
+        let user_id = get_replied_user_id(&message, &state).await?;
+        let role = cmd[2].parse()?;
+
+        add_user(message, state, user_id, role).await?;
+    } else if cmd.len() == 4 && cmd[1] == "add" {
+        // /users add $id $role
+        let user_id = parse_user_id(&cmd[2])?;
+        let role = cmd[3].parse()?;
+
+        add_user(message, state, user_id, role).await?;
+    } else if cmd.len() == 3 && cmd[1] == "remove" {
+        // /users remove $id
+        let user_id = parse_user_id(&cmd[2])?;
+
+        remove_user(message, state, user_id).await?;
+    } else {
+        return Err(anyhow!("command error")).context(format_unknown_command_help(PATTERN));
+    }
+
+    Ok(())
+}
+
+async fn show_users(message: TelegramMessage, state: AppState) -> Result<()> {
+    let env_users = &ENV.get().unwrap().telegram_user.users;
+
+    let mut response = if acl::is_open(&state).await? {
+        "No user is set, everyone in the group can use the bot.".to_string()
+    } else {
+        "Users:".to_string()
+    };
+
+    for user in state.acl_session.get_users().await? {
+        response += &format!("\n<code>{}</code>: {}", user.id, user.role);
+    }
+
+    if !env_users.is_empty() {
+        response += &format!("\n\nAdmins in tg_user_name: {}", env_users.join(", "));
+    }
+
+    message
+        .respond(InputMessage::html(&response))
+        .await
+        .context(response)?;
+
+    Ok(())
+}
+
+async fn add_user(
+    message: TelegramMessage,
+    state: AppState,
+    user_id: i64,
+    role: Role,
+) -> Result<()> {
+    let sender_id = message.sender_user_id();
+
+    if sender_id == Some(user_id) && role != Role::Admin {
+        return Err(anyhow!("you can't change your own role"));
+    }
+
+    if role != Role::Admin
+        && state.acl_session.get_role(user_id).await? == Some(Role::Admin)
+        && !acl::has_other_admin(&state, user_id).await?
+    {
+        return Err(anyhow!("you can't change the role of the last admin"));
+    }
+
+    let mut response = format!("User {} is set as {}.", user_id, role);
+
+    // otherwise the sender would lose access once the first user is added
+    if acl::is_open(&state).await? {
+        if let Some(sender_id) = sender_id {
+            if sender_id != user_id {
+                state.acl_session.set_user(sender_id, Role::Admin).await?;
+
+                response += &format!("\nYou ({}) are set as admin.", sender_id);
+            }
+        }
+    }
+
+    state.acl_session.set_user(user_id, role).await?;
+
+    message.respond(response.as_str()).await.context(response)?;
+
+    Ok(())
+}
+
+async fn remove_user(message: TelegramMessage, state: AppState, user_id: i64) -> Result<()> {
+    if message.sender_user_id() == Some(user_id) {
+        return Err(anyhow!("you can't remove yourself"));
+    }
+
+    if !acl::has_other_admin(&state, user_id).await? {
+        return Err(anyhow!(
+            "you can't remove the last admin, add another admin or set tg_user_name first"
+        ));
+    }
+
+    let response = if state.acl_session.delete_user(user_id).await? {
+        format!("User {} is removed.", user_id)
+    } else {
+        format!("User {} is not in the list.", user_id)
+    };
+    message.respond(response.as_str()).await.context(response)?;
+
+    Ok(())
+}
+
+async fn get_replied_user_id(message: &TelegramMessage, state: &AppState) -> Result<i64> {
+    let replied_message_id = message
+        .raw
+        .reply_to_message_id()
+        .ok_or_else(|| anyhow!("reply to a message of the user, or specify the user id"))?;
+
+    let replied_message = state
+        .telegram_bot
+        .get_message(message.chat(), replied_message_id)
+        .await?;
+
+    replied_message
+        .sender_user_id()
+        .ok_or_else(|| anyhow!("the replied message is not sent by a user"))
+}
+
+fn parse_user_id(value: &str) -> Result<i64> {
+    value
+        .parse()
+        .map_err(|_| anyhow!("user id should be a number, like 123456789"))
+}
@@ -10,6 +10,7 @@ use crate::{
     message::{ChatEntity, MessageInfo, TelegramMessage},
 };
 use anyhow::{anyhow, Context, Result};
+use grammers_client::types::PackedChat;
 
 pub fn get_message_info(link: &str) -> Result<MessageInfo> {
     let (message_info, is_private) =
@@ -78,9 +79,14 @@ pub fn get_message_link(chat_entity: &ChatEntity, id: i32) -> String {
     }
 }
 
-pub fn format_message_link(chat_id: i64, message_id: i32, filename: &str) -> String {
+// messages in private chats can't be linked to
+pub fn format_message_link(chat: &PackedChat, message_id: i32, filename: &str) -> String {
+    if chat.is_user() {
+        return filename.to_string();
+    }
+
     format!(
         "<a href=\"https://t.me/c/{}/{}\">{}</a>",
-        chat_id, message_id, filename
+        chat.id, message_id, filename
     )
 }
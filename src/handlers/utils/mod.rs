@@ -6,6 +6,7 @@
 */
 
 pub mod message;
+pub mod preflight;
 pub mod text;
 pub mod upload;
 pub mod zip;
@@ -13,6 +14,8 @@ pub mod zip;
 use crate::{
     client::onedrive::invalid_name::{INVALID_COMPONENT, INVALID_NAME, INVALID_NAME_PREFIX},
     error::ResultExt,
+    state::AppState,
+    storage::StorageType,
     utils::{get_current_timestamp, get_ext},
 };
 use anyhow::{anyhow, Context, Result};
@@ -218,6 +221,41 @@ pub async fn validate_root_path(root_path: &str) -> Result<()> {
     Ok(())
 }
 
+// root path in the storage that new tasks of the chat are uploaded to
+pub async fn get_upload_root_path(
+    state: &AppState,
+    storage_type: &StorageType,
+    chat_id: i64,
+    topic_id: Option<i32>,
+) -> Result<String> {
+    let is_temp = state.does_temp_root_path_exist(storage_type).await?;
+    let mut root_path = state.get_root_path(storage_type, true).await?;
+
+    // a temporary directory still takes precedence over the directory of the topic
+    if let Some(topic_id) = topic_id.filter(|_| !is_temp) {
+        if let Some(topic_dir) = state.get_topic_dir(chat_id, topic_id).await {
+            root_path = join_topic_dir(&root_path, &topic_dir);
+        }
+    }
+
+    if *storage_type == StorageType::S3 {
+        let s3_target = state.get_s3_target(chat_id).await?;
+
+        return Ok(s3_target.join_root_path(&root_path));
+    }
+
+    Ok(root_path)
+}
+
+// relative topic directories are subfolders of the current directory
+pub fn join_topic_dir(root_path: &str, topic_dir: &str) -> String {
+    if topic_dir.starts_with('/') {
+        topic_dir.to_string()
+    } else {
+        format!("{}/{}", root_path.trim_end_matches('/'), topic_dir)
+    }
+}
+
 fn preprocess_url_file_name(filename: &str) -> String {
     if validate_filename(filename) {
         filename
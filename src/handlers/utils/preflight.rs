@@ -0,0 +1,123 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use crate::{
+    state::AppState,
+    storage::{StorageBackend, StorageType},
+    usage::{day_period, format_bytes, month_period},
+};
+use anyhow::{anyhow, Result};
+
+// reject the task before inserting if it can't fit in the storage
+pub async fn check_drive_space(
+    state: &AppState,
+    storage_type: &StorageType,
+    total_length: u64,
+) -> Result<()> {
+    let remaining = match state.storage(storage_type)?.get_free_space().await {
+        Ok(Some(remaining)) => remaining,
+        Ok(None) => return Ok(()),
+        Err(e) => {
+            // some drives don't report quota, don't block uploading for them
+            tracing::warn!("failed to get drive quota, skip free space check: {:?}", e);
+
+            return Ok(());
+        }
+    };
+
+    // the quota is of the current account and drive
+    let (onedrive_user, onedrive_drive_id) = state.get_onedrive_drive(storage_type).await?;
+
+    let queued_length = state
+        .task_session
+        .get_queued_length(storage_type.clone(), onedrive_user, onedrive_drive_id)
+        .await?;
+
+    let available_length = remaining.saturating_sub(queued_length);
+
+    tracing::debug!(
+        "drive remaining: {} queued: {} required: {}",
+        remaining,
+        queued_length,
+        total_length
+    );
+
+    if total_length > available_length {
+        return Err(anyhow!(
+            "Not enough space in {} storage.\nFile size {:.2}MB, remaining {:.2}MB, {:.2}MB already queued.",
+            storage_type,
+            total_length as f64 / 1024.0 / 1024.0,
+            remaining as f64 / 1024.0 / 1024.0,
+            queued_length as f64 / 1024.0 / 1024.0
+        ));
+    }
+
+    Ok(())
+}
+
+// reject the task before inserting if the sender has used up their limits
+pub async fn check_user_limits(
+    state: &AppState,
+    user_id: Option<i64>,
+    total_length: u64,
+) -> Result<()> {
+    // tasks from http api are not limited
+    let Some(user_id) = user_id else {
+        return Ok(());
+    };
+
+    let limits = state.usage_session.get_limits(user_id).await?;
+
+    if limits.is_empty() {
+        return Ok(());
+    }
+
+    let unfinished_tasks = state
+        .task_session
+        .get_user_unfinished_tasks(user_id)
+        .await?;
+
+    if let Some(queued_limit) = limits.queued {
+        if unfinished_tasks.len() as u64 >= queued_limit {
+            return Err(anyhow!(
+                "Too many tasks.\nYou have {} unfinished tasks, the limit is {}.",
+                unfinished_tasks.len(),
+                queued_limit
+            ));
+        }
+    }
+
+    // unfinished tasks are counted after they complete
+    let queued_length: u64 = unfinished_tasks
+        .iter()
+        .map(|task| task.total_length.max(0) as u64)
+        .sum();
+
+    for (limit, period, period_name) in [
+        (limits.daily, day_period(), "today"),
+        (limits.monthly, month_period(), "this month"),
+    ] {
+        let Some(limit) = limit else {
+            continue;
+        };
+
+        let used_length = state.usage_session.get_bytes(user_id, &period).await?;
+
+        if used_length + queued_length + total_length > limit {
+            return Err(anyhow!(
+                "Transfer limit exceeded.\nFile size {}, used {} {}, {} already queued, the limit is {}.",
+                format_bytes(total_length),
+                format_bytes(used_length),
+                period_name,
+                format_bytes(queued_length),
+                format_bytes(limit)
+            ));
+        }
+    }
+
+    Ok(())
+}
@@ -5,7 +5,7 @@
 :license: MIT, see LICENSE for more details.
 */
 
-use crate::state::AppState;
+use crate::{client::TelegramClient, state::AppState};
 use anyhow::{Context, Result};
 use grammers_client::types::{
     media::Uploaded,
@@ -13,10 +13,15 @@ use grammers_client::types::{
 };
 use std::io::Cursor;
 
-pub async fn upload_thumb(state: AppState, thumbs: Vec<PhotoSize>) -> Result<Option<Uploaded>> {
+// thumbs are downloaded by the client that got the media
+pub async fn upload_thumb(
+    state: &AppState,
+    client: &TelegramClient,
+    thumbs: Vec<PhotoSize>,
+) -> Result<Option<Uploaded>> {
     let uploaded = match thumbs.largest() {
         Some(thumb) => {
-            let mut download = state.telegram_user.iter_download(thumb);
+            let mut download = client.iter_download(thumb);
 
             let mut buffer = Vec::new();
             while let Some(chunk) = download
@@ -0,0 +1,153 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+// inline buttons attached to the indicators of tasks
+
+use crate::{
+    acl::{self, Role},
+    error::ResultExt,
+    state::AppState,
+    tasker::{cancel_task, pause_task, resume_task, retry_task, TaskStatus},
+};
+use anyhow::{Context, Result};
+use grammers_client::{button, reply_markup, types::CallbackQuery, InputMessage};
+
+const CANCEL_CALLBACK_DATA: &str = "cancel";
+const PAUSE_CALLBACK_DATA: &str = "pause";
+const RESUME_CALLBACK_DATA: &str = "resume";
+const RETRY_CALLBACK_DATA: &str = "retry";
+// telegram rejects longer answers
+const MAX_ANSWER_LEN: usize = 200;
+
+// buttons shown depend on the status of the task
+pub enum TaskButtons {
+    // waiting or uploading
+    Running,
+    Paused,
+    // failed or cancelled
+    Stopped,
+}
+
+pub fn with_task_buttons(input_message: InputMessage, buttons: TaskButtons) -> InputMessage {
+    let row = match buttons {
+        TaskButtons::Running => vec![
+            button::inline("Pause", PAUSE_CALLBACK_DATA),
+            button::inline("Cancel", CANCEL_CALLBACK_DATA),
+        ],
+        TaskButtons::Paused => vec![
+            button::inline("Resume", RESUME_CALLBACK_DATA),
+            button::inline("Cancel", CANCEL_CALLBACK_DATA),
+        ],
+        TaskButtons::Stopped => vec![button::inline("Retry", RETRY_CALLBACK_DATA)],
+    };
+
+    input_message.reply_markup(&reply_markup::inline(vec![row]))
+}
+
+pub async fn handle_callback_query(query: CallbackQuery, state: AppState) -> Result<()> {
+    let result = handle_button(&query, &state).await;
+
+    // the button keeps loading until the query is answered, so errors are answered too
+    let answer = match &result {
+        Ok(answer) => query.answer().text(*answer),
+        Err(e) => {
+            let text = e
+                .to_string()
+                .chars()
+                .take(MAX_ANSWER_LEN)
+                .collect::<String>();
+
+            query.answer().alert(text)
+        }
+    };
+
+    // the error of the button is more useful than the one of answering it
+    answer
+        .send()
+        .await
+        .context("failed to answer callback query")
+        .trace();
+
+    result?;
+
+    Ok(())
+}
+
+async fn handle_button(query: &CallbackQuery, state: &AppState) -> Result<&'static str> {
+    // same as sending the task
+    let role = acl::get_role(state, Some(query.sender().clone())).await?;
+
+    if !role.is_some_and(|role| role >= Role::Uploader) {
+        return Ok("You need the uploader role to do this.");
+    }
+
+    // the button is attached to the indicator of the task
+    let message_indicator = query
+        .load_message()
+        .await
+        .context("failed to load message of the button")?;
+
+    let chat_id = query.chat().id();
+    let data = query.data();
+
+    let Some(task) = state
+        .task_session
+        .get_task_from_message_indicator_id(chat_id, message_indicator.id())
+        .await?
+    else {
+        if data != RETRY_CALLBACK_DATA.as_bytes() {
+            return Ok("The task is already finished.");
+        }
+
+        // finished tasks are only kept in history
+        let Some(record) = state
+            .task_session
+            .get_history_from_message_indicator_id(chat_id, message_indicator.id())
+            .await
+        else {
+            return Ok("The task is too old to be retried.");
+        };
+
+        if record.status == TaskStatus::Completed {
+            return Ok("The task is already completed.");
+        }
+
+        retry_task(state.clone(), record.task.id).await?;
+
+        return Ok("Retrying.");
+    };
+
+    let answer = if data == CANCEL_CALLBACK_DATA.as_bytes() {
+        cancel_task(state.clone(), task.id).await?;
+
+        tracing::info!("task {} cancelled by button", task.filename);
+
+        "Cancelled."
+    } else if data == PAUSE_CALLBACK_DATA.as_bytes() {
+        if task.status == TaskStatus::Paused {
+            return Ok("The task is already paused.");
+        }
+
+        pause_task(state.clone(), task.id).await?;
+
+        "Paused."
+    } else if data == RESUME_CALLBACK_DATA.as_bytes() {
+        if task.status != TaskStatus::Paused {
+            return Ok("The task is not paused.");
+        }
+
+        resume_task(state.clone(), task.id).await?;
+
+        "Resumed."
+    } else if data == RETRY_CALLBACK_DATA.as_bytes() {
+        "The task is not finished yet."
+    } else {
+        "Unknown button."
+    };
+
+    Ok(answer)
+}
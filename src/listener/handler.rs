@@ -8,7 +8,7 @@
 use super::{EventType, Events};
 use crate::{
     error::{ErrorExt, ResultUnwrapExt},
-    message::{ChatEntity, TelegramMessage},
+    message::TelegramMessage,
     state::AppState,
     tasker::BatchAborter,
 };
@@ -85,19 +85,16 @@ impl<'h> Handler<'h> {
     async fn handle_batch(&self, message: TelegramMessage) -> Result<()> {
         tracing::info!("handle batch");
 
-        let telegram_user = self.state.telegram_user.clone();
+        let chat_user = self.state.get_chat_user(message.chat()).await?;
+        let chat_client = self.state.chat_client(&chat_user.pack());
 
-        let chat_user = telegram_user
-            .get_chat(&ChatEntity::from(message.chat()))
-            .await?;
-
-        let message_user = telegram_user.get_message(&chat_user, message.id()).await?;
+        let message_user = chat_client.get_message(&chat_user, message.id()).await?;
 
         let media = message_user
             .media()
             .ok_or_else(|| anyhow!("message does not contain any media"))?;
 
-        let mut download = telegram_user.iter_download(&media);
+        let mut download = chat_client.iter_download(&media);
         let mut batch_bytes = Vec::new();
         while let Some(chunk) = download
             .next()
@@ -5,20 +5,25 @@
 :license: MIT, see LICENSE for more details.
 */
 
+mod callback;
 mod events;
 mod handler;
 
 use crate::{
+    auth_server,
     client::utils::chat_from_hex,
+    env::ENV,
     error::{ErrorExt, ResultExt, ResultUnwrapExt},
     message::{ChatEntity, TelegramMessage},
     state::{AppState, State},
     tasker::Tasker,
 };
 use anyhow::{Ok, Result};
+use callback::handle_callback_query;
+pub use callback::{with_task_buttons, TaskButtons};
 use events::Events;
 pub use events::{EventType, HashMapExt};
-use grammers_client::Update;
+use grammers_client::{types::Chat, Update};
 use handler::Handler;
 use std::sync::Arc;
 
@@ -37,11 +42,23 @@ impl Listener {
     pub async fn run(self) {
         tracing::info!("listener started");
 
+        let env = ENV.get().unwrap();
+        if env.dashboard_token.is_some() || env.api_token.is_some() || env.metrics_token.is_some() {
+            auth_server::spawn_persistent(self.state.clone())
+                .await
+                .trace();
+        }
+
         let tasker = Tasker::new(self.state.clone());
         tokio::spawn(async move {
             tasker.run().await;
         });
 
+        let state = self.state.clone();
+        tokio::spawn(async move {
+            state.webhook.run().await;
+        });
+
         let state = self.state.clone();
         tokio::spawn(async move {
             loop {
@@ -68,12 +85,29 @@ impl Listener {
                 if !message_raw.outgoing() {
                     let message = TelegramMessage::new(client.clone(), message_raw);
 
+                    // progress is sent again below messages received after it
+                    if let Chat::User(user) = message.chat() {
+                        self.state
+                            .private_last_message_ids
+                            .lock()
+                            .await
+                            .insert(user.id(), message.id());
+                    }
+
                     let handler = Handler::new(&self.events, self.state.clone());
                     if let Err(e) = handler.handle_message(message.clone()).await {
                         e.send(message).await.unwrap_both().trace();
                     }
                 }
             }
+            Update::CallbackQuery(query) => {
+                let state = self.state.clone();
+
+                // cancelling may take a while, don't block other updates
+                tokio::spawn(async move {
+                    handle_callback_query(query, state).await.trace();
+                });
+            }
             Update::MessageDeleted(messages_info) => {
                 // abort the task if the related message is deleted
                 // bot can only catch deleted message immediately if it is sent by itself
@@ -5,6 +5,7 @@
 :license: MIT, see LICENSE for more details.
 */
 
+mod acl;
 mod auth_server;
 mod client;
 mod env;
@@ -12,14 +13,20 @@ mod error;
 mod handlers;
 mod listener;
 mod message;
+mod metrics;
+mod settings;
 mod state;
+mod storage;
 mod tasker;
 mod trace;
+mod usage;
 mod utils;
+mod webhook;
 
 use env::{Env, ENV};
 use handlers::{
-    auth, auto_delete, clear, dir, drive, file, help, link, links, logs, start, url, version,
+    api, auth, auto_delete, clear, cp, dir, drive, file, help, link, links, logs, mkdir, mv,
+    progress, quota, rename, rm, s3, share, start, url, usage, users, version,
 };
 use listener::{EventType, HashMapExt, Listener};
 use std::collections::HashMap;
@@ -45,9 +52,26 @@ async fn main() {
         .on(EventType::command(auth::PATTERN), auth::handler)
         .on(EventType::command(clear::PATTERN), clear::handler)
         .on(EventType::command(dir::PATTERN), dir::handler)
+        .on(EventType::command(mkdir::PATTERN), mkdir::handler)
+        .on(EventType::command(mv::PATTERN), mv::handler)
+        .on(EventType::command(cp::PATTERN), cp::handler)
+        .on(EventType::command(rm::PATTERN), rm::handler)
+        .on(EventType::command(rename::PATTERN), rename::handler)
         .on(EventType::command(drive::PATTERN), drive::handler)
+        .on(EventType::command(quota::PATTERN), quota::handler)
+        .on(EventType::command(s3::PATTERN), s3::handler)
+        .on(EventType::command(api::PATTERN), api::handler)
+        // crate::settings is the store of runtime settings
+        .on(
+            EventType::command(handlers::settings::PATTERN),
+            handlers::settings::handler,
+        )
+        .on(EventType::command(users::PATTERN), users::handler)
+        .on(EventType::command(usage::PATTERN), usage::handler)
         .on(EventType::command(url::PATTERN), url::handler)
         .on(EventType::command(links::PATTERN), links::handler)
+        .on(EventType::command(share::PATTERN), share::handler)
+        .on(EventType::command(progress::PATTERN), progress::handler)
         .on(EventType::command(version::PATTERN), version::handler)
         .on(EventType::media(), file::handler)
         .on(EventType::text(), link::handler);
@@ -7,7 +7,10 @@
 
 use crate::client::TelegramClient;
 use anyhow::Result;
-use grammers_client::types::{Chat, InputMessage, Media, Message, PackedChat};
+use grammers_client::{
+    grammers_tl_types as tl,
+    types::{Chat, InputMessage, Media, Message, PackedChat},
+};
 use std::sync::Arc;
 use tokio::sync::mpsc::Sender;
 
@@ -53,7 +56,28 @@ impl TelegramMessage {
         self.raw.sender()
     }
 
+    // none for anonymous group admins, channels and bots
+    pub fn sender_user_id(&self) -> Option<i64> {
+        match self.sender() {
+            Some(Chat::User(user)) if !user.is_bot() => Some(user.id()),
+            _ => None,
+        }
+    }
+
+    // id of the forum topic the message is in, none for the general topic and chats without topics
+    pub fn topic_id(&self) -> Option<i32> {
+        match &self.raw.raw.reply_to {
+            Some(tl::enums::MessageReplyHeader::Header(header)) if header.forum_topic => {
+                header.reply_to_top_id.or(header.reply_to_msg_id)
+            }
+            _ => None,
+        }
+    }
+
+    // kept in the topic of the message
     pub async fn respond<M: Into<InputMessage>>(&self, message: M) -> Result<Self> {
+        let message = message.into().reply_to(self.topic_id());
+
         self.client.send_message(self.chat(), message).await
     }
 
@@ -0,0 +1,294 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+// rendered in prometheus text format by the persistent server
+
+use crate::{storage::StorageType, tasker::TaskStatus};
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    sync::{
+        atomic::{AtomicI64, AtomicU64, Ordering},
+        Mutex, MutexGuard, PoisonError,
+    },
+    time::Duration,
+};
+
+pub static METRICS: Metrics = Metrics::new();
+
+// seconds
+const DURATION_BUCKETS: [f64; 9] = [
+    10.0, 30.0, 60.0, 300.0, 900.0, 1800.0, 3600.0, 7200.0, 21600.0,
+];
+
+#[derive(Default)]
+struct Histogram {
+    // not cumulative, summed up when rendering
+    buckets: [u64; DURATION_BUCKETS.len()],
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    fn observe(&mut self, value: f64) {
+        if let Some(index) = DURATION_BUCKETS.iter().position(|bound| value <= *bound) {
+            self.buckets[index] += 1;
+        }
+
+        self.sum += value;
+        self.count += 1;
+    }
+}
+
+pub enum TransferSource {
+    Telegram,
+    Url,
+}
+
+impl TransferSource {
+    const fn label(&self) -> &'static str {
+        match self {
+            Self::Telegram => "telegram",
+            Self::Url => "url",
+        }
+    }
+}
+
+pub enum RetryKind {
+    Download,
+    Upload,
+}
+
+impl RetryKind {
+    const fn label(&self) -> &'static str {
+        match self {
+            Self::Download => "download",
+            Self::Upload => "upload",
+        }
+    }
+}
+
+pub struct Metrics {
+    // source -> bytes
+    downloaded_bytes: Mutex<BTreeMap<&'static str, u64>>,
+    // storage -> bytes
+    uploaded_bytes: Mutex<BTreeMap<String, u64>>,
+    // kind -> retries
+    retries: Mutex<BTreeMap<&'static str, u64>>,
+    // final status -> durations
+    task_durations: Mutex<BTreeMap<String, Histogram>>,
+    flood_waits: AtomicU64,
+    token_refresh_failures: AtomicU64,
+    active_workers: AtomicI64,
+}
+
+impl Metrics {
+    const fn new() -> Self {
+        Self {
+            downloaded_bytes: Mutex::new(BTreeMap::new()),
+            uploaded_bytes: Mutex::new(BTreeMap::new()),
+            retries: Mutex::new(BTreeMap::new()),
+            task_durations: Mutex::new(BTreeMap::new()),
+            flood_waits: AtomicU64::new(0),
+            token_refresh_failures: AtomicU64::new(0),
+            active_workers: AtomicI64::new(0),
+        }
+    }
+
+    pub fn add_downloaded_bytes(&self, source: &TransferSource, length: usize) {
+        *lock(&self.downloaded_bytes)
+            .entry(source.label())
+            .or_default() += length as u64;
+    }
+
+    pub fn add_uploaded_bytes(&self, storage: &StorageType, length: usize) {
+        *lock(&self.uploaded_bytes)
+            .entry(storage.to_string())
+            .or_default() += length as u64;
+    }
+
+    pub fn add_retry(&self, kind: &RetryKind) {
+        *lock(&self.retries).entry(kind.label()).or_default() += 1;
+    }
+
+    pub fn observe_task_duration(&self, status: &TaskStatus, duration: Duration) {
+        lock(&self.task_durations)
+            .entry(status.to_string())
+            .or_default()
+            .observe(duration.as_secs_f64());
+    }
+
+    pub fn add_flood_wait(&self) {
+        self.flood_waits.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn add_token_refresh_failure(&self) {
+        self.token_refresh_failures.fetch_add(1, Ordering::Relaxed);
+    }
+
+    // the worker is counted until the guard is dropped
+    pub fn start_worker(&self) -> WorkerGuard<'_> {
+        self.active_workers.fetch_add(1, Ordering::Relaxed);
+
+        WorkerGuard { metrics: self }
+    }
+
+    // queue depth is counted from the task session when scraped
+    pub fn render(&self, queue_depth: &BTreeMap<String, u64>) -> String {
+        let mut output = String::new();
+
+        write_header(
+            &mut output,
+            "telegram_onedrive_tasks_queued",
+            "gauge",
+            "Tasks in the queue by status.",
+        );
+        for (status, count) in queue_depth {
+            let _ = writeln!(
+                output,
+                "telegram_onedrive_tasks_queued{{status=\"{}\"}} {}",
+                status, count
+            );
+        }
+
+        write_header(
+            &mut output,
+            "telegram_onedrive_downloaded_bytes_total",
+            "counter",
+            "Bytes downloaded from telegram or urls.",
+        );
+        for (source, bytes) in lock(&self.downloaded_bytes).iter() {
+            let _ = writeln!(
+                output,
+                "telegram_onedrive_downloaded_bytes_total{{source=\"{}\"}} {}",
+                source, bytes
+            );
+        }
+
+        write_header(
+            &mut output,
+            "telegram_onedrive_uploaded_bytes_total",
+            "counter",
+            "Bytes uploaded to storages.",
+        );
+        for (storage, bytes) in lock(&self.uploaded_bytes).iter() {
+            let _ = writeln!(
+                output,
+                "telegram_onedrive_uploaded_bytes_total{{storage=\"{}\"}} {}",
+                storage, bytes
+            );
+        }
+
+        write_header(
+            &mut output,
+            "telegram_onedrive_task_duration_seconds",
+            "histogram",
+            "Duration of finished tasks by status.",
+        );
+        for (status, histogram) in lock(&self.task_durations).iter() {
+            let mut cumulative = 0;
+
+            for (bound, count) in DURATION_BUCKETS.iter().zip(histogram.buckets) {
+                cumulative += count;
+
+                let _ = writeln!(
+                    output,
+                    "telegram_onedrive_task_duration_seconds_bucket{{status=\"{}\",le=\"{}\"}} {}",
+                    status, bound, cumulative
+                );
+            }
+
+            let _ = writeln!(
+                output,
+                "telegram_onedrive_task_duration_seconds_bucket{{status=\"{}\",le=\"+Inf\"}} {}",
+                status, histogram.count
+            );
+            let _ = writeln!(
+                output,
+                "telegram_onedrive_task_duration_seconds_sum{{status=\"{}\"}} {}",
+                status, histogram.sum
+            );
+            let _ = writeln!(
+                output,
+                "telegram_onedrive_task_duration_seconds_count{{status=\"{}\"}} {}",
+                status, histogram.count
+            );
+        }
+
+        write_header(
+            &mut output,
+            "telegram_onedrive_retries_total",
+            "counter",
+            "Retried chunk downloads and uploads.",
+        );
+        for (kind, retries) in lock(&self.retries).iter() {
+            let _ = writeln!(
+                output,
+                "telegram_onedrive_retries_total{{kind=\"{}\"}} {}",
+                kind, retries
+            );
+        }
+
+        write_header(
+            &mut output,
+            "telegram_onedrive_flood_waits_total",
+            "counter",
+            "Flood waits received when sending queued bot messages.",
+        );
+        let _ = writeln!(
+            output,
+            "telegram_onedrive_flood_waits_total {}",
+            self.flood_waits.load(Ordering::Relaxed)
+        );
+
+        write_header(
+            &mut output,
+            "telegram_onedrive_token_refresh_failures_total",
+            "counter",
+            "Failed onedrive access token refreshes.",
+        );
+        let _ = writeln!(
+            output,
+            "telegram_onedrive_token_refresh_failures_total {}",
+            self.token_refresh_failures.load(Ordering::Relaxed)
+        );
+
+        write_header(
+            &mut output,
+            "telegram_onedrive_active_workers",
+            "gauge",
+            "Task handlers running now.",
+        );
+        let _ = writeln!(
+            output,
+            "telegram_onedrive_active_workers {}",
+            self.active_workers.load(Ordering::Relaxed)
+        );
+
+        output
+    }
+}
+
+pub struct WorkerGuard<'a> {
+    metrics: &'a Metrics,
+}
+
+impl Drop for WorkerGuard<'_> {
+    fn drop(&mut self) {
+        self.metrics.active_workers.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+fn write_header(output: &mut String, name: &str, metric_type: &str, help: &str) {
+    let _ = writeln!(output, "# HELP {} {}", name, help);
+    let _ = writeln!(output, "# TYPE {} {}", name, metric_type);
+}
+
+// counters are still usable if a thread panicked while holding the lock
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
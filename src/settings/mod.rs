@@ -0,0 +1,258 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+// runtime options changed by /settings, they take precedence over env and are kept across restarts
+
+mod entries;
+mod session;
+
+use crate::{
+    env::ENV,
+    error::ResultExt,
+    storage::{ConflictBehavior, StorageType, S3_PART_SIZE},
+    tasker::ProgressStyle,
+};
+use anyhow::{anyhow, Context, Result};
+use grammers_client::client::files::MAX_CHUNK_SIZE;
+pub use session::SettingsSession;
+use tokio::sync::RwLock;
+
+// kept in the same session but not shown in /settings
+pub const TEMP_ROOT_PATH_KEY: &str = "temp_root_path";
+pub const API_CHAT_KEY: &str = "api_chat";
+// followed by chat id and topic id
+const TOPIC_DIR_KEY_PREFIX: &str = "topic_dir:";
+// followed by storage type, onedrive keeps its root path in its own session
+const ROOT_PATH_KEY_PREFIX: &str = "root_path:";
+const TEMP_ROOT_PATH_KEY_PREFIX: &str = "temp_root_path:";
+
+// onedrive requires upload parts to be multiples of 320 KiB
+const PART_SIZE_UNIT: u64 = 320;
+// onedrive rejects parts larger than 60 MiB
+const MAX_PART_SIZE: u64 = 61440;
+
+// key, description
+pub const SETTING_KEYS: [(&str, &str); 6] = [
+    (
+        "auto_delete",
+        "delete messages after tasks are done, true or false",
+    ),
+    ("worker_num", "max number of tasks handled at the same time"),
+    (
+        "url_part_size",
+        "size in KiB of parts uploaded from urls, a multiple of 320 up to 61440",
+    ),
+    (
+        "tg_part_chunks",
+        "number of 512KiB telegram chunks downloaded in parallel and uploaded as a part",
+    ),
+    (
+        "conflict",
+        "rename, replace or fail if the file already exists",
+    ),
+    ("progress_style", "detailed, compact or off"),
+];
+
+#[derive(Clone)]
+pub struct RuntimeSettings {
+    pub auto_delete: bool,
+    pub worker_num: u8,
+    // KiB
+    pub url_part_size: u64,
+    pub tg_part_chunks: u8,
+    pub conflict: ConflictBehavior,
+    pub progress_style: ProgressStyle,
+}
+
+impl RuntimeSettings {
+    fn from_env() -> Self {
+        let env = ENV.get().unwrap();
+
+        Self {
+            auto_delete: env.should_auto_delete,
+            worker_num: env.task_handler_num,
+            url_part_size: 3200,
+            tg_part_chunks: 4,
+            conflict: ConflictBehavior::Rename,
+            progress_style: ProgressStyle::Detailed,
+        }
+    }
+
+    pub fn get(&self, key: &str) -> Result<String> {
+        let value = match key {
+            "auto_delete" => self.auto_delete.to_string(),
+            "worker_num" => self.worker_num.to_string(),
+            "url_part_size" => self.url_part_size.to_string(),
+            "tg_part_chunks" => self.tg_part_chunks.to_string(),
+            "conflict" => self.conflict.to_string(),
+            "progress_style" => self.progress_style.to_string(),
+            _ => return Err(anyhow!("unknown setting {}", key)),
+        };
+
+        Ok(value)
+    }
+
+    fn set(&mut self, key: &str, value: &str) -> Result<()> {
+        match key {
+            "auto_delete" => self.auto_delete = parse_bool(value)?,
+            "worker_num" => {
+                self.worker_num = parse_in_range(value, 1, 32)?;
+            }
+            "url_part_size" => {
+                let url_part_size = parse_in_range(value, PART_SIZE_UNIT, MAX_PART_SIZE)?;
+
+                if url_part_size % PART_SIZE_UNIT != 0 {
+                    return Err(anyhow!(
+                        "url_part_size should be a multiple of {}",
+                        PART_SIZE_UNIT
+                    ));
+                }
+
+                self.url_part_size = url_part_size;
+            }
+            "tg_part_chunks" => {
+                self.tg_part_chunks = parse_in_range(value, 1, 16)?;
+            }
+            "conflict" => self.conflict = value.parse()?,
+            "progress_style" => self.progress_style = value.parse()?,
+            _ => return Err(anyhow!("unknown setting {}", key)),
+        }
+
+        Ok(())
+    }
+
+    // with s3, every task may hold its part and a staged s3 part at the same time,
+    // tasks would wait for each other's memory forever if they don't fit
+    fn check_memory_limit(&self) -> Result<()> {
+        let env = ENV.get().unwrap();
+
+        if env.storage.s3.is_none() {
+            return Ok(());
+        }
+
+        let part_size =
+            (self.url_part_size * 1024).max(u64::from(self.tg_part_chunks) * MAX_CHUNK_SIZE as u64);
+        let required_size = u64::from(self.worker_num) * (part_size + S3_PART_SIZE as u64);
+        let required_mib = required_size.div_ceil(1024 * 1024);
+
+        if required_mib > env.memory_limit {
+            return Err(anyhow!(
+                "memory_limit should be at least {} MiB for worker_num × (part size + 8 MiB of s3)",
+                required_mib
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+pub struct Settings {
+    pub session: SettingsSession,
+    values: RwLock<RuntimeSettings>,
+}
+
+impl Settings {
+    pub async fn new(session_path: &str) -> Result<Self> {
+        let session = SettingsSession::new(session_path).await?;
+
+        let mut values = RuntimeSettings::from_env();
+
+        for entry in session.get_values().await? {
+            if SETTING_KEYS.iter().any(|(key, _)| *key == entry.key) {
+                // a saved value may become invalid after upgrading, the default is used then
+                values
+                    .set(&entry.key, &entry.value)
+                    .context(format!("failed to load setting {}", entry.key))
+                    .trace();
+            }
+        }
+
+        values.check_memory_limit()?;
+
+        Ok(Self {
+            session,
+            values: RwLock::new(values),
+        })
+    }
+
+    pub async fn get(&self) -> RuntimeSettings {
+        self.values.read().await.clone()
+    }
+
+    pub async fn set(&self, key: &str, value: &str) -> Result<()> {
+        let mut values = self.values.write().await;
+
+        // only applied after it's saved
+        let mut new_values = values.clone();
+        new_values.set(key, value)?;
+        new_values.check_memory_limit()?;
+
+        self.session.set_value(key, &new_values.get(key)?).await?;
+
+        *values = new_values;
+
+        tracing::info!("set setting {} to {}", key, value);
+
+        Ok(())
+    }
+
+    // back to the value from env or the default
+    pub async fn reset(&self, key: &str) -> Result<()> {
+        let default_value = RuntimeSettings::from_env().get(key)?;
+
+        let mut values = self.values.write().await;
+
+        let mut new_values = values.clone();
+        new_values.set(key, &default_value)?;
+        new_values.check_memory_limit()?;
+
+        self.session.delete_value(key).await?;
+
+        *values = new_values;
+
+        tracing::info!("reset setting {}", key);
+
+        Ok(())
+    }
+}
+
+pub fn topic_dir_key(chat_id: i64, topic_id: i32) -> String {
+    format!("{}{}:{}", TOPIC_DIR_KEY_PREFIX, chat_id, topic_id)
+}
+
+pub fn root_path_key(storage_type: &StorageType) -> String {
+    format!("{}{}", ROOT_PATH_KEY_PREFIX, storage_type)
+}
+
+pub fn temp_root_path_key(storage_type: &StorageType) -> String {
+    format!("{}{}", TEMP_ROOT_PATH_KEY_PREFIX, storage_type)
+}
+
+pub fn parse_topic_dir_key(key: &str) -> Option<(i64, i32)> {
+    let (chat_id, topic_id) = key.strip_prefix(TOPIC_DIR_KEY_PREFIX)?.split_once(':')?;
+
+    Some((chat_id.parse().ok()?, topic_id.parse().ok()?))
+}
+
+fn parse_bool(value: &str) -> Result<bool> {
+    match value {
+        "true" | "on" => Ok(true),
+        "false" | "off" => Ok(false),
+        _ => Err(anyhow!("value should be true or false")),
+    }
+}
+
+fn parse_in_range<T>(value: &str, min: T, max: T) -> Result<T>
+where
+    T: std::str::FromStr + PartialOrd + std::fmt::Display,
+{
+    value
+        .parse::<T>()
+        .ok()
+        .filter(|value| *value >= min && *value <= max)
+        .ok_or_else(|| anyhow!("value should be a number from {} to {}", min, max))
+}
@@ -0,0 +1,106 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use super::entries;
+use anyhow::{Context, Result};
+use sea_orm::{
+    sea_query::OnConflict, ConnectionTrait, DatabaseConnection, EntityName, EntityTrait, Schema,
+    Set,
+};
+
+// cloned to the clients that keep their own settings, like the temporary onedrive dir
+#[derive(Clone)]
+pub struct SettingsSession {
+    connection: DatabaseConnection,
+}
+
+impl SettingsSession {
+    pub async fn new(session_path: &str) -> Result<Self> {
+        let connection = Self::connect_db(session_path).await?;
+
+        Ok(Self { connection })
+    }
+
+    async fn connect_db(path: &str) -> Result<DatabaseConnection> {
+        let connection = sea_orm::Database::connect(format!("sqlite://{}?mode=rwc", path))
+            .await
+            .context("failed to connect to settings session")?;
+
+        Self::create_table_if_not_exists(&connection).await?;
+
+        Ok(connection)
+    }
+
+    async fn create_table_if_not_exists(connection: &DatabaseConnection) -> Result<()> {
+        if !Self::is_table_exists(connection).await {
+            let backend = connection.get_database_backend();
+
+            let table_create_statement =
+                Schema::new(backend).create_table_from_entity(entries::Entity);
+
+            connection
+                .execute(backend.build(&table_create_statement))
+                .await
+                .context(format!(
+                    "failed to create table {}",
+                    entries::Entity.table_name()
+                ))?;
+        }
+
+        Ok(())
+    }
+
+    async fn is_table_exists(connection: &DatabaseConnection) -> bool {
+        let result = entries::Entity::find().one(connection).await;
+
+        result.is_ok()
+    }
+
+    pub async fn get_value(&self, key: &str) -> Result<Option<String>> {
+        let entry = entries::Entity::find_by_id(key.to_string())
+            .one(&self.connection)
+            .await
+            .context(format!("failed to get setting {}", key))?;
+
+        Ok(entry.map(|entry| entry.value))
+    }
+
+    pub async fn get_values(&self) -> Result<Vec<entries::Model>> {
+        entries::Entity::find()
+            .all(&self.connection)
+            .await
+            .context("failed to get settings")
+    }
+
+    pub async fn set_value(&self, key: &str, value: &str) -> Result<()> {
+        let insert_item = entries::ActiveModel {
+            key: Set(key.to_string()),
+            value: Set(value.to_string()),
+        };
+
+        entries::Entity::insert(insert_item)
+            .on_conflict(
+                OnConflict::column(entries::Column::Key)
+                    .update_column(entries::Column::Value)
+                    .to_owned(),
+            )
+            .exec(&self.connection)
+            .await
+            .context(format!("failed to set setting {}", key))?;
+
+        Ok(())
+    }
+
+    pub async fn delete_value(&self, key: &str) -> Result<()> {
+        entries::Entity::delete_by_id(key.to_string())
+            .exec(&self.connection)
+            .await
+            .context(format!("failed to delete setting {}", key))?;
+
+        Ok(())
+    }
+}
@@ -6,40 +6,440 @@
 */
 
 use crate::{
-    client::{OneDriveClient, TelegramClient},
-    env::ENV,
+    acl::AclSession,
+    client::{onedrive::share::ShareOption, utils::chat_from_hex, OneDriveClient, TelegramClient},
+    env::{S3Env, WebDavEnv, ENV},
     error::ResultExt,
-    tasker::TaskSession,
+    message::ChatEntity,
+    settings::{
+        parse_topic_dir_key, root_path_key, temp_root_path_key, topic_dir_key, Settings,
+        API_CHAT_KEY,
+    },
+    storage::{
+        LocalStorage, S3Storage, S3Target, Storage, StorageBackend, StorageType, WebDavStorage,
+    },
+    tasker::{BufferPool, InsertTask, ProgressMode, TaskSession},
+    usage::UsageSession,
+    webhook::{TaskEvent, Webhook},
 };
-use std::sync::{atomic::AtomicBool, Arc};
+use anyhow::{anyhow, Result};
+use grammers_client::types::{Chat, PackedChat};
+use std::{collections::HashMap, sync::Arc};
+use tokio::sync::{Mutex, RwLock};
 
 pub struct State {
     pub telegram_bot: TelegramClient,
     pub telegram_user: TelegramClient,
     pub onedrive: OneDriveClient,
-    pub should_auto_delete: AtomicBool,
+    pub local_storage: Option<LocalStorage>,
+    pub webdav_storage: Option<WebDavStorage>,
+    pub s3_storage: Option<S3Storage>,
+    // storage that new tasks are uploaded to
+    pub current_storage: RwLock<StorageType>,
+    pub settings: Settings,
+    pub acl_session: AclSession,
+    // chat id -> share link option for completed uploads
+    pub share_options: Mutex<HashMap<i64, ShareOption>>,
+    // chat id -> s3 bucket and prefix, default one is used if not set
+    pub s3_targets: Mutex<HashMap<i64, S3Target>>,
+    // chat id -> where progress is shown, aggregate if not set
+    pub progress_modes: Mutex<HashMap<i64, ProgressMode>>,
+    // (chat id, sender id) -> item path waiting for /rm confirm
+    pub pending_removals: Mutex<HashMap<(i64, i64), String>>,
+    // chat that tasks submitted through http api are announced in
+    pub api_chat: Mutex<Option<PackedChat>>,
+    // (chat id, forum topic id) -> directory that tasks posted in the topic are uploaded to
+    pub topic_dirs: Mutex<HashMap<(i64, i32), String>>,
+    // private chat id -> id of the latest message the bot received in it
+    pub private_last_message_ids: Mutex<HashMap<i64, i32>>,
     pub task_session: TaskSession,
+    // buffers of parts being transferred
+    pub buffer_pool: BufferPool,
+    pub usage_session: UsageSession,
+    pub webhook: Webhook,
 }
 
 impl State {
     pub async fn new() -> Self {
         let env = ENV.get().unwrap();
 
+        let settings = Settings::new(&env.settings_session_path)
+            .await
+            .unwrap_or_trace();
+        let acl_session = AclSession::new(&env.acl_session_path)
+            .await
+            .unwrap_or_trace();
         let telegram_bot = TelegramClient::new_bot().await.unwrap_or_trace();
         let telegram_user = TelegramClient::new_user().await.unwrap_or_trace();
-        let onedrive = OneDriveClient::new().await.unwrap_or_trace();
-        let should_auto_delete = AtomicBool::new(env.should_auto_delete);
+        let onedrive = OneDriveClient::new(settings.session.clone())
+            .await
+            .unwrap_or_trace();
+        let buffer_pool = BufferPool::new(env.memory_limit as usize * 1024 * 1024);
+        let local_storage = env.storage.local_root.as_deref().map(LocalStorage::new);
+        let webdav_storage = env.storage.webdav.as_ref().map(
+            |WebDavEnv {
+                 url,
+                 username,
+                 password,
+             }| {
+                WebDavStorage::new(url, username.clone(), password.clone()).unwrap_or_trace()
+            },
+        );
+        let s3_storage = env.storage.s3.as_ref().map(
+            |S3Env {
+                 endpoint,
+                 region,
+                 access_key,
+                 secret_key,
+                 bucket,
+                 prefix,
+             }| {
+                S3Storage::new(
+                    endpoint,
+                    region,
+                    access_key,
+                    secret_key,
+                    S3Target {
+                        bucket: bucket.clone(),
+                        prefix: prefix.clone(),
+                    },
+                    buffer_pool.clone(),
+                )
+                .unwrap_or_trace()
+            },
+        );
+        let current_storage = RwLock::new(env.storage.default_storage.clone());
+        let share_options = Mutex::new(
+            env.chats
+                .iter()
+                .filter_map(|(chat_id, chat)| Some((*chat_id, chat.share.clone()?)))
+                .collect(),
+        );
+        let s3_targets = Mutex::new(
+            env.chats
+                .iter()
+                .filter_map(|(chat_id, chat)| {
+                    let default_target = &s3_storage.as_ref()?.default_target;
+
+                    if chat.s3_bucket.is_none() && chat.s3_prefix.is_none() {
+                        return None;
+                    }
+
+                    let target = S3Target {
+                        bucket: chat
+                            .s3_bucket
+                            .clone()
+                            .unwrap_or_else(|| default_target.bucket.clone()),
+                        prefix: chat
+                            .s3_prefix
+                            .clone()
+                            .unwrap_or_else(|| default_target.prefix.clone()),
+                    };
+
+                    Some((*chat_id, target))
+                })
+                .collect(),
+        );
+        let progress_modes = Mutex::new(
+            env.chats
+                .iter()
+                .filter_map(|(chat_id, chat)| Some((*chat_id, chat.progress?)))
+                .collect(),
+        );
+        let pending_removals = Mutex::new(HashMap::new());
+        let api_chat = Mutex::new(
+            settings
+                .session
+                .get_value(API_CHAT_KEY)
+                .await
+                .unwrap_or_trace()
+                .and_then(|chat_hex| chat_from_hex(&chat_hex).ok()),
+        );
+        let topic_dirs = Mutex::new(
+            settings
+                .session
+                .get_values()
+                .await
+                .unwrap_or_trace()
+                .into_iter()
+                .filter_map(|entry| Some((parse_topic_dir_key(&entry.key)?, entry.value)))
+                .collect(),
+        );
+        let private_last_message_ids = Mutex::new(HashMap::new());
         let task_session = TaskSession::new(&env.tasker_session_path)
             .await
             .unwrap_or_trace();
+        let usage_session = UsageSession::new(&env.usage_session_path)
+            .await
+            .unwrap_or_trace();
+        let webhook = Webhook::new().await.unwrap_or_trace();
 
         Self {
             telegram_bot,
             telegram_user,
             onedrive,
-            should_auto_delete,
+            local_storage,
+            webdav_storage,
+            s3_storage,
+            current_storage,
+            settings,
+            acl_session,
+            share_options,
+            s3_targets,
+            progress_modes,
+            pending_removals,
+            api_chat,
+            topic_dirs,
+            private_last_message_ids,
             task_session,
+            buffer_pool,
+            usage_session,
+            webhook,
+        }
+    }
+
+    // returns id of the inserted task
+    pub async fn insert_task(&self, insert_task: InsertTask) -> Result<i64> {
+        // the account may be changed by /drive before the task is completed
+        let (onedrive_user, onedrive_drive_id) =
+            self.get_onedrive_drive(&insert_task.storage).await?;
+
+        let id = self
+            .task_session
+            .insert_task(insert_task, onedrive_user, onedrive_drive_id)
+            .await?;
+
+        if let Some(task) = self.task_session.get_task(id).await? {
+            self.webhook.emit(TaskEvent::Inserted, &task).await;
+        }
+
+        Ok(id)
+    }
+
+    // (account, drive id) that onedrive uploads go to, none for other storages and the personal drive
+    pub async fn get_onedrive_drive(
+        &self,
+        storage_type: &StorageType,
+    ) -> Result<(Option<String>, Option<String>)> {
+        if *storage_type != StorageType::OneDrive {
+            return Ok((None, None));
+        }
+
+        let onedrive_user = self.onedrive.get_current_username().await?;
+        let onedrive_drive_id = self.onedrive.get_drive_info().await.map(|drive| drive.id);
+
+        Ok((onedrive_user, onedrive_drive_id))
+    }
+
+    pub fn storage(&self, storage_type: &StorageType) -> Result<Storage<'_>> {
+        match storage_type {
+            StorageType::OneDrive => Ok(Storage::OneDrive(&self.onedrive)),
+            StorageType::Local => self
+                .local_storage
+                .as_ref()
+                .map(Storage::Local)
+                .ok_or_else(|| anyhow!("local storage is not configured, set local_root first")),
+            StorageType::WebDav => self
+                .webdav_storage
+                .as_ref()
+                .map(Storage::WebDav)
+                .ok_or_else(|| anyhow!("webdav storage is not configured, set webdav_url first")),
+            StorageType::S3 => self
+                .s3_storage
+                .as_ref()
+                .map(Storage::S3)
+                .ok_or_else(|| anyhow!("s3 storage is not configured, set s3_endpoint first")),
+        }
+    }
+
+    pub async fn get_s3_target(&self, chat_id: i64) -> Result<S3Target> {
+        let s3_storage = self
+            .s3_storage
+            .as_ref()
+            .ok_or_else(|| anyhow!("s3 storage is not configured, set s3_endpoint first"))?;
+
+        let target = self
+            .s3_targets
+            .lock()
+            .await
+            .get(&chat_id)
+            .cloned()
+            .unwrap_or_else(|| s3_storage.default_target.clone());
+
+        Ok(target)
+    }
+
+    pub async fn get_progress_mode(&self, chat_id: i64) -> ProgressMode {
+        self.progress_modes
+            .lock()
+            .await
+            .get(&chat_id)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    pub async fn get_topic_dir(&self, chat_id: i64, topic_id: i32) -> Option<String> {
+        self.topic_dirs
+            .lock()
+            .await
+            .get(&(chat_id, topic_id))
+            .cloned()
+    }
+
+    pub async fn set_topic_dir(&self, chat_id: i64, topic_id: i32, dir: &str) -> Result<()> {
+        self.settings
+            .session
+            .set_value(&topic_dir_key(chat_id, topic_id), dir)
+            .await?;
+        self.topic_dirs
+            .lock()
+            .await
+            .insert((chat_id, topic_id), dir.to_string());
+
+        Ok(())
+    }
+
+    pub async fn reset_topic_dir(&self, chat_id: i64, topic_id: i32) -> Result<()> {
+        self.settings
+            .session
+            .delete_value(&topic_dir_key(chat_id, topic_id))
+            .await?;
+        self.topic_dirs.lock().await.remove(&(chat_id, topic_id));
+
+        Ok(())
+    }
+
+    // each storage has its own root path, the one of onedrive is kept per account in its session
+    pub async fn get_root_path(
+        &self,
+        storage_type: &StorageType,
+        should_consume_temp: bool,
+    ) -> Result<String> {
+        if *storage_type == StorageType::OneDrive {
+            return self.onedrive.get_root_path(should_consume_temp).await;
         }
+
+        let session = &self.settings.session;
+        let temp_key = temp_root_path_key(storage_type);
+
+        if let Some(temp_root_path) = session.get_value(&temp_key).await? {
+            if should_consume_temp {
+                session.delete_value(&temp_key).await?;
+            }
+
+            return Ok(temp_root_path);
+        }
+
+        let root_path = session
+            .get_value(&root_path_key(storage_type))
+            .await?
+            .unwrap_or_else(|| self.onedrive.default_root_path.clone());
+
+        Ok(root_path)
+    }
+
+    pub async fn does_temp_root_path_exist(&self, storage_type: &StorageType) -> Result<bool> {
+        if *storage_type == StorageType::OneDrive {
+            return Ok(self.onedrive.does_temp_root_path_exist().await);
+        }
+
+        let temp_root_path = self
+            .settings
+            .session
+            .get_value(&temp_root_path_key(storage_type))
+            .await?;
+
+        Ok(temp_root_path.is_some())
+    }
+
+    pub async fn set_root_path(&self, storage_type: &StorageType, path: &str) -> Result<()> {
+        if *storage_type == StorageType::OneDrive {
+            return self.onedrive.set_root_path(path).await;
+        }
+
+        self.storage(storage_type)?.validate_dir(path).await?;
+
+        let session = &self.settings.session;
+        session
+            .delete_value(&temp_root_path_key(storage_type))
+            .await?;
+        session
+            .set_value(&root_path_key(storage_type), path)
+            .await?;
+
+        tracing::info!("set {} root path: {}", storage_type, path);
+
+        Ok(())
+    }
+
+    pub async fn reset_root_path(&self, storage_type: &StorageType) -> Result<()> {
+        if *storage_type == StorageType::OneDrive {
+            return self.onedrive.reset_root_path().await;
+        }
+
+        let session = &self.settings.session;
+        session
+            .delete_value(&temp_root_path_key(storage_type))
+            .await?;
+        session.delete_value(&root_path_key(storage_type)).await?;
+
+        tracing::info!("reset {} root path to default", storage_type);
+
+        Ok(())
+    }
+
+    pub async fn set_temp_root_path(&self, storage_type: &StorageType, path: &str) -> Result<()> {
+        if *storage_type == StorageType::OneDrive {
+            return self.onedrive.set_temp_root_path(path).await;
+        }
+
+        self.storage(storage_type)?.validate_dir(path).await?;
+
+        self.settings
+            .session
+            .set_value(&temp_root_path_key(storage_type), path)
+            .await?;
+
+        tracing::info!("{} temp root path: {}", storage_type, path);
+
+        Ok(())
+    }
+
+    pub async fn clear_temp_root_path(&self, storage_type: &StorageType) -> Result<()> {
+        if *storage_type == StorageType::OneDrive {
+            return self.onedrive.clear_temp_root_path().await;
+        }
+
+        self.settings
+            .session
+            .delete_value(&temp_root_path_key(storage_type))
+            .await?;
+
+        tracing::info!("clear {} temp root path", storage_type);
+
+        Ok(())
+    }
+
+    // the chat seen by the client that reads and deletes its messages, see chat_client
+    pub async fn get_chat_user(&self, chat: Chat) -> Result<Chat> {
+        match chat {
+            Chat::User(_) => Ok(chat),
+            _ => self.telegram_user.get_chat(&ChatEntity::from(chat)).await,
+        }
+    }
+
+    // bots can't read the history of groups, so the user does it,
+    // but in private chats with the bot, message ids are only valid for the bot
+    pub fn chat_client(&self, chat_user: &PackedChat) -> &TelegramClient {
+        if chat_user.is_user() {
+            &self.telegram_bot
+        } else {
+            &self.telegram_user
+        }
+    }
+
+    pub async fn current_storage_type(&self) -> StorageType {
+        self.current_storage.read().await.clone()
     }
 }
 
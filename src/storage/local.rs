@@ -0,0 +1,242 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use super::{
+    utils::{numbered_filename, split_root_path},
+    ConflictBehavior, StorageBackend, UploadTarget, UploadedItem,
+};
+use anyhow::{anyhow, Context, Result};
+use bytes::Bytes;
+use std::{
+    ffi::OsString,
+    io::{ErrorKind, SeekFrom},
+    path::{Path, PathBuf},
+};
+use tokio::{
+    fs::{self, OpenOptions},
+    io::{AsyncSeekExt, AsyncWriteExt},
+};
+
+// unfinished uploads are written to $filename.part, and renamed after the last part
+const PART_EXTENSION: &str = "part";
+// the same, but the file with the same name is replaced after the last part
+const REPLACE_PART_EXTENSION: &str = "replace-part";
+
+pub struct LocalStorage {
+    pub root: PathBuf,
+}
+
+impl LocalStorage {
+    pub fn new(root: &str) -> Self {
+        Self {
+            root: PathBuf::from(root),
+        }
+    }
+
+    fn get_dir(&self, root_path: &str) -> Result<PathBuf> {
+        let dir = split_root_path(root_path)?
+            .into_iter()
+            .fold(self.root.clone(), |dir, segment| dir.join(segment));
+
+        Ok(dir)
+    }
+}
+
+impl StorageBackend for LocalStorage {
+    async fn create_upload_session(
+        &self,
+        root_path: &str,
+        filename: &str,
+        _total_length: u64,
+        conflict: ConflictBehavior,
+    ) -> Result<UploadTarget> {
+        let dir = self.get_dir(root_path)?;
+
+        fs::create_dir_all(&dir)
+            .await
+            .context(format!("failed to create dir {}", dir.display()))?;
+
+        let mut index = 0;
+
+        // reserve the file name by creating its part file, so that uploads with the same name won't conflict
+        let part_path = loop {
+            let name = numbered_filename(filename, index);
+            let file_path = dir.join(&name);
+            let part_path = get_part_path(&file_path, conflict);
+
+            index += 1;
+
+            let exists = fs::try_exists(&file_path)
+                .await
+                .context("failed to check if file exists")?;
+
+            if !conflict.check(&name, exists)? {
+                continue;
+            }
+
+            match OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&part_path)
+                .await
+            {
+                Ok(_) => break part_path,
+                Err(e)
+                    if e.kind() == ErrorKind::AlreadyExists
+                        && conflict == ConflictBehavior::Rename =>
+                {
+                    continue
+                }
+                Err(e) => {
+                    return Err(e).context(format!(
+                        "failed to create part file {}",
+                        part_path.display()
+                    ))
+                }
+            }
+        };
+
+        tracing::debug!("created local upload session {}", part_path.display());
+
+        Ok(UploadTarget {
+            upload_url: part_path.to_string_lossy().to_string(),
+            current_length: 0,
+        })
+    }
+
+    async fn upload_part(
+        &self,
+        upload_url: &str,
+        buffer: &Bytes,
+        current_length: u64,
+        total_length: u64,
+    ) -> Result<Option<UploadedItem>> {
+        let part_path = Path::new(upload_url);
+
+        let mut file = OpenOptions::new()
+            .write(true)
+            .open(part_path)
+            .await
+            .context(format!("failed to open part file {}", upload_url))?;
+
+        file.seek(SeekFrom::Start(current_length))
+            .await
+            .context("failed to seek part file")?;
+        file.write_all(buffer)
+            .await
+            .context("failed to write part file")?;
+        file.flush().await.context("failed to flush part file")?;
+        drop(file);
+
+        if current_length + (buffer.len() as u64) < total_length {
+            return Ok(None);
+        }
+
+        let file_path = complete_part_file(part_path).await?;
+
+        let name = file_path
+            .file_name()
+            .ok_or_else(|| anyhow!("uploaded file has no name"))?
+            .to_string_lossy()
+            .to_string();
+
+        Ok(Some(UploadedItem {
+            name,
+            id: None,
+            size: total_length,
+        }))
+    }
+
+    async fn get_resume_offset(&self, upload_url: &str) -> Result<u64> {
+        let metadata = fs::metadata(upload_url).await.context(format!(
+            "failed to get metadata of part file {}",
+            upload_url
+        ))?;
+
+        Ok(metadata.len())
+    }
+
+    async fn get_free_space(&self) -> Result<Option<u64>> {
+        Ok(None)
+    }
+
+    async fn cancel_upload(&self, upload_url: &str) -> Result<()> {
+        match fs::remove_file(upload_url).await {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(e).context(format!("failed to remove part file {}", upload_url)),
+        }
+    }
+
+    async fn validate_dir(&self, root_path: &str) -> Result<()> {
+        let dir = self.get_dir(root_path)?;
+
+        match fs::metadata(&dir).await {
+            Ok(metadata) if !metadata.is_dir() => {
+                Err(anyhow!("{} is not a directory in local storage", root_path))
+            }
+            Ok(_) => Ok(()),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(e).context(format!("failed to get dir {}", dir.display())),
+        }
+    }
+}
+
+fn get_part_path(file_path: &Path, conflict: ConflictBehavior) -> PathBuf {
+    let mut part_path = OsString::from(file_path.as_os_str());
+    part_path.push(".");
+
+    if conflict == ConflictBehavior::Replace {
+        part_path.push(REPLACE_PART_EXTENSION);
+    } else {
+        part_path.push(PART_EXTENSION);
+    }
+
+    PathBuf::from(part_path)
+}
+
+async fn complete_part_file(part_path: &Path) -> Result<PathBuf> {
+    let dir = part_path
+        .parent()
+        .ok_or_else(|| anyhow!("part file has no parent dir"))?;
+
+    let filename = part_path
+        .file_stem()
+        .ok_or_else(|| anyhow!("part file has no name"))?
+        .to_string_lossy()
+        .to_string();
+
+    let should_replace = part_path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        == Some(REPLACE_PART_EXTENSION);
+
+    // a file with the same name may be created by others during uploading
+    let mut index = 0;
+    let file_path = loop {
+        let file_path = dir.join(numbered_filename(&filename, index));
+
+        if should_replace
+            || !fs::try_exists(&file_path)
+                .await
+                .context("failed to check if file exists")?
+        {
+            break file_path;
+        }
+
+        index += 1;
+    };
+
+    fs::rename(part_path, &file_path).await.context(format!(
+        "failed to rename part file to {}",
+        file_path.display()
+    ))?;
+
+    tracing::info!("saved file to {}", file_path.display());
+
+    Ok(file_path)
+}
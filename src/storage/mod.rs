@@ -0,0 +1,294 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+mod local;
+mod s3;
+mod utils;
+mod webdav;
+
+use crate::client::OneDriveClient;
+use anyhow::{anyhow, Result};
+use bytes::Bytes;
+pub use local::LocalStorage;
+pub use s3::{S3Storage, S3Target, PART_SIZE as S3_PART_SIZE};
+use sea_orm::{
+    sea_query::{ArrayType, ValueType, ValueTypeErr},
+    ColIdx, ColumnType, DbErr, QueryResult, TryGetError, TryGetable, Value,
+};
+use std::{fmt::Display, str::FromStr};
+pub use webdav::WebDavStorage;
+
+pub struct UploadTarget {
+    // where the following parts should be uploaded to, stored in the task
+    pub upload_url: String,
+    // where the upload should start from
+    pub current_length: u64,
+}
+
+pub struct UploadedItem {
+    pub name: String,
+    pub id: Option<String>,
+    pub size: u64,
+}
+
+pub trait StorageBackend {
+    async fn create_upload_session(
+        &self,
+        root_path: &str,
+        filename: &str,
+        total_length: u64,
+        conflict: ConflictBehavior,
+    ) -> Result<UploadTarget>;
+
+    // parts must be uploaded sequentially, returns the uploaded item after the last part
+    // the buffer is cloned without copying if the backend needs to own it
+    async fn upload_part(
+        &self,
+        upload_url: &str,
+        buffer: &Bytes,
+        current_length: u64,
+        total_length: u64,
+    ) -> Result<Option<UploadedItem>>;
+
+    async fn get_resume_offset(&self, upload_url: &str) -> Result<u64>;
+
+    // None if the backend doesn't report its free space
+    async fn get_free_space(&self) -> Result<Option<u64>>;
+
+    // release what the unfinished upload holds
+    async fn cancel_upload(&self, upload_url: &str) -> Result<()>;
+
+    // whether new tasks can be uploaded to the directory, it's created on upload if missing
+    async fn validate_dir(&self, root_path: &str) -> Result<()>;
+}
+
+pub enum Storage<'s> {
+    OneDrive(&'s OneDriveClient),
+    Local(&'s LocalStorage),
+    WebDav(&'s WebDavStorage),
+    S3(&'s S3Storage),
+}
+
+impl StorageBackend for Storage<'_> {
+    async fn create_upload_session(
+        &self,
+        root_path: &str,
+        filename: &str,
+        total_length: u64,
+        conflict: ConflictBehavior,
+    ) -> Result<UploadTarget> {
+        match self {
+            Self::OneDrive(storage) => {
+                storage
+                    .create_upload_session(root_path, filename, total_length, conflict)
+                    .await
+            }
+            Self::Local(storage) => {
+                storage
+                    .create_upload_session(root_path, filename, total_length, conflict)
+                    .await
+            }
+            Self::WebDav(storage) => {
+                storage
+                    .create_upload_session(root_path, filename, total_length, conflict)
+                    .await
+            }
+            Self::S3(storage) => {
+                storage
+                    .create_upload_session(root_path, filename, total_length, conflict)
+                    .await
+            }
+        }
+    }
+
+    async fn upload_part(
+        &self,
+        upload_url: &str,
+        buffer: &Bytes,
+        current_length: u64,
+        total_length: u64,
+    ) -> Result<Option<UploadedItem>> {
+        match self {
+            Self::OneDrive(storage) => {
+                storage
+                    .upload_part(upload_url, buffer, current_length, total_length)
+                    .await
+            }
+            Self::Local(storage) => {
+                storage
+                    .upload_part(upload_url, buffer, current_length, total_length)
+                    .await
+            }
+            Self::WebDav(storage) => {
+                storage
+                    .upload_part(upload_url, buffer, current_length, total_length)
+                    .await
+            }
+            Self::S3(storage) => {
+                storage
+                    .upload_part(upload_url, buffer, current_length, total_length)
+                    .await
+            }
+        }
+    }
+
+    async fn get_resume_offset(&self, upload_url: &str) -> Result<u64> {
+        match self {
+            Self::OneDrive(storage) => storage.get_resume_offset(upload_url).await,
+            Self::Local(storage) => storage.get_resume_offset(upload_url).await,
+            Self::WebDav(storage) => storage.get_resume_offset(upload_url).await,
+            Self::S3(storage) => storage.get_resume_offset(upload_url).await,
+        }
+    }
+
+    async fn get_free_space(&self) -> Result<Option<u64>> {
+        match self {
+            Self::OneDrive(storage) => storage.get_free_space().await,
+            Self::Local(storage) => storage.get_free_space().await,
+            Self::WebDav(storage) => storage.get_free_space().await,
+            Self::S3(storage) => storage.get_free_space().await,
+        }
+    }
+
+    async fn cancel_upload(&self, upload_url: &str) -> Result<()> {
+        match self {
+            Self::OneDrive(storage) => storage.cancel_upload(upload_url).await,
+            Self::Local(storage) => storage.cancel_upload(upload_url).await,
+            Self::WebDav(storage) => storage.cancel_upload(upload_url).await,
+            Self::S3(storage) => storage.cancel_upload(upload_url).await,
+        }
+    }
+
+    async fn validate_dir(&self, root_path: &str) -> Result<()> {
+        match self {
+            Self::OneDrive(storage) => storage.validate_dir(root_path).await,
+            Self::Local(storage) => storage.validate_dir(root_path).await,
+            Self::WebDav(storage) => storage.validate_dir(root_path).await,
+            Self::S3(storage) => storage.validate_dir(root_path).await,
+        }
+    }
+}
+
+// what to do if the file already exists
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ConflictBehavior {
+    // file.txt -> file (1).txt
+    Rename,
+    Replace,
+    Fail,
+}
+
+impl ConflictBehavior {
+    // whether the name can be used when it exists or not
+    pub fn check(self, filename: &str, exists: bool) -> Result<bool> {
+        match (self, exists) {
+            (_, false) | (Self::Replace, true) => Ok(true),
+            (Self::Rename, true) => Ok(false),
+            (Self::Fail, true) => Err(anyhow!("{} already exists", filename)),
+        }
+    }
+}
+
+impl FromStr for ConflictBehavior {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "rename" => Ok(Self::Rename),
+            "replace" => Ok(Self::Replace),
+            "fail" => Ok(Self::Fail),
+            _ => Err(anyhow!(
+                "conflict behavior should be one of rename, replace and fail"
+            )),
+        }
+    }
+}
+
+impl Display for ConflictBehavior {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Rename => write!(f, "rename"),
+            Self::Replace => write!(f, "replace"),
+            Self::Fail => write!(f, "fail"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum StorageType {
+    OneDrive,
+    Local,
+    WebDav,
+    S3,
+}
+
+impl FromStr for StorageType {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "onedrive" => Ok(Self::OneDrive),
+            "local" => Ok(Self::Local),
+            "webdav" => Ok(Self::WebDav),
+            "s3" => Ok(Self::S3),
+            _ => Err(anyhow!(
+                "storage type should be one of onedrive, local, webdav and s3"
+            )),
+        }
+    }
+}
+
+impl ValueType for StorageType {
+    fn try_from(v: Value) -> Result<Self, ValueTypeErr> {
+        match v {
+            Value::String(Some(value)) => value.parse().map_err(|_| ValueTypeErr),
+            _ => Err(ValueTypeErr),
+        }
+    }
+
+    fn type_name() -> String {
+        "StorageType".to_string()
+    }
+
+    fn array_type() -> ArrayType {
+        ArrayType::String
+    }
+
+    fn column_type() -> ColumnType {
+        ColumnType::String(None)
+    }
+}
+
+impl From<StorageType> for Value {
+    fn from(value: StorageType) -> Self {
+        Self::String(Some(Box::new(value.to_string())))
+    }
+}
+
+impl TryGetable for StorageType {
+    fn try_get_by<I: ColIdx>(res: &QueryResult, index: I) -> Result<Self, TryGetError> {
+        let value: String = res.try_get_by(index)?;
+
+        value.parse().map_err(|_| {
+            TryGetError::DbErr(DbErr::Type(format!(
+                "storage type value should be one of onedrive, local, webdav and s3: {}",
+                value
+            )))
+        })
+    }
+}
+
+impl Display for StorageType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::OneDrive => write!(f, "onedrive"),
+            Self::Local => write!(f, "local"),
+            Self::WebDav => write!(f, "webdav"),
+            Self::S3 => write!(f, "s3"),
+        }
+    }
+}
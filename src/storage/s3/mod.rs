@@ -0,0 +1,771 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+mod sign;
+
+use super::{
+    utils::{numbered_filename, split_root_path},
+    ConflictBehavior, StorageBackend, UploadTarget, UploadedItem,
+};
+use crate::{
+    tasker::{BufferPool, PooledBuffer},
+    utils::get_http_client,
+};
+use anyhow::{anyhow, Context, Result};
+use bytes::Bytes;
+use regex::Regex;
+use reqwest::{header, Method, Response, StatusCode};
+use sign::{sha256_hex, uri_encode, uri_encode_path, Signer};
+use std::{collections::HashMap, fmt::Display, ops::Deref};
+use tokio::sync::Mutex;
+
+// s3 requires every part except the last one to be at least 5MiB,
+// so parts from the tasker are buffered until reaching this size
+pub const PART_SIZE: usize = 8 * 1024 * 1024;
+
+// ListParts returns at most 1000 parts per request
+const MAX_LIST_PARTS: &str = "1000";
+
+#[derive(Clone)]
+pub struct S3Target {
+    pub bucket: String,
+    pub prefix: String,
+}
+
+impl S3Target {
+    // the first segment of a s3 root path is the bucket, like /$bucket/$prefix/$root_path
+    pub fn join_root_path(&self, root_path: &str) -> String {
+        let mut path = format!("/{}", self.bucket);
+
+        for segment in [self.prefix.as_str(), root_path] {
+            let segment = segment.trim_matches('/');
+
+            if !segment.is_empty() {
+                path.push('/');
+                path.push_str(segment);
+            }
+        }
+
+        path
+    }
+}
+
+impl Display for S3Target {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.prefix.is_empty() {
+            write!(f, "bucket {}", self.bucket)
+        } else {
+            write!(f, "bucket {} with prefix {}", self.bucket, self.prefix)
+        }
+    }
+}
+
+pub struct S3Storage {
+    pub endpoint: String,
+    host: String,
+    signer: Signer,
+    pub default_target: S3Target,
+    http_client: reqwest::Client,
+    // data waiting for the next part takes memory from the tasker's budget
+    buffer_pool: BufferPool,
+    // upload url -> parts uploaded and data waiting for the next part
+    uploads: Mutex<HashMap<String, S3Upload>>,
+}
+
+struct S3Upload {
+    // (part number, etag)
+    parts: Vec<(u32, String)>,
+    uploaded_length: u64,
+    buffer: S3Buffer,
+}
+
+// data waiting for the next part
+enum S3Buffer {
+    // parts from the tasker are copied until reaching PART_SIZE, the rest goes to the next part
+    Filling(PooledBuffer),
+    // a part from the tasker as is, or a filled buffer being uploaded, cloned for retries
+    Frozen(Bytes),
+}
+
+impl Default for S3Buffer {
+    fn default() -> Self {
+        Self::Frozen(Bytes::new())
+    }
+}
+
+impl Deref for S3Buffer {
+    type Target = [u8];
+
+    fn deref(&self) -> &Self::Target {
+        match self {
+            Self::Filling(buffer) => buffer,
+            Self::Frozen(bytes) => bytes,
+        }
+    }
+}
+
+impl S3Upload {
+    fn current_length(&self) -> u64 {
+        self.uploaded_length + self.buffer.len() as u64
+    }
+
+    // returns the data that doesn't fit in the staged part
+    // a task holds at most its part from the tasker and one staged part of PART_SIZE
+    async fn append(&mut self, buffer_pool: &BufferPool, buffer: Bytes) -> Result<Bytes> {
+        let mut pooled_buffer = match std::mem::take(&mut self.buffer) {
+            // the part from the tasker is already in the pool and large enough to be uploaded as is
+            staged if staged.is_empty() && buffer.len() >= PART_SIZE => {
+                self.buffer = S3Buffer::Frozen(buffer);
+
+                return Ok(Bytes::new());
+            }
+            S3Buffer::Filling(pooled_buffer) => pooled_buffer,
+            S3Buffer::Frozen(staged) => {
+                let mut pooled_buffer = buffer_pool.acquire(PART_SIZE.max(staged.len())).await?;
+                pooled_buffer.extend_from_slice(&staged);
+
+                pooled_buffer
+            }
+        };
+
+        let length = PART_SIZE
+            .saturating_sub(pooled_buffer.len())
+            .min(buffer.len());
+        pooled_buffer.extend_from_slice(&buffer[..length]);
+        self.buffer = S3Buffer::Filling(pooled_buffer);
+
+        Ok(buffer.slice(length..))
+    }
+
+    // frozen once, so that retries upload the same part without copying
+    fn freeze(&mut self) -> Bytes {
+        let bytes = match std::mem::take(&mut self.buffer) {
+            S3Buffer::Filling(pooled_buffer) => pooled_buffer.freeze(),
+            S3Buffer::Frozen(bytes) => bytes,
+        };
+
+        self.buffer = S3Buffer::Frozen(bytes.clone());
+
+        bytes
+    }
+}
+
+// stored as the upload url of the task: $bucket/$key?uploadId=$upload_id
+struct S3Location {
+    bucket: String,
+    key: String,
+    upload_id: String,
+}
+
+impl S3Location {
+    fn parse(upload_url: &str) -> Result<Self> {
+        let (object, upload_id) = upload_url
+            .rsplit_once("?uploadId=")
+            .ok_or_else(|| anyhow!("upload id not found in s3 upload url"))?;
+
+        let (bucket, key) = object
+            .split_once('/')
+            .ok_or_else(|| anyhow!("object key not found in s3 upload url"))?;
+
+        Ok(Self {
+            bucket: bucket.to_string(),
+            key: key.to_string(),
+            upload_id: upload_id.to_string(),
+        })
+    }
+
+    fn upload_url(&self) -> String {
+        format!("{}/{}?uploadId={}", self.bucket, self.key, self.upload_id)
+    }
+
+    fn path(&self) -> String {
+        object_path(&self.bucket, &self.key)
+    }
+}
+
+fn object_path(bucket: &str, key: &str) -> String {
+    format!("/{}/{}", uri_encode(bucket), uri_encode_path(key))
+}
+
+fn get_xml_values(content: &str, tag: &str) -> Result<Vec<String>> {
+    let pattern = format!("(?s)<{0}>(.*?)</{0}>", tag);
+    let re = Regex::new(&pattern)
+        .context("invalid regex pattern")
+        .context(pattern)?;
+
+    let values = re
+        .captures_iter(content)
+        .map(|captures| unescape_xml(&captures[1]))
+        .collect();
+
+    Ok(values)
+}
+
+fn get_xml_value(content: &str, tag: &str) -> Result<String> {
+    get_xml_values(content, tag)?
+        .into_iter()
+        .next()
+        .ok_or_else(|| anyhow!("{} not found in s3 response", tag))
+}
+
+fn unescape_xml(s: &str) -> String {
+    s.replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&amp;", "&")
+}
+
+impl S3Storage {
+    pub fn new(
+        endpoint: &str,
+        region: &str,
+        access_key: &str,
+        secret_key: &str,
+        default_target: S3Target,
+        buffer_pool: BufferPool,
+    ) -> Result<Self> {
+        let endpoint_url = url::Url::parse(endpoint).context("failed to parse s3 endpoint")?;
+
+        let host_str = endpoint_url
+            .host_str()
+            .ok_or_else(|| anyhow!("host not found in s3 endpoint"))?;
+
+        let host = endpoint_url.port().map_or_else(
+            || host_str.to_string(),
+            |port| format!("{}:{}", host_str, port),
+        );
+
+        Ok(Self {
+            endpoint: endpoint.trim_end_matches('/').to_string(),
+            host,
+            signer: Signer {
+                access_key: access_key.to_string(),
+                secret_key: secret_key.to_string(),
+                region: region.to_string(),
+            },
+            default_target,
+            http_client: get_http_client()?,
+            buffer_pool,
+            uploads: Mutex::new(HashMap::new()),
+        })
+    }
+
+    async fn send_request(
+        &self,
+        method: Method,
+        path: &str,
+        query: &[(&str, &str)],
+        body: Bytes,
+    ) -> Result<Response> {
+        let payload_hash = sha256_hex(&body);
+
+        let mut url = format!("{}{}", self.endpoint, path);
+
+        if !query.is_empty() {
+            let query_string = query
+                .iter()
+                .map(|(key, value)| format!("{}={}", uri_encode(key), uri_encode(value)))
+                .collect::<Vec<_>>()
+                .join("&");
+
+            url.push('?');
+            url.push_str(&query_string);
+        }
+
+        tracing::debug!("s3 request: {} {}", method, url);
+
+        let request = self.http_client.request(method.clone(), url).body(body);
+
+        self.signer
+            .sign(request, &method, &self.host, path, query, &payload_hash)?
+            .send()
+            .await
+            .context("failed to send request to s3")
+    }
+
+    async fn send_checked_request(
+        &self,
+        method: Method,
+        path: &str,
+        query: &[(&str, &str)],
+        body: Bytes,
+    ) -> Result<Response> {
+        let response = self.send_request(method, path, query, body).await?;
+
+        let status = response.status();
+
+        if !status.is_success() {
+            let content = response.text().await.unwrap_or_default();
+
+            let message = get_xml_value(&content, "Message").unwrap_or(content);
+
+            return Err(anyhow!("{}: {}", status, message)).context("s3 request failed");
+        }
+
+        Ok(response)
+    }
+
+    async fn does_object_exist(&self, bucket: &str, key: &str) -> Result<bool> {
+        let response = self
+            .send_request(Method::HEAD, &object_path(bucket, key), &[], Bytes::new())
+            .await?;
+
+        match response.status() {
+            StatusCode::NOT_FOUND => Ok(false),
+            status if status.is_success() => Ok(true),
+            status => Err(anyhow!("failed to check s3 object {}: {}", key, status)),
+        }
+    }
+
+    async fn list_parts(&self, location: &S3Location) -> Result<S3Upload> {
+        let mut parts = Vec::new();
+        let mut uploaded_length = 0;
+        let mut part_number_marker = "0".to_string();
+
+        loop {
+            let response = self
+                .send_checked_request(
+                    Method::GET,
+                    &location.path(),
+                    &[
+                        ("uploadId", &location.upload_id),
+                        ("max-parts", MAX_LIST_PARTS),
+                        ("part-number-marker", &part_number_marker),
+                    ],
+                    Bytes::new(),
+                )
+                .await
+                .context("failed to list parts")?;
+
+            let content = response
+                .text()
+                .await
+                .context("failed to get list parts response text")?;
+
+            let part_values = get_xml_values(&content, "Part")?;
+
+            for part in part_values {
+                let part_number = get_xml_value(&part, "PartNumber")?
+                    .parse::<u32>()
+                    .context("failed to parse part number")?;
+                let etag = get_xml_value(&part, "ETag")?;
+                let size = get_xml_value(&part, "Size")?
+                    .parse::<u64>()
+                    .context("failed to parse part size")?;
+
+                parts.push((part_number, etag));
+                uploaded_length += size;
+            }
+
+            if get_xml_value(&content, "IsTruncated").unwrap_or_default() != "true" {
+                break;
+            }
+
+            part_number_marker = get_xml_value(&content, "NextPartNumberMarker")?;
+        }
+
+        Ok(S3Upload {
+            parts,
+            uploaded_length,
+            buffer: S3Buffer::default(),
+        })
+    }
+
+    async fn upload_buffer(&self, location: &S3Location, upload: &mut S3Upload) -> Result<()> {
+        let part_number = upload.parts.len() as u32 + 1;
+        let part = upload.freeze();
+
+        let response = self
+            .send_checked_request(
+                Method::PUT,
+                &location.path(),
+                &[
+                    ("partNumber", &part_number.to_string()),
+                    ("uploadId", &location.upload_id),
+                ],
+                part.clone(),
+            )
+            .await
+            .context(format!("failed to upload part {}", part_number))?;
+
+        let etag = response
+            .headers()
+            .get(header::ETAG)
+            .ok_or_else(|| anyhow!("etag not found in upload part response"))?
+            .to_str()
+            .context("header ETag has invisible ASCII chars")?
+            .to_string();
+
+        upload.parts.push((part_number, etag));
+        upload.uploaded_length += part.len() as u64;
+        upload.buffer = S3Buffer::default();
+
+        Ok(())
+    }
+
+    async fn complete_upload(&self, location: &S3Location, upload: &S3Upload) -> Result<()> {
+        let parts = upload
+            .parts
+            .iter()
+            .map(|(part_number, etag)| {
+                format!(
+                    "<Part><PartNumber>{}</PartNumber><ETag>{}</ETag></Part>",
+                    part_number,
+                    etag.replace('&', "&amp;")
+                )
+            })
+            .collect::<Vec<_>>()
+            .concat();
+
+        let body = format!(
+            "<CompleteMultipartUpload>{}</CompleteMultipartUpload>",
+            parts
+        );
+
+        let response = self
+            .send_checked_request(
+                Method::POST,
+                &location.path(),
+                &[("uploadId", &location.upload_id)],
+                Bytes::from(body),
+            )
+            .await
+            .context("failed to complete multipart upload")?;
+
+        // s3 may respond 200 with an error in the body
+        let content = response
+            .text()
+            .await
+            .context("failed to get complete multipart upload response text")?;
+
+        if let Ok(message) = get_xml_value(&content, "Message") {
+            return Err(anyhow!(message)).context("failed to complete multipart upload");
+        }
+
+        Ok(())
+    }
+
+    async fn upload_to(
+        &self,
+        location: &S3Location,
+        upload: &mut S3Upload,
+        mut buffer: Bytes,
+        is_last: bool,
+    ) -> Result<Option<UploadedItem>> {
+        loop {
+            // a full part is uploaded before the next one is staged
+            if upload.buffer.len() >= PART_SIZE {
+                self.upload_buffer(location, upload).await?;
+            }
+
+            if buffer.is_empty() {
+                break;
+            }
+
+            buffer = upload.append(&self.buffer_pool, buffer).await?;
+        }
+
+        if is_last && !upload.buffer.is_empty() {
+            self.upload_buffer(location, upload).await?;
+        }
+
+        if !is_last {
+            return Ok(None);
+        }
+
+        // an empty file still needs one part
+        if upload.parts.is_empty() {
+            self.upload_buffer(location, upload).await?;
+        }
+
+        self.complete_upload(location, upload).await?;
+
+        let name = location
+            .key
+            .rsplit('/')
+            .next()
+            .unwrap_or_default()
+            .to_string();
+
+        tracing::info!("saved file to s3 {}/{}", location.bucket, location.key);
+
+        Ok(Some(UploadedItem {
+            name,
+            id: Some(location.key.clone()),
+            size: upload.uploaded_length,
+        }))
+    }
+}
+
+impl StorageBackend for S3Storage {
+    async fn create_upload_session(
+        &self,
+        root_path: &str,
+        filename: &str,
+        _total_length: u64,
+        conflict: ConflictBehavior,
+    ) -> Result<UploadTarget> {
+        let segments = split_root_path(root_path)?;
+
+        let (bucket, prefix) = segments
+            .split_first()
+            .ok_or_else(|| anyhow!("s3 root path should start with a bucket"))?;
+
+        let mut index = 0;
+        let key = loop {
+            let name = numbered_filename(filename, index);
+            let key = prefix
+                .iter()
+                .cloned()
+                .chain([name.clone()])
+                .collect::<Vec<_>>()
+                .join("/");
+
+            index += 1;
+
+            if conflict.check(&name, self.does_object_exist(bucket, &key).await?)? {
+                break key;
+            }
+        };
+
+        let response = self
+            .send_checked_request(
+                Method::POST,
+                &object_path(bucket, &key),
+                &[("uploads", "")],
+                Bytes::new(),
+            )
+            .await
+            .context("failed to create multipart upload")?;
+
+        let content = response
+            .text()
+            .await
+            .context("failed to get create multipart upload response text")?;
+
+        let location = S3Location {
+            bucket: bucket.clone(),
+            key,
+            upload_id: get_xml_value(&content, "UploadId")?,
+        };
+
+        tracing::debug!("created s3 multipart upload {}", location.upload_url());
+
+        Ok(UploadTarget {
+            upload_url: location.upload_url(),
+            current_length: 0,
+        })
+    }
+
+    async fn upload_part(
+        &self,
+        upload_url: &str,
+        buffer: &Bytes,
+        current_length: u64,
+        total_length: u64,
+    ) -> Result<Option<UploadedItem>> {
+        let location = S3Location::parse(upload_url)?;
+
+        // taken out of the map so that other uploads won't wait for this one
+        let upload = self.uploads.lock().await.remove(upload_url);
+
+        // after restarting, the buffered data is lost, continue from the uploaded parts
+        let mut upload = match upload {
+            Some(upload) => upload,
+            None => self.list_parts(&location).await?,
+        };
+
+        let end_length = current_length + buffer.len() as u64;
+
+        // the part may have been received before a failed retry, like 416 of onedrive, or only partly
+        // the upload is dropped otherwise, which releases its staged part
+        if upload.current_length() < current_length || upload.current_length() > end_length {
+            return Err(anyhow!(
+                "s3 upload expects part from {}, got {}",
+                upload.current_length(),
+                current_length
+            ));
+        }
+
+        let received_length = (upload.current_length() - current_length) as usize;
+
+        let result = self
+            .upload_to(
+                &location,
+                &mut upload,
+                buffer.slice(received_length..),
+                end_length >= total_length,
+            )
+            .await;
+
+        // kept for the next part or a retry, a failed task releases it by cancel_upload
+        if !matches!(result, Ok(Some(_))) {
+            self.uploads
+                .lock()
+                .await
+                .insert(upload_url.to_string(), upload);
+        }
+
+        result
+    }
+
+    async fn get_resume_offset(&self, upload_url: &str) -> Result<u64> {
+        if let Some(upload) = self.uploads.lock().await.get(upload_url) {
+            return Ok(upload.current_length());
+        }
+
+        let upload = self.list_parts(&S3Location::parse(upload_url)?).await?;
+
+        Ok(upload.uploaded_length)
+    }
+
+    async fn get_free_space(&self) -> Result<Option<u64>> {
+        Ok(None)
+    }
+
+    async fn cancel_upload(&self, upload_url: &str) -> Result<()> {
+        self.uploads.lock().await.remove(upload_url);
+
+        let location = S3Location::parse(upload_url)?;
+
+        self.send_checked_request(
+            Method::DELETE,
+            &location.path(),
+            &[("uploadId", &location.upload_id)],
+            Bytes::new(),
+        )
+        .await
+        .context("failed to abort multipart upload")?;
+
+        Ok(())
+    }
+
+    // s3 has no directories, the path only becomes a part of object keys
+    async fn validate_dir(&self, root_path: &str) -> Result<()> {
+        split_root_path(root_path)?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use tokio::time::timeout;
+
+    const WAIT: Duration = Duration::from_millis(100);
+    // a batch of 6 telegram chunks
+    const TASKER_PART_SIZE: usize = 3 * 1024 * 1024;
+    const UPLOAD_URL: &str = "bucket/file?uploadId=upload";
+
+    fn empty_upload() -> S3Upload {
+        S3Upload {
+            parts: Vec::new(),
+            uploaded_length: 0,
+            buffer: S3Buffer::default(),
+        }
+    }
+
+    // nothing listens on the port, so requests fail
+    fn unreachable_storage(buffer_pool: BufferPool) -> S3Storage {
+        S3Storage::new(
+            "http://127.0.0.1:1",
+            "us-east-1",
+            "access",
+            "secret",
+            S3Target {
+                bucket: "bucket".to_string(),
+                prefix: String::new(),
+            },
+            buffer_pool,
+        )
+        .unwrap()
+    }
+
+    async fn staged_upload(buffer_pool: &BufferPool) -> S3Upload {
+        let mut upload = empty_upload();
+        let rest = upload
+            .append(buffer_pool, Bytes::from_static(b"part"))
+            .await
+            .unwrap();
+        assert!(rest.is_empty());
+
+        upload
+    }
+
+    #[tokio::test]
+    async fn staged_part_fits_with_tasker_part() {
+        let pool = BufferPool::new(PART_SIZE + TASKER_PART_SIZE);
+        let mut upload = empty_upload();
+
+        for _ in 0..10 {
+            let mut part = timeout(WAIT, pool.acquire(TASKER_PART_SIZE))
+                .await
+                .unwrap()
+                .unwrap();
+            part.resize(TASKER_PART_SIZE, 0);
+            let mut rest = part.freeze();
+
+            while !rest.is_empty() {
+                rest = timeout(WAIT, upload.append(&pool, rest))
+                    .await
+                    .unwrap()
+                    .unwrap();
+
+                // what upload_buffer does after the part is sent
+                if upload.buffer.len() >= PART_SIZE {
+                    let part = upload.freeze();
+                    upload.uploaded_length += part.len() as u64;
+                    upload.buffer = S3Buffer::default();
+                }
+            }
+        }
+
+        assert_eq!(upload.current_length(), 10 * TASKER_PART_SIZE as u64);
+    }
+
+    #[tokio::test]
+    async fn mismatched_part_releases_staged_part() {
+        let pool = BufferPool::new(PART_SIZE);
+        let storage = unreachable_storage(pool.clone());
+
+        let upload = staged_upload(&pool).await;
+        storage
+            .uploads
+            .lock()
+            .await
+            .insert(UPLOAD_URL.to_string(), upload);
+
+        let result = storage
+            .upload_part(UPLOAD_URL, &Bytes::from_static(b"part"), 100, 200)
+            .await;
+
+        assert!(result.is_err());
+        assert!(storage.uploads.lock().await.is_empty());
+        assert!(timeout(WAIT, pool.acquire(PART_SIZE)).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn cancelled_upload_releases_staged_part() {
+        let pool = BufferPool::new(PART_SIZE);
+        let storage = unreachable_storage(pool.clone());
+
+        let upload = staged_upload(&pool).await;
+        storage
+            .uploads
+            .lock()
+            .await
+            .insert(UPLOAD_URL.to_string(), upload);
+
+        // released even if aborting the multipart upload fails
+        assert!(storage.cancel_upload(UPLOAD_URL).await.is_err());
+        assert!(storage.uploads.lock().await.is_empty());
+        assert!(timeout(WAIT, pool.acquire(PART_SIZE)).await.is_ok());
+    }
+}
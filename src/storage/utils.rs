@@ -0,0 +1,32 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use anyhow::{anyhow, Result};
+use std::path::{Component, Path};
+
+// same as onedrive's conflict behavior: file.txt -> file (1).txt
+pub fn numbered_filename(filename: &str, index: u32) -> String {
+    if index == 0 {
+        return filename.to_string();
+    }
+
+    match filename.rsplit_once('.') {
+        Some((stem, ext)) if !stem.is_empty() => format!("{} ({}).{}", stem, index, ext),
+        _ => format!("{} ({})", filename, index),
+    }
+}
+
+// root path is relative to the storage root, it must not escape from it
+pub fn split_root_path(root_path: &str) -> Result<Vec<String>> {
+    Path::new(root_path.trim_start_matches('/'))
+        .components()
+        .map(|component| match component {
+            Component::Normal(segment) => Ok(segment.to_string_lossy().to_string()),
+            _ => Err(anyhow!("root path should not contain . or ..")),
+        })
+        .collect()
+}
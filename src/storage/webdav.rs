@@ -0,0 +1,338 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use super::{
+    utils::{numbered_filename, split_root_path},
+    ConflictBehavior, StorageBackend, UploadTarget, UploadedItem,
+};
+use crate::utils::get_http_client;
+use anyhow::{anyhow, Context, Result};
+use bytes::Bytes;
+use percent_encoding::{percent_decode_str, utf8_percent_encode, AsciiSet, CONTROLS};
+use reqwest::{header, Body, Method, RequestBuilder, Response, StatusCode};
+use std::{collections::HashMap, sync::Arc};
+use tokio::{
+    sync::{mpsc, Mutex},
+    task::JoinHandle,
+};
+
+const SEGMENT_ENCODE_SET: &AsciiSet = &CONTROLS
+    .add(b' ')
+    .add(b'"')
+    .add(b'#')
+    .add(b'%')
+    .add(b'/')
+    .add(b'<')
+    .add(b'>')
+    .add(b'?')
+    .add(b'`')
+    .add(b'{')
+    .add(b'}');
+
+pub struct WebDavStorage {
+    pub url: String,
+    username: Option<String>,
+    password: Option<String>,
+    http_client: reqwest::Client,
+    // upload url -> streaming PUT request,
+    // None before the first part is uploaded or after the request ended early
+    uploads: Mutex<HashMap<String, Arc<Mutex<Option<WebDavUpload>>>>>,
+}
+
+// webdav has no upload session, parts are streamed into the body of a single PUT request
+struct WebDavUpload {
+    sender: mpsc::Sender<Bytes>,
+    handle: JoinHandle<reqwest::Result<Response>>,
+    current_length: u64,
+}
+
+impl WebDavStorage {
+    pub fn new(url: &str, username: Option<String>, password: Option<String>) -> Result<Self> {
+        Ok(Self {
+            url: url.trim_end_matches('/').to_string(),
+            username,
+            password,
+            http_client: get_http_client()?,
+            uploads: Mutex::new(HashMap::new()),
+        })
+    }
+
+    fn request(&self, method: Method, url: &str) -> RequestBuilder {
+        let mut request = self.http_client.request(method, url);
+
+        if let Some(username) = &self.username {
+            request = request.basic_auth(username, self.password.as_ref());
+        }
+
+        request
+    }
+
+    async fn create_collections(&self, segments: &[String]) -> Result<String> {
+        let mkcol = Method::from_bytes(b"MKCOL").context("failed to build method MKCOL")?;
+
+        let mut collection_url = self.url.clone();
+
+        for segment in segments {
+            collection_url.push('/');
+            collection_url.push_str(&utf8_percent_encode(segment, SEGMENT_ENCODE_SET).to_string());
+
+            let response = self
+                .request(mkcol.clone(), &format!("{}/", collection_url))
+                .send()
+                .await
+                .context("failed to send request for creating webdav collection")?;
+
+            // 405: Method Not Allowed, the collection already exists
+            if !response.status().is_success()
+                && response.status() != StatusCode::METHOD_NOT_ALLOWED
+            {
+                return Err(anyhow!(
+                    "failed to create webdav collection {}: {}",
+                    collection_url,
+                    response.status()
+                ));
+            }
+        }
+
+        Ok(collection_url)
+    }
+
+    async fn does_file_exist(&self, file_url: &str) -> Result<bool> {
+        let response = self
+            .request(Method::HEAD, file_url)
+            .send()
+            .await
+            .context("failed to send head request for webdav file")?;
+
+        match response.status() {
+            StatusCode::NOT_FOUND => Ok(false),
+            status if status.is_success() => Ok(true),
+            status => Err(anyhow!(
+                "failed to check webdav file {}: {}",
+                file_url,
+                status
+            )),
+        }
+    }
+
+    fn start_upload(&self, upload_url: &str, total_length: u64) -> WebDavUpload {
+        let (sender, receiver) = mpsc::channel::<Bytes>(1);
+
+        let body = futures::stream::unfold(receiver, |mut receiver| async move {
+            receiver
+                .recv()
+                .await
+                .map(|chunk| (Ok::<_, std::io::Error>(chunk), receiver))
+        });
+
+        // content length is set explicitly, some servers don't accept chunked transfer encoding
+        let request = self
+            .request(Method::PUT, upload_url)
+            .header(header::CONTENT_LENGTH, total_length)
+            .body(Body::wrap_stream(body));
+
+        let handle = tokio::spawn(async move { request.send().await });
+
+        WebDavUpload {
+            sender,
+            handle,
+            current_length: 0,
+        }
+    }
+}
+
+impl StorageBackend for WebDavStorage {
+    async fn create_upload_session(
+        &self,
+        root_path: &str,
+        filename: &str,
+        _total_length: u64,
+        conflict: ConflictBehavior,
+    ) -> Result<UploadTarget> {
+        let collection_url = self
+            .create_collections(&split_root_path(root_path)?)
+            .await?;
+
+        let mut uploads = self.uploads.lock().await;
+
+        let mut index = 0;
+        let upload_url = loop {
+            let name = numbered_filename(filename, index);
+            let file_url = format!(
+                "{}/{}",
+                collection_url,
+                utf8_percent_encode(&name, SEGMENT_ENCODE_SET)
+            );
+
+            index += 1;
+
+            // uploading files can't be replaced
+            if uploads.contains_key(&file_url) {
+                if conflict == ConflictBehavior::Rename {
+                    continue;
+                }
+
+                return Err(anyhow!("{} is being uploaded", name));
+            }
+
+            if conflict.check(&name, self.does_file_exist(&file_url).await?)? {
+                break file_url;
+            }
+        };
+
+        uploads.insert(upload_url.clone(), Arc::new(Mutex::new(None)));
+
+        tracing::debug!("created webdav upload session {}", upload_url);
+
+        Ok(UploadTarget {
+            upload_url,
+            current_length: 0,
+        })
+    }
+
+    async fn upload_part(
+        &self,
+        upload_url: &str,
+        buffer: &Bytes,
+        current_length: u64,
+        total_length: u64,
+    ) -> Result<Option<UploadedItem>> {
+        // kept in the map while sending, so that a paused or failed part can be sent again,
+        // other uploads only wait for the map lock
+        let upload = self
+            .uploads
+            .lock()
+            .await
+            .get(upload_url)
+            .cloned()
+            .ok_or_else(|| {
+                anyhow!("webdav upload session not found, retry the task to start over")
+            })?;
+
+        let mut upload = upload.lock().await;
+
+        if upload.is_none() && current_length != 0 {
+            return Err(anyhow!(
+                "webdav upload can only be started from the beginning, retry the task to start over"
+            ));
+        }
+
+        let webdav_upload =
+            upload.get_or_insert_with(|| self.start_upload(upload_url, total_length));
+
+        if webdav_upload.current_length != current_length {
+            return Err(anyhow!(
+                "webdav upload expects part from {}, got {}",
+                webdav_upload.current_length,
+                current_length
+            ));
+        }
+
+        // nothing is sent if the task is paused while waiting, so the part is sent again on resume
+        if webdav_upload.sender.send(buffer.clone()).await.is_err() {
+            // the request ended before receiving all parts, the body can't be continued
+            let webdav_upload = upload.take().unwrap();
+
+            let status = webdav_upload
+                .handle
+                .await
+                .context("failed to join webdav upload handle")?
+                .context("failed to upload file to webdav")?
+                .status();
+
+            return Err(anyhow!(
+                "webdav upload ended unexpectedly at {} bytes: {}",
+                webdav_upload.current_length,
+                status
+            ));
+        }
+
+        webdav_upload.current_length += buffer.len() as u64;
+
+        if webdav_upload.current_length < total_length {
+            return Ok(None);
+        }
+
+        let webdav_upload = upload.take().unwrap();
+
+        // close the body
+        drop(webdav_upload.sender);
+
+        let response = webdav_upload
+            .handle
+            .await
+            .context("failed to join webdav upload handle")?
+            .context("failed to upload file to webdav")?;
+
+        if !response.status().is_success() {
+            return Err(anyhow!(
+                "failed to upload file to webdav: {}",
+                response.status()
+            ));
+        }
+
+        self.uploads.lock().await.remove(upload_url);
+
+        let name = upload_url
+            .rsplit('/')
+            .next()
+            .map(|name| percent_decode_str(name).decode_utf8_lossy().to_string())
+            .ok_or_else(|| anyhow!("uploaded file has no name"))?;
+
+        tracing::info!("saved file to {}", upload_url);
+
+        Ok(Some(UploadedItem {
+            name,
+            id: None,
+            size: total_length,
+        }))
+    }
+
+    async fn get_resume_offset(&self, upload_url: &str) -> Result<u64> {
+        let upload = self
+            .uploads
+            .lock()
+            .await
+            .get(upload_url)
+            .cloned()
+            .ok_or_else(|| {
+                anyhow!("webdav upload session not found, retry the task to start over")
+            })?;
+
+        let current_length = upload
+            .lock()
+            .await
+            .as_ref()
+            .map_or(0, |upload| upload.current_length);
+
+        Ok(current_length)
+    }
+
+    async fn get_free_space(&self) -> Result<Option<u64>> {
+        Ok(None)
+    }
+
+    async fn cancel_upload(&self, upload_url: &str) -> Result<()> {
+        let upload = self.uploads.lock().await.remove(upload_url);
+
+        if let Some(upload) = upload {
+            let upload = upload.lock().await.take();
+
+            if let Some(upload) = upload {
+                upload.handle.abort();
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn validate_dir(&self, root_path: &str) -> Result<()> {
+        split_root_path(root_path)?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,195 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+// memory shared by the transfers of all tasks, parts wait for space instead of being over-allocated
+
+use anyhow::{anyhow, Context, Result};
+use bytes::{Bytes, BytesMut};
+use std::{
+    ops::{Deref, DerefMut},
+    sync::{Arc, Mutex},
+};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+// buffers that are not in use, each keeps the budget of its capacity
+type FreeBuffers = Arc<Mutex<Vec<(BytesMut, OwnedSemaphorePermit)>>>;
+
+#[derive(Clone)]
+pub struct BufferPool {
+    // bytes
+    limit: usize,
+    // one permit for each byte of allocated buffers
+    budget: Arc<Semaphore>,
+    free_buffers: FreeBuffers,
+}
+
+impl BufferPool {
+    pub fn new(limit: usize) -> Self {
+        Self {
+            limit,
+            budget: Arc::new(Semaphore::new(limit)),
+            free_buffers: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+
+    // waits until there is enough memory left for the buffer
+    pub async fn acquire(&self, size: usize) -> Result<PooledBuffer> {
+        if size > self.limit {
+            return Err(anyhow!(
+                "part of {} bytes exceeds memory_limit of {} bytes",
+                size,
+                self.limit
+            ));
+        }
+
+        if let Some((buffer, permit)) = self.take_free_buffer(size) {
+            return Ok(self.pooled_buffer(buffer, permit));
+        }
+
+        let permit = if let Ok(permit) = self.budget.clone().try_acquire_many_owned(size as u32) {
+            permit
+        } else {
+            // free buffers of other sizes may hold the budget
+            self.free_buffers.lock().unwrap().clear();
+
+            self.budget
+                .clone()
+                .acquire_many_owned(size as u32)
+                .await
+                .context("failed to acquire memory budget")?
+        };
+
+        Ok(self.pooled_buffer(BytesMut::with_capacity(size), permit))
+    }
+
+    fn take_free_buffer(&self, size: usize) -> Option<(BytesMut, OwnedSemaphorePermit)> {
+        let mut free_buffers = self.free_buffers.lock().unwrap();
+
+        let index = free_buffers
+            .iter()
+            .position(|(buffer, _)| buffer.capacity() >= size)?;
+
+        Some(free_buffers.swap_remove(index))
+    }
+
+    fn pooled_buffer(&self, buffer: BytesMut, permit: OwnedSemaphorePermit) -> PooledBuffer {
+        PooledBuffer {
+            buffer,
+            permit: Some(permit),
+            free_buffers: self.free_buffers.clone(),
+        }
+    }
+}
+
+// returned to the pool when dropped, or when the last clone of the frozen part is dropped
+pub struct PooledBuffer {
+    buffer: BytesMut,
+    permit: Option<OwnedSemaphorePermit>,
+    free_buffers: FreeBuffers,
+}
+
+impl PooledBuffer {
+    // the part is shared with retries without copying, and keeps the budget until it's dropped
+    pub fn freeze(self) -> Bytes {
+        Bytes::from_owner(self)
+    }
+}
+
+impl AsRef<[u8]> for PooledBuffer {
+    fn as_ref(&self) -> &[u8] {
+        &self.buffer
+    }
+}
+
+impl Deref for PooledBuffer {
+    type Target = BytesMut;
+
+    fn deref(&self) -> &Self::Target {
+        &self.buffer
+    }
+}
+
+impl DerefMut for PooledBuffer {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.buffer
+    }
+}
+
+impl Drop for PooledBuffer {
+    fn drop(&mut self) {
+        let Some(permit) = self.permit.take() else {
+            return;
+        };
+
+        // the buffer is never split, so the capacity is kept without allocating
+        let mut buffer = std::mem::take(&mut self.buffer);
+        buffer.clear();
+
+        self.free_buffers.lock().unwrap().push((buffer, permit));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use tokio::time::timeout;
+
+    const WAIT: Duration = Duration::from_millis(100);
+
+    #[tokio::test]
+    async fn budget_blocks_past_limit() {
+        let pool = BufferPool::new(10);
+
+        assert!(pool.acquire(11).await.is_err());
+
+        let buffer = pool.acquire(6).await.unwrap();
+        assert!(timeout(WAIT, pool.acquire(6)).await.is_err());
+
+        drop(buffer);
+        assert!(timeout(WAIT, pool.acquire(6)).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn released_buffer_is_reused() {
+        let pool = BufferPool::new(10);
+
+        let mut buffer = pool.acquire(8).await.unwrap();
+        buffer.extend_from_slice(b"part");
+        let ptr = buffer.as_ptr();
+        drop(buffer);
+
+        let buffer = pool.acquire(4).await.unwrap();
+        assert_eq!(buffer.as_ptr(), ptr);
+        assert!(buffer.is_empty());
+        assert_eq!(pool.budget.available_permits(), 2);
+    }
+
+    #[tokio::test]
+    async fn frozen_part_keeps_budget() {
+        let pool = BufferPool::new(10);
+
+        let mut buffer = pool.acquire(8).await.unwrap();
+        buffer.extend_from_slice(b"part");
+        let ptr = buffer.as_ptr();
+
+        // like a part being uploaded with a clone kept for retries
+        let part = buffer.freeze();
+        let retry = part.clone();
+        drop(part);
+
+        assert!(pool.free_buffers.lock().unwrap().is_empty());
+        assert!(timeout(WAIT, pool.acquire(8)).await.is_err());
+        assert_eq!(pool.budget.available_permits(), 2);
+
+        assert_eq!(&retry[..], b"part");
+        drop(retry);
+
+        let buffer = timeout(WAIT, pool.acquire(8)).await.unwrap().unwrap();
+        assert_eq!(buffer.as_ptr(), ptr);
+    }
+}
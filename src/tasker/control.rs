@@ -0,0 +1,220 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+// control tasks from the dashboard and the buttons under indicators
+
+use super::{
+    progress::strip_indicator_progress,
+    session::TaskRecord,
+    tasks::{self, InsertTask, TaskStatus},
+};
+use crate::{
+    client::utils::chat_from_hex,
+    error::ResultExt,
+    handlers::utils::preflight::{check_drive_space, check_user_limits},
+    listener::{with_task_buttons, TaskButtons},
+    state::AppState,
+    storage::{StorageBackend, UploadTarget},
+};
+use anyhow::{anyhow, Context, Result};
+use grammers_client::InputMessage;
+
+// appended to the indicator by pause_task and removed after resuming
+const PAUSED_SUFFIX: &str = "\n\nPaused.";
+
+pub async fn cancel_task(state: AppState, id: i64) -> Result<()> {
+    let session = &state.task_session;
+
+    let task = session
+        .get_task(id)
+        .await?
+        .ok_or_else(|| anyhow!("task {} not found", id))?;
+
+    let mut task_aborters = session.task_aborters.lock().await;
+    let task_aborter = task_aborters.remove(&(task.chat_id, task.message_indicator_id));
+    drop(task_aborters);
+
+    if let Some(task_aborter) = task_aborter {
+        // the upload is cancelled and recorded by the task handler
+        task_aborter.abort();
+    } else {
+        state
+            .storage(&task.storage)?
+            .cancel_upload(&task.upload_url)
+            .await
+            .trace();
+
+        session
+            .add_history(task.clone(), TaskStatus::Cancelled, None)
+            .await;
+    }
+
+    session.delete_task(id).await?;
+
+    edit_message_indicator(&task, &state, "Cancelled.", TaskButtons::Stopped).await?;
+
+    Ok(())
+}
+
+// the upload session is kept, so the task goes on from where it stopped after resuming
+pub async fn pause_task(state: AppState, id: i64) -> Result<()> {
+    let session = &state.task_session;
+
+    let task = session
+        .get_task(id)
+        .await?
+        .ok_or_else(|| anyhow!("task {} not found", id))?;
+
+    if !matches!(
+        task.status,
+        TaskStatus::Waiting | TaskStatus::Fetched | TaskStatus::Started
+    ) {
+        return Err(anyhow!("task {} is {}", id, task.status));
+    }
+
+    // tasks are fetched with the aborters locked, so a fetched task always has one
+    let mut task_aborters = session.task_aborters.lock().await;
+    session.set_task_status(id, TaskStatus::Paused).await?;
+    let task_aborter = task_aborters.remove(&(task.chat_id, task.message_indicator_id));
+    drop(task_aborters);
+
+    if let Some(task_aborter) = task_aborter {
+        task_aborter.pause();
+    }
+
+    edit_message_indicator(&task, &state, "Paused.", TaskButtons::Paused).await?;
+
+    Ok(())
+}
+
+pub async fn resume_task(state: AppState, id: i64) -> Result<()> {
+    let session = &state.task_session;
+
+    let task = session
+        .get_task(id)
+        .await?
+        .ok_or_else(|| anyhow!("task {} not found", id))?;
+
+    if task.status != TaskStatus::Paused {
+        return Err(anyhow!("task {} is not paused", id));
+    }
+
+    session.set_task_status(id, TaskStatus::Waiting).await?;
+    session.notify_tasker();
+
+    tracing::info!("resume task: {}", task.filename);
+
+    let chat_bot = chat_from_hex(&task.chat_bot_hex)?;
+
+    let message_indicator = state
+        .telegram_bot
+        .get_message(chat_bot, task.message_indicator_id)
+        .await?;
+
+    let text = message_indicator.text();
+    let response = text.strip_suffix(PAUSED_SUFFIX).unwrap_or(&text);
+    message_indicator
+        .edit(
+            task.message_indicator_id,
+            with_task_buttons(InputMessage::html(response), TaskButtons::Running),
+        )
+        .await
+        .context(response.to_string())?;
+
+    Ok(())
+}
+
+// failed or cancelled tasks are uploaded again from the beginning
+pub async fn retry_task(state: AppState, id: i64) -> Result<()> {
+    let session = &state.task_session;
+
+    // kept in history until the new task is inserted, so that a failed retry can be retried again
+    let TaskRecord { task, status, .. } = session
+        .get_history_record(id)
+        .await
+        .ok_or_else(|| anyhow!("task {} not found in history", id))?;
+
+    if status == TaskStatus::Completed {
+        return Err(anyhow!("task {} is already completed", id));
+    }
+
+    let total_length = task.total_length as u64;
+
+    // limits and free space may have changed since the task was inserted
+    check_user_limits(&state, task.user_id, total_length).await?;
+    check_drive_space(&state, &task.storage, total_length).await?;
+
+    let conflict = state.settings.get().await.conflict;
+
+    let UploadTarget {
+        upload_url,
+        current_length,
+    } = state
+        .storage(&task.storage)?
+        .create_upload_session(&task.root_path, &task.filename, total_length, conflict)
+        .await?;
+
+    state
+        .insert_task(InsertTask {
+            cmd_type: task.cmd_type.clone(),
+            filename: task.filename.clone(),
+            root_path: task.root_path.clone(),
+            url: task.url.clone(),
+            storage: task.storage.clone(),
+            upload_url,
+            current_length,
+            total_length,
+            chat_id: task.chat_id,
+            chat_bot_hex: task.chat_bot_hex.clone(),
+            chat_user_hex: task.chat_user_hex.clone(),
+            chat_origin_hex: task.chat_origin_hex.clone(),
+            message_id: task.message_id,
+            message_indicator_id: task.message_indicator_id,
+            message_origin_id: task.message_origin_id,
+            topic_id: task.topic_id,
+            auto_delete: task.auto_delete,
+            user_id: task.user_id,
+        })
+        .await?;
+
+    session.remove_history(id).await;
+
+    tracing::info!("retry task: {}", task.filename);
+
+    edit_message_indicator(&task, &state, "Retrying.", TaskButtons::Running).await?;
+
+    Ok(())
+}
+
+async fn edit_message_indicator(
+    task: &tasks::Model,
+    state: &AppState,
+    status: &str,
+    buttons: TaskButtons,
+) -> Result<()> {
+    let chat_bot = chat_from_hex(&task.chat_bot_hex)?;
+
+    let message_indicator = state
+        .telegram_bot
+        .get_message(chat_bot, task.message_indicator_id)
+        .await?;
+
+    let response = format!(
+        "{}\n\n{}",
+        strip_indicator_progress(&message_indicator.text()),
+        status
+    );
+    message_indicator
+        .edit(
+            task.message_indicator_id,
+            with_task_buttons(InputMessage::html(&response), buttons),
+        )
+        .await
+        .context(response)?;
+
+    Ok(())
+}
@@ -17,11 +17,11 @@ pub async fn handler(
     cancellation_token: CancellationToken,
     state: AppState,
 ) -> Result<()> {
-    let filename =
+    let uploaded_item =
         match multi_parts_uploader_from_tg_file(&task, progress.clone(), cancellation_token, state)
             .await
         {
-            Ok(filename) => filename,
+            Ok(uploaded_item) => uploaded_item,
             Err(e) => {
                 if e.downcast_ref::<TaskAbortError>().is_some() {
                     return Ok(());
@@ -30,7 +30,9 @@ pub async fn handler(
             }
         };
 
-    progress.update_filename(task.id, &filename).await?;
+    progress
+        .update_uploaded_item(task.id, &uploaded_item)
+        .await?;
 
     Ok(())
 }
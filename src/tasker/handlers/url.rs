@@ -6,13 +6,16 @@
 */
 
 use super::{tasks, transfer::multi_parts_uploader_from_url, Progress};
+use crate::state::AppState;
 use anyhow::Result;
 use std::sync::Arc;
 
-pub async fn handler(task: tasks::Model, progress: Arc<Progress>) -> Result<()> {
-    let filename = multi_parts_uploader_from_url(&task, progress.clone()).await?;
+pub async fn handler(task: tasks::Model, progress: Arc<Progress>, state: AppState) -> Result<()> {
+    let uploaded_item = multi_parts_uploader_from_url(&task, progress.clone(), state).await?;
 
-    progress.update_filename(task.id, &filename).await?;
+    progress
+        .update_uploaded_item(task.id, &uploaded_item)
+        .await?;
 
     Ok(())
 }
@@ -5,6 +5,8 @@
 :license: MIT, see LICENSE for more details.
 */
 
+mod buffer;
+mod control;
 mod handlers;
 mod progress;
 mod session;
@@ -13,21 +15,37 @@ mod transfer;
 
 use crate::{
     client::utils::chat_from_hex,
-    env::ENV,
     error::{ErrorExt, ResultExt, ResultUnwrapExt},
+    listener::{with_task_buttons, TaskButtons},
     message::TelegramMessage,
+    metrics::METRICS,
     state::AppState,
+    storage::{StorageBackend, StorageType},
+    webhook::TaskEvent,
 };
 use anyhow::{Context, Result};
+pub use buffer::{BufferPool, PooledBuffer};
+pub use control::{cancel_task, pause_task, resume_task, retry_task};
 use grammers_client::InputMessage;
 use path_slash::PathBufExt;
-use progress::Progress;
-pub use session::{BatchAborter, TaskAborter, TaskSession};
-use std::{path::Path, sync::Arc, time::Duration};
-pub use tasks::{CmdType, InsertTask};
-use tokio::sync::Semaphore;
+use progress::{strip_indicator_progress, Progress};
+pub use progress::{ProgressMode, ProgressStyle};
+pub use session::{BatchAborter, TaskAborter, TaskRecord, TaskSession};
+use std::{
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+pub use tasks::{CmdType, InsertTask, Model as TaskModel, TaskStatus};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
 use tokio_util::sync::CancellationToken;
 
+// tasks are dispatched when notified, polling only recovers missed ones
+const RECOVERY_POLL_INTERVAL: Duration = Duration::from_secs(10);
+
 pub struct Tasker {
     state: AppState,
     progress: Arc<Progress>,
@@ -52,20 +70,43 @@ impl Tasker {
             progress_clone.run().await;
         });
 
-        let handler_num = ENV.get().unwrap().task_handler_num;
+        let mut handler_num = self.state.settings.get().await.worker_num;
 
         let semaphore = Arc::new(Semaphore::new(handler_num as usize));
 
         loop {
-            self.handle_tasks(semaphore.clone()).await.trace();
+            // worker number may be changed by /settings
+            let new_handler_num = self.state.settings.get().await.worker_num;
+            resize_semaphore(&semaphore, handler_num, new_handler_num);
+            handler_num = new_handler_num;
+
+            // as many tasks as free workers
+            while let Ok(permit) = semaphore.clone().try_acquire_owned() {
+                match self.handle_task(permit).await {
+                    Ok(true) => {}
+                    Ok(false) => break,
+                    Err(e) => {
+                        e.trace();
+
+                        break;
+                    }
+                }
+            }
 
-            tokio::time::sleep(Duration::from_secs(1)).await;
+            // woken up by inserted tasks and finished workers, polling is only for recovery
+            tokio::select! {
+                () = self.session().wait_for_tasks() => {}
+                () = tokio::time::sleep(RECOVERY_POLL_INTERVAL) => {}
+            }
         }
     }
 
-    async fn handle_tasks(&self, semaphore: Arc<Semaphore>) -> Result<()> {
+    // returns false if no task is waiting
+    async fn handle_task(&self, permit: OwnedSemaphorePermit) -> Result<bool> {
+        let busy_user_ids = self.get_busy_user_ids().await?;
+
         let mut aborters = self.state.task_session.task_aborters.lock().await;
-        let task = self.session().fetch_task().await?;
+        let task = self.session().fetch_task(&busy_user_ids).await?;
 
         if let Some(task) = task {
             let chat = chat_from_hex(&task.chat_bot_hex)?;
@@ -84,10 +125,9 @@ impl Tasker {
 
                 tracing::info!("task {} aborted", task.filename);
 
-                return Ok(());
+                return Ok(true);
             };
 
-            let semaphore_clone = semaphore.clone();
             let state_clone = self.state.clone();
             let progress_clone = self.progress.clone();
 
@@ -99,31 +139,72 @@ impl Tasker {
                 &task.filename,
             );
             let cancellation_token = aborter.token.clone();
+            let paused = aborter.paused.clone();
             aborters.insert((chat.id, task.message_indicator_id), aborter);
             drop(aborters);
 
             tokio::spawn(async move {
-                let _permit = semaphore_clone
-                    .acquire()
-                    .await
-                    .context("failed to acquire semaphore for task handler")
-                    .unwrap_or_trace();
+                let _worker = METRICS.start_worker();
 
                 if let Err(e) = handler_dispatch(
                     task,
                     message.clone(),
                     progress_clone,
                     cancellation_token,
-                    state_clone,
+                    paused,
+                    state_clone.clone(),
                 )
                 .await
                 {
                     e.send(message).await.unwrap_both().trace();
                 }
+
+                // a worker is free now
+                drop(permit);
+                state_clone.task_session.notify_tasker();
             });
+
+            Ok(true)
+        } else {
+            Ok(false)
+        }
+    }
+
+    // users who have reached their limit of running tasks
+    async fn get_busy_user_ids(&self) -> Result<Vec<i64>> {
+        let running_tasks_number = self.session().get_running_tasks_number_by_user().await?;
+
+        let mut busy_user_ids = Vec::new();
+
+        for (user_id, running_number) in running_tasks_number {
+            let limits = self.state.usage_session.get_limits(user_id).await?;
+
+            if limits
+                .running
+                .is_some_and(|running_limit| running_number >= running_limit)
+            {
+                busy_user_ids.push(user_id);
+            }
         }
 
-        Ok(())
+        Ok(busy_user_ids)
+    }
+}
+
+fn resize_semaphore(semaphore: &Arc<Semaphore>, old_num: u8, new_num: u8) {
+    if new_num > old_num {
+        semaphore.add_permits((new_num - old_num) as usize);
+    } else if new_num < old_num {
+        // permits held by running tasks are taken back after they finish
+        let semaphore = semaphore.clone();
+        tokio::spawn(async move {
+            if let Ok(permits) = semaphore
+                .acquire_many_owned(u32::from(old_num - new_num))
+                .await
+            {
+                permits.forget();
+            }
+        });
     }
 }
 
@@ -132,22 +213,26 @@ async fn handler_dispatch(
     message: TelegramMessage,
     progress: Arc<Progress>,
     cancellation_token: CancellationToken,
+    paused: Arc<AtomicBool>,
     state: AppState,
 ) -> Result<()> {
     let session = &state.task_session;
     let telegram_bot = &state.telegram_bot;
-    let telegram_user = &state.telegram_user;
 
     session
         .set_task_status(task.id, tasks::TaskStatus::Started)
         .await?;
 
+    state.webhook.emit(TaskEvent::Started, &task).await;
+
+    let started_at = Instant::now();
+
     let fut = async {
         match task.cmd_type {
             CmdType::Url => {
                 tracing::info!("handle url task");
 
-                handlers::url::handler(task.clone(), progress).await
+                handlers::url::handler(task.clone(), progress, state.clone()).await
             }
             CmdType::File | CmdType::Link => {
                 tracing::info!("handle file or link task");
@@ -174,6 +259,9 @@ async fn handler_dispatch(
         }
     };
 
+    // the upload of the task stops here whatever the result is
+    session.flush_current_length(task.id).await?;
+
     let chat_id = message.chat().id();
 
     let mut task_aborters = state.task_session.task_aborters.lock().await;
@@ -187,16 +275,60 @@ async fn handler_dispatch(
     let batch_is_processing = batch_aborter.map_or(false, |batch_aborter| batch_aborter.processing);
     drop(batch_aborters);
 
+    if aborted && paused.load(Ordering::Relaxed) {
+        // set again in case that it's paused before being started
+        session
+            .set_task_status(task.id, tasks::TaskStatus::Paused)
+            .await?;
+
+        return Ok(());
+    }
+
     if aborted {
+        METRICS.observe_task_duration(&tasks::TaskStatus::Cancelled, started_at.elapsed());
+
+        state
+            .storage(&task.storage)?
+            .cancel_upload(&task.upload_url)
+            .await
+            .trace();
+
+        session
+            .add_history(task, tasks::TaskStatus::Cancelled, None)
+            .await;
+
         return Ok(());
     }
 
     match result {
         Ok(()) => {
+            METRICS.observe_task_duration(&tasks::TaskStatus::Completed, started_at.elapsed());
+
             session
                 .set_task_status(task.id, tasks::TaskStatus::Completed)
                 .await?;
 
+            // filename and length may be updated while uploading
+            let finished_task = session
+                .get_task(task.id)
+                .await?
+                .unwrap_or_else(|| task.clone());
+            if let Some(user_id) = finished_task.user_id {
+                state
+                    .usage_session
+                    .add_bytes(user_id, finished_task.total_length.max(0) as u64)
+                    .await
+                    .trace();
+            }
+
+            state
+                .webhook
+                .emit(TaskEvent::Completed, &finished_task)
+                .await;
+            session
+                .add_history(finished_task, tasks::TaskStatus::Completed, None)
+                .await;
+
             if task_aborter_exists {
                 if task.auto_delete {
                     let chat_bot = chat_from_hex(&task.chat_bot_hex)?;
@@ -216,7 +348,8 @@ async fn handler_dispatch(
                         batch_aborters.remove(&(chat_id, task.message_id));
                         drop(batch_aborters);
 
-                        telegram_user
+                        state
+                            .chat_client(&chat_user)
                             .delete_messages(chat_user, &[task.message_id])
                             .await?;
                     }
@@ -226,12 +359,36 @@ async fn handler_dispatch(
             }
         }
         Err(e) => {
+            let error = format!("{:#}", e);
+            let error_chain = e.chain().map(ToString::to_string).collect();
+
+            METRICS.observe_task_duration(&tasks::TaskStatus::Failed, started_at.elapsed());
+
             e.send(message.clone()).await.unwrap_both().trace();
 
+            // a retry starts a new upload, so what this one holds is released
+            state
+                .storage(&task.storage)?
+                .cancel_upload(&task.upload_url)
+                .await
+                .trace();
+
             session
                 .set_task_status(task.id, tasks::TaskStatus::Failed)
                 .await?;
 
+            let finished_task = session
+                .get_task(task.id)
+                .await?
+                .unwrap_or_else(|| task.clone());
+            state
+                .webhook
+                .emit(TaskEvent::Failed(error_chain), &finished_task)
+                .await;
+            session
+                .add_history(finished_task, tasks::TaskStatus::Failed, Some(error))
+                .await;
+
             handle_failed_task(task.clone(), state.clone()).await?;
         }
     }
@@ -242,6 +399,9 @@ async fn handler_dispatch(
 }
 
 async fn handle_completed_task(task: tasks::Model, state: AppState) -> Result<()> {
+    // filename may be changed by the storage if conflicted
+    let task = state.task_session.get_task(task.id).await?.unwrap_or(task);
+
     let chat_bot = chat_from_hex(&task.chat_bot_hex)?;
 
     let file_path_raw = Path::new(&task.root_path).join(task.filename);
@@ -253,12 +413,34 @@ async fn handle_completed_task(task: tasks::Model, state: AppState) -> Result<()
         .get_message(chat_bot, task.message_indicator_id)
         .await?;
 
-    let response = format!(
+    let mut response = format!(
         "{}\n\nDone.\nFile uploaded to {}\nSize {:.2}MB.",
-        message_indicator.text(),
+        strip_indicator_progress(&message_indicator.text()),
         file_path,
         task.total_length as f64 / 1024.0 / 1024.0
     );
+
+    // only onedrive items can be shared
+    let share_option = if task.storage == StorageType::OneDrive {
+        state.share_options.lock().await.get(&task.chat_id).cloned()
+    } else {
+        None
+    };
+
+    if let Some(share_option) = share_option {
+        match state
+            .onedrive
+            .create_share_link(task.onedrive_user.as_deref(), &file_path, &share_option)
+            .await
+        {
+            Ok(link) => response.push_str(&format!("\nShare link: {}", link)),
+            Err(e) => {
+                response.push_str("\nFailed to create share link.");
+
+                e.trace();
+            }
+        }
+    }
     message_indicator
         .edit(task.message_indicator_id, InputMessage::html(&response))
         .await
@@ -276,9 +458,15 @@ async fn handle_failed_task(task: tasks::Model, state: AppState) -> Result<()> {
         .get_message(chat_bot, task.message_indicator_id)
         .await?;
 
-    let response = format!("{}\n\nFailed.", message_indicator.text());
+    let response = format!(
+        "{}\n\nFailed.",
+        strip_indicator_progress(&message_indicator.text())
+    );
     message_indicator
-        .edit(task.message_id, InputMessage::html(&response))
+        .edit(
+            task.message_indicator_id,
+            with_task_buttons(InputMessage::html(&response), TaskButtons::Stopped),
+        )
         .await
         .context(response)?;
 
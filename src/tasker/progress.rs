@@ -9,27 +9,164 @@ use super::{session::ChatHex, tasks, TaskSession};
 use crate::{
     client::utils::chat_from_hex,
     error::{ErrorExt, ResultExt, ResultUnwrapExt},
+    listener::{with_task_buttons, TaskButtons},
     state::AppState,
+    storage::UploadedItem,
+    usage::format_bytes,
 };
 use anyhow::{anyhow, Context, Result};
 use grammers_client::InputMessage;
-use std::{collections::HashMap, time::Duration};
+use std::{
+    collections::{hash_map::Entry, HashMap, HashSet, VecDeque},
+    fmt::Display,
+    str::FromStr,
+    time::{Duration, Instant},
+};
+use tokio::sync::Mutex;
+
+// speed is measured over the latest samples, so that it follows changes of the network
+const RATE_WINDOW: Duration = Duration::from_secs(20);
+const PROGRESS_BAR_WIDTH: usize = 10;
+// current lengths are kept in memory and written to db at most this often
+const FLUSH_INTERVAL: Duration = Duration::from_secs(10);
+// progress appended to indicators starts with it
+const INDICATOR_PROGRESS_PREFIX: &str = "\n\nProgress: ";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ProgressStyle {
+    // a line for each task
+    Detailed,
+    // a line for all tasks
+    Compact,
+    // no progress message
+    Off,
+}
+
+impl FromStr for ProgressStyle {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "detailed" => Ok(Self::Detailed),
+            "compact" => Ok(Self::Compact),
+            "off" => Ok(Self::Off),
+            _ => Err(anyhow!(
+                "progress style should be one of detailed, compact and off"
+            )),
+        }
+    }
+}
+
+impl Display for ProgressStyle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Detailed => write!(f, "detailed"),
+            Self::Compact => write!(f, "compact"),
+            Self::Off => write!(f, "off"),
+        }
+    }
+}
+
+// where progress of tasks in a chat is shown
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum ProgressMode {
+    // a progress message for all tasks
+    #[default]
+    Aggregate,
+    // progress of each task in its indicator
+    Indicator,
+}
+
+impl FromStr for ProgressMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "aggregate" => Ok(Self::Aggregate),
+            "indicator" => Ok(Self::Indicator),
+            _ => Err(anyhow!(
+                "progress mode should be one of aggregate and indicator"
+            )),
+        }
+    }
+}
+
+impl Display for ProgressMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Aggregate => write!(f, "aggregate"),
+            Self::Indicator => write!(f, "indicator"),
+        }
+    }
+}
+
+// task id -> (time, current length) of the latest syncs
+#[derive(Default)]
+struct TransferRates(HashMap<i64, VecDeque<(Instant, i64)>>);
+
+impl TransferRates {
+    fn record(&mut self, task: &tasks::Model, now: Instant) {
+        let samples = self.0.entry(task.id).or_default();
+
+        samples.push_back((now, task.current_length));
+
+        while samples
+            .front()
+            .is_some_and(|(time, _)| now.duration_since(*time) > RATE_WINDOW)
+        {
+            samples.pop_front();
+        }
+    }
+
+    // bytes per second, none until the task is synced twice
+    fn get(&self, id: i64) -> Option<f64> {
+        let samples = self.0.get(&id)?;
+
+        let (first_time, first_length) = samples.front()?;
+        let (last_time, last_length) = samples.back()?;
+
+        let seconds = last_time.duration_since(*first_time).as_secs_f64();
+
+        if seconds > 0.0 {
+            Some((last_length - first_length).max(0) as f64 / seconds)
+        } else {
+            None
+        }
+    }
+
+    fn retain(&mut self, ids: &HashSet<i64>) {
+        self.0.retain(|id, _| ids.contains(id));
+    }
+}
+
+struct IndicatorText {
+    // text of the indicator without progress
+    text: String,
+    last_response: Option<String>,
+}
 
 pub struct Progress {
     state: AppState,
+    transfer_rates: Mutex<TransferRates>,
+    // task id -> indicator, for chats in indicator mode
+    indicator_texts: Mutex<HashMap<i64, IndicatorText>>,
 }
 
 impl Progress {
-    pub const fn new(state: AppState) -> Self {
-        Self { state }
+    pub fn new(state: AppState) -> Self {
+        Self {
+            state,
+            transfer_rates: Mutex::new(TransferRates::default()),
+            indicator_texts: Mutex::new(HashMap::new()),
+        }
     }
 
     fn session(&self) -> &TaskSession {
         &self.state.task_session
     }
 
-    pub async fn set_current_length(&self, id: i64, current_length: u64) -> Result<()> {
-        self.session().set_current_length(id, current_length).await
+    pub async fn set_current_length(&self, id: i64, current_length: u64) {
+        self.session().set_current_length(id, current_length).await;
     }
 
     pub async fn run(&self) {
@@ -37,6 +174,7 @@ impl Progress {
 
         let mut chat_progress_message_id = HashMap::new();
         let mut last_progress_response = String::new();
+        let mut flushed_at = Instant::now();
 
         loop {
             self.handle_chat_tasks_progress(
@@ -46,45 +184,53 @@ impl Progress {
             .await
             .trace();
 
+            if flushed_at.elapsed() >= FLUSH_INTERVAL {
+                self.session().flush_current_lengths().await.trace();
+
+                flushed_at = Instant::now();
+            }
+
             tokio::time::sleep(Duration::from_secs(2)).await;
         }
     }
 
     async fn handle_chat_tasks_progress(
         &self,
-        chat_progress_message_id: &mut HashMap<String, Option<i32>>,
+        chat_progress_message_id: &mut HashMap<ChatHex, Option<i32>>,
         last_progress_response: &mut String,
     ) -> Result<()> {
         let chat_tasks = self.session().get_chats_current_tasks().await?;
 
-        for (
-            ChatHex {
-                chat_bot_hex,
-                chat_user_hex,
-            },
-            current_tasks,
-        ) in chat_tasks
-        {
-            if chat_progress_message_id.get(&chat_bot_hex).is_none() {
-                chat_progress_message_id.insert(chat_bot_hex.clone(), None);
+        let progress_style = self.state.settings.get().await.progress_style;
+
+        self.record_transfer_rates(&chat_tasks).await;
+
+        // forum topics of a chat have their own progress messages
+        for (chat_hex, current_tasks) in chat_tasks {
+            if chat_progress_message_id.get(&chat_hex).is_none() {
+                chat_progress_message_id.insert(chat_hex.clone(), None);
             }
 
             let telegram_bot = &self.state.telegram_bot;
 
-            if !current_tasks.is_empty() {
-                let result = self
-                    .sync_chat_progress(
-                        &chat_bot_hex,
-                        &chat_user_hex,
-                        current_tasks,
-                        chat_progress_message_id,
-                        last_progress_response,
-                    )
-                    .await;
+            if !current_tasks.is_empty() && progress_style != ProgressStyle::Off {
+                let chat = chat_from_hex(&chat_hex.chat_bot_hex)?;
+
+                let result = match self.state.get_progress_mode(chat.id).await {
+                    ProgressMode::Aggregate => {
+                        self.sync_chat_progress(
+                            &chat_hex,
+                            &current_tasks,
+                            progress_style,
+                            chat_progress_message_id,
+                            last_progress_response,
+                        )
+                        .await
+                    }
+                    ProgressMode::Indicator => self.sync_indicator_progress(&current_tasks).await,
+                };
 
                 if let Err(e) = result {
-                    let chat = chat_from_hex(&chat_bot_hex)?;
-
                     e.send_chat(telegram_bot, chat).await.unwrap_both().trace();
                 }
             }
@@ -96,22 +242,42 @@ impl Progress {
         Ok(())
     }
 
+    async fn record_transfer_rates(&self, chat_tasks: &HashMap<ChatHex, Vec<tasks::Model>>) {
+        let now = Instant::now();
+
+        let mut transfer_rates = self.transfer_rates.lock().await;
+
+        let mut current_task_ids = HashSet::new();
+
+        for task in chat_tasks.values().flatten() {
+            transfer_rates.record(task, now);
+            current_task_ids.insert(task.id);
+        }
+
+        // finished tasks
+        transfer_rates.retain(&current_task_ids);
+        self.indicator_texts
+            .lock()
+            .await
+            .retain(|id, _| current_task_ids.contains(id));
+    }
+
     async fn remove_chats_without_tasks(
         &self,
-        chat_progress_message_id: &mut HashMap<String, Option<i32>>,
+        chat_progress_message_id: &mut HashMap<ChatHex, Option<i32>>,
     ) -> Result<()> {
         let telegram_bot = &self.state.telegram_bot;
 
         let mut chat_to_be_removed = Vec::new();
 
-        for (chat_bot_hex, progress_message_id) in chat_progress_message_id.iter() {
+        for (chat_hex, progress_message_id) in chat_progress_message_id.iter() {
             let has_started_tasks = self
                 .session()
-                .does_chat_has_started_tasks(chat_bot_hex)
+                .does_chat_has_started_tasks(&chat_hex.chat_bot_hex, chat_hex.topic_id)
                 .await?;
 
             if !has_started_tasks {
-                let chat = chat_from_hex(chat_bot_hex)?;
+                let chat = chat_from_hex(&chat_hex.chat_bot_hex)?;
 
                 if let Some(progress_message_id) = progress_message_id {
                     if let Err(e) = telegram_bot
@@ -124,12 +290,12 @@ impl Progress {
 
                 tracing::debug!("chat without tasks to be removed: {}", chat.id);
 
-                chat_to_be_removed.push(chat_bot_hex.clone());
+                chat_to_be_removed.push(chat_hex.clone());
             }
         }
 
-        for chat_bot_hex in chat_to_be_removed {
-            chat_progress_message_id.remove(&chat_bot_hex);
+        for chat_hex in chat_to_be_removed {
+            chat_progress_message_id.remove(&chat_hex);
         }
 
         Ok(())
@@ -137,33 +303,75 @@ impl Progress {
 
     async fn sync_chat_progress(
         &self,
-        chat_bot_hex: &str,
-        chat_user_hex: &str,
-        current_tasks: Vec<tasks::Model>,
-        chat_progress_message_id: &mut HashMap<String, Option<i32>>,
+        chat_hex: &ChatHex,
+        current_tasks: &[tasks::Model],
+        progress_style: ProgressStyle,
+        chat_progress_message_id: &mut HashMap<ChatHex, Option<i32>>,
         last_progress_response: &mut String,
     ) -> Result<()> {
         let telegram_bot = &self.state.telegram_bot;
-        let telegram_user = &self.state.telegram_user;
+        let chat = chat_from_hex(&chat_hex.chat_bot_hex)?;
 
-        let chat = chat_from_hex(chat_bot_hex)?;
+        let transfer_rates = self.transfer_rates.lock().await;
+
+        let (current_length, total_length, speed) =
+            current_tasks
+                .iter()
+                .fold((0, 0, None), |(current, total, speed), task| {
+                    let task_speed = transfer_rates.get(task.id);
+
+                    (
+                        current + task.current_length,
+                        total + task.total_length,
+                        // unknown until any task has a speed
+                        speed.map_or(task_speed, |speed| Some(speed + task_speed.unwrap_or(0.0))),
+                    )
+                });
 
         let mut response = "Progress:\n".to_string();
 
-        for task_progress in current_tasks {
+        if progress_style == ProgressStyle::Compact {
             response += &format!(
-                "\n<a href=\"https://t.me/c/{}/{}\">{}</a>: {:.2}/{:.2}MB",
-                chat.id,
-                task_progress.message_id,
-                task_progress.filename,
-                task_progress.current_length as f64 / 1024. / 1024.,
-                task_progress.total_length as f64 / 1024. / 1024.
+                "\n{} tasks\n{}",
+                current_tasks.len(),
+                format_progress(current_length, total_length, speed)
             );
+        } else {
+            for task_progress in current_tasks {
+                // messages in private chats can't be linked to
+                let filename = if chat.is_user() {
+                    task_progress.filename.clone()
+                } else {
+                    format!(
+                        "<a href=\"https://t.me/c/{}/{}\">{}</a>",
+                        chat.id, task_progress.message_id, task_progress.filename
+                    )
+                };
+
+                response += &format!(
+                    "\n{}\n{}",
+                    filename,
+                    format_progress(
+                        task_progress.current_length,
+                        task_progress.total_length,
+                        transfer_rates.get(task_progress.id)
+                    )
+                );
+            }
+
+            if current_tasks.len() > 1 {
+                response += &format!(
+                    "\n\nTotal\n{}",
+                    format_progress(current_length, total_length, speed)
+                );
+            }
         }
 
+        drop(transfer_rates);
+
         let pending_tasks_number = self
             .session()
-            .get_chat_pending_tasks_number(chat_bot_hex)
+            .get_chat_pending_tasks_number(&chat_hex.chat_bot_hex, chat_hex.topic_id)
             .await?;
 
         if pending_tasks_number > 0 {
@@ -171,22 +379,17 @@ impl Progress {
         }
 
         let progress_message_id = chat_progress_message_id
-            .get_mut(chat_bot_hex)
-            .ok_or_else(|| anyhow!("chat_bot_hex not in chat_progress_message_id"))?;
+            .get_mut(chat_hex)
+            .ok_or_else(|| anyhow!("chat_hex not in chat_progress_message_id"))?;
 
         match progress_message_id {
             Some(progress_message_id) => {
-                let chat_user = chat_from_hex(chat_user_hex)?;
+                let latest_message_id = self
+                    .get_latest_message_id(chat_hex, *progress_message_id)
+                    .await?;
 
-                let latest_message = telegram_user
-                    .iter_messages(chat_user)
-                    .limit(1)
-                    .next()
-                    .await
-                    .context("failed to iter messages for latest message")?;
-
-                if let Some(latest_message) = latest_message {
-                    if latest_message.id() == *progress_message_id {
+                if let Some(latest_message_id) = latest_message_id {
+                    if latest_message_id == *progress_message_id {
                         if *last_progress_response != response {
                             telegram_bot
                                 .edit_message(
@@ -203,7 +406,10 @@ impl Progress {
                             .await?;
 
                         let message = telegram_bot
-                            .send_message(chat, InputMessage::html(response.as_str()))
+                            .send_message(
+                                chat,
+                                InputMessage::html(response.as_str()).reply_to(chat_hex.topic_id),
+                            )
                             .await
                             .context(response.clone())?;
 
@@ -213,7 +419,10 @@ impl Progress {
             }
             None => {
                 let message = telegram_bot
-                    .send_message(chat, InputMessage::html(response.as_str()))
+                    .send_message(
+                        chat,
+                        InputMessage::html(response.as_str()).reply_to(chat_hex.topic_id),
+                    )
                     .await
                     .context(response.clone())?;
 
@@ -226,7 +435,152 @@ impl Progress {
         Ok(())
     }
 
-    pub async fn update_filename(&self, id: i64, filename: &str) -> Result<()> {
-        self.session().update_filename(id, filename).await
+    // the progress of each task replaces the previous one in its indicator
+    async fn sync_indicator_progress(&self, current_tasks: &[tasks::Model]) -> Result<()> {
+        let telegram_bot = &self.state.telegram_bot;
+
+        for task in current_tasks {
+            let chat = chat_from_hex(&task.chat_bot_hex)?;
+
+            let speed = self.transfer_rates.lock().await.get(task.id);
+
+            let mut indicator_texts = self.indicator_texts.lock().await;
+
+            let indicator_text = match indicator_texts.entry(task.id) {
+                Entry::Occupied(entry) => entry.into_mut(),
+                Entry::Vacant(entry) => {
+                    let message_indicator = telegram_bot
+                        .get_message(chat, task.message_indicator_id)
+                        .await?;
+
+                    entry.insert(IndicatorText {
+                        text: strip_indicator_progress(&message_indicator.text()).to_string(),
+                        last_response: None,
+                    })
+                }
+            };
+
+            let response = format!(
+                "{}{}{}",
+                indicator_text.text,
+                INDICATOR_PROGRESS_PREFIX,
+                format_progress(task.current_length, task.total_length, speed)
+            );
+
+            // telegram refuses to edit a message without changes
+            if indicator_text.last_response.as_ref() == Some(&response) {
+                continue;
+            }
+
+            indicator_text.last_response = Some(response.clone());
+            drop(indicator_texts);
+
+            telegram_bot
+                .edit_message(
+                    chat,
+                    task.message_indicator_id,
+                    with_task_buttons(InputMessage::html(&response), TaskButtons::Running),
+                )
+                .await
+                .context(response)?;
+        }
+
+        Ok(())
+    }
+
+    async fn get_latest_message_id(
+        &self,
+        chat_hex: &ChatHex,
+        progress_message_id: i32,
+    ) -> Result<Option<i32>> {
+        let chat_user = chat_from_hex(&chat_hex.chat_user_hex)?;
+
+        // bots can't read the history, messages received in private chats are recorded instead
+        if chat_user.is_user() {
+            let latest_message_id = self
+                .state
+                .private_last_message_ids
+                .lock()
+                .await
+                .get(&chat_user.id)
+                .map_or(progress_message_id, |id| (*id).max(progress_message_id));
+
+            return Ok(Some(latest_message_id));
+        }
+
+        // the latest message of the whole chat may be in another topic
+        if let Some(topic_id) = chat_hex.topic_id {
+            return self
+                .state
+                .telegram_user
+                .get_latest_topic_message_id(chat_user, topic_id)
+                .await;
+        }
+
+        let latest_message = self
+            .state
+            .telegram_user
+            .iter_messages(chat_user)
+            .limit(1)
+            .next()
+            .await
+            .context("failed to iter messages for latest message")?;
+
+        Ok(latest_message.map(|message| message.id()))
+    }
+
+    pub async fn update_uploaded_item(&self, id: i64, uploaded_item: &UploadedItem) -> Result<()> {
+        self.session().update_uploaded_item(id, uploaded_item).await
+    }
+}
+
+// text of an indicator before progress is appended to it in indicator mode
+pub fn strip_indicator_progress(text: &str) -> &str {
+    text.rfind(INDICATOR_PROGRESS_PREFIX)
+        .map_or(text, |index| &text[..index])
+}
+
+// like ▓▓▓▓░░░░░░ 40.0% 40.00MB/100.00MB 2.00MB/s 30s left
+fn format_progress(current_length: i64, total_length: i64, speed: Option<f64>) -> String {
+    let current_length = current_length.max(0) as u64;
+    let total_length = total_length.max(0) as u64;
+
+    let ratio = if total_length > 0 {
+        (current_length as f64 / total_length as f64).min(1.0)
+    } else {
+        0.0
+    };
+
+    let filled = (ratio * PROGRESS_BAR_WIDTH as f64).round() as usize;
+
+    let mut progress = format!(
+        "{}{} {:.1}% {}/{}",
+        "▓".repeat(filled),
+        "░".repeat(PROGRESS_BAR_WIDTH - filled),
+        ratio * 100.0,
+        format_bytes(current_length),
+        format_bytes(total_length)
+    );
+
+    if let Some(speed) = speed {
+        progress += &format!(" {}/s", format_bytes(speed as u64));
+
+        if speed > 0.0 {
+            let seconds_left = total_length.saturating_sub(current_length) as f64 / speed;
+
+            progress += &format!(" {} left", format_duration(seconds_left as u64));
+        }
+    }
+
+    progress
+}
+
+fn format_duration(seconds: u64) -> String {
+    if seconds < 60 {
+        format!("{}s", seconds)
+    } else if seconds < 60 * 60 {
+        format!("{}m{:02}s", seconds / 60, seconds % 60)
+    } else {
+        format!("{}h{:02}m", seconds / 60 / 60, seconds / 60 % 60)
     }
 }
@@ -6,23 +6,46 @@
 */
 
 use super::tasks::{self, InsertTask, TaskStatus};
+use crate::{
+    storage::{StorageType, UploadedItem},
+    utils::get_current_timestamp,
+};
 use anyhow::{Context, Ok, Result};
 use sea_orm::{
-    sea_query::Expr, ActiveValue, ColumnTrait, Condition, ConnectionTrait, DatabaseConnection,
-    EntityName, EntityTrait, PaginatorTrait, QueryFilter, Schema, Set,
+    sea_query::{Expr, SimpleExpr},
+    ActiveValue, ColumnTrait, Condition, ConnectionTrait, DatabaseConnection, EntityName,
+    EntityTrait, PaginatorTrait, QueryFilter, QueryOrder, Schema, Set, TransactionTrait,
+};
+use std::{
+    collections::{HashMap, VecDeque},
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+use tokio::{
+    fs,
+    sync::{Mutex, Notify},
 };
-use std::{collections::HashMap, path::Path, sync::Arc};
-use tokio::{fs, sync::Mutex};
 use tokio_util::sync::CancellationToken;
 
 // (chat id, message indicator id) -> aborter
 pub type TaskAborters = Arc<Mutex<HashMap<(i64, i32), TaskAborter>>>;
 pub type BatchAborters = Arc<Mutex<HashMap<(i64, i32), BatchAborter>>>;
 
+// finished tasks are deleted from db, only the latest ones are kept in memory
+const HISTORY_LIMIT: usize = 100;
+
 pub struct TaskSession {
     connection: DatabaseConnection,
     pub task_aborters: TaskAborters,
     pub batch_aborters: BatchAborters,
+    history: Mutex<VecDeque<TaskRecord>>,
+    // task id -> current length not written to db yet, updated for each chunk
+    current_lengths: Mutex<HashMap<i64, u64>>,
+    // wakes up the tasker when a task may be ready to run
+    task_notify: Notify,
 }
 
 impl TaskSession {
@@ -36,14 +59,29 @@ impl TaskSession {
         let connection = Self::connect_db(session_path).await?;
         let task_aborters = Arc::new(Mutex::new(HashMap::new()));
         let batch_aborters = Arc::new(Mutex::new(HashMap::new()));
+        let history = Mutex::new(VecDeque::new());
+        let current_lengths = Mutex::new(HashMap::new());
+        let task_notify = Notify::new();
 
         Ok(Self {
             connection,
             task_aborters,
             batch_aborters,
+            history,
+            current_lengths,
+            task_notify,
         })
     }
 
+    // the notification is kept if the tasker is not waiting, so it won't be missed
+    pub fn notify_tasker(&self) {
+        self.task_notify.notify_one();
+    }
+
+    pub async fn wait_for_tasks(&self) {
+        self.task_notify.notified().await;
+    }
+
     async fn connect_db(path: &str) -> Result<DatabaseConnection> {
         let connection = sea_orm::Database::connect(format!("sqlite://{}?mode=rwc", path))
             .await
@@ -79,9 +117,21 @@ impl TaskSession {
         result.is_ok()
     }
 
-    pub async fn fetch_task(&self) -> Result<Option<tasks::Model>> {
+    // tasks of the excluded users are left waiting, like users with too many running tasks
+    pub async fn fetch_task(&self, excluded_user_ids: &[i64]) -> Result<Option<tasks::Model>> {
+        let mut condition = Condition::all().add(tasks::Column::Status.eq(TaskStatus::Waiting));
+
+        if !excluded_user_ids.is_empty() {
+            condition = condition.add(
+                Condition::any()
+                    .add(tasks::Column::UserId.is_null())
+                    .add(tasks::Column::UserId.is_not_in(excluded_user_ids.iter().copied())),
+            );
+        }
+
         let task = tasks::Entity::find()
-            .filter(tasks::Column::Status.eq(TaskStatus::Waiting))
+            .filter(condition)
+            .order_by_asc(tasks::Column::Id)
             .one(&self.connection)
             .await
             .context("failed to get a task")?;
@@ -101,6 +151,7 @@ impl TaskSession {
             filename,
             root_path,
             url,
+            storage,
             upload_url,
             current_length,
             total_length,
@@ -111,8 +162,12 @@ impl TaskSession {
             message_id,
             message_indicator_id,
             message_origin_id,
+            topic_id,
             auto_delete,
+            user_id,
         }: InsertTask,
+        onedrive_user: Option<String>,
+        onedrive_drive_id: Option<String>,
     ) -> Result<i64> {
         let insert_item = tasks::ActiveModel {
             id: ActiveValue::default(),
@@ -120,7 +175,9 @@ impl TaskSession {
             filename: Set(filename.to_string()),
             root_path: Set(root_path.to_string()),
             url: Set(url),
+            storage: Set(storage),
             upload_url: Set(upload_url.to_string()),
+            item_id: Set(None),
             current_length: Set(current_length as i64),
             total_length: Set(total_length as i64),
             chat_id: Set(chat_id),
@@ -130,8 +187,12 @@ impl TaskSession {
             message_id: Set(message_id),
             message_indicator_id: Set(message_indicator_id),
             message_origin_id: Set(message_origin_id),
+            topic_id: Set(topic_id),
             status: Set(TaskStatus::Waiting),
             auto_delete: Set(auto_delete),
+            user_id: Set(user_id),
+            onedrive_user: Set(onedrive_user),
+            onedrive_drive_id: Set(onedrive_drive_id),
         };
 
         let id = tasks::Entity::insert(insert_item)
@@ -140,9 +201,35 @@ impl TaskSession {
             .context("failed to insert url task")?
             .last_insert_id;
 
+        self.notify_tasker();
+
         Ok(id)
     }
 
+    pub async fn get_task(&self, id: i64) -> Result<Option<tasks::Model>> {
+        let mut task = tasks::Entity::find_by_id(id)
+            .one(&self.connection)
+            .await
+            .context("failed to get task")?;
+
+        self.apply_current_lengths(task.as_mut_slice()).await;
+
+        Ok(task)
+    }
+
+    // unfinished tasks in the order they were inserted
+    pub async fn get_tasks(&self) -> Result<Vec<tasks::Model>> {
+        let mut tasks = tasks::Entity::find()
+            .order_by_asc(tasks::Column::Id)
+            .all(&self.connection)
+            .await
+            .context("failed to get tasks")?;
+
+        self.apply_current_lengths(&mut tasks).await;
+
+        Ok(tasks)
+    }
+
     pub async fn set_task_status(&self, id: i64, status: TaskStatus) -> Result<()> {
         tasks::Entity::update_many()
             .filter(tasks::Column::Id.eq(id))
@@ -154,34 +241,75 @@ impl TaskSession {
         Ok(())
     }
 
-    pub async fn set_current_length(&self, id: i64, current_length: u64) -> Result<()> {
-        tasks::Entity::update_many()
-            .filter(tasks::Column::Id.eq(id))
-            .col_expr(
-                tasks::Column::CurrentLength,
-                Expr::value(current_length as i64),
-            )
-            .exec(&self.connection)
+    // only kept in memory until the next flush
+    pub async fn set_current_length(&self, id: i64, current_length: u64) {
+        self.current_lengths.lock().await.insert(id, current_length);
+    }
+
+    pub async fn flush_current_lengths(&self) -> Result<()> {
+        let current_lengths = std::mem::take(&mut *self.current_lengths.lock().await);
+
+        if current_lengths.is_empty() {
+            return Ok(());
+        }
+
+        let transaction = self
+            .connection
+            .begin()
+            .await
+            .context("failed to begin transaction for current lengths")?;
+
+        for (id, current_length) in current_lengths {
+            write_current_length(&transaction, id, current_length).await?;
+        }
+
+        transaction
+            .commit()
             .await
-            .context("failed to update current length")?;
+            .context("failed to commit current lengths")?;
+
+        Ok(())
+    }
+
+    // for finished or paused tasks
+    pub async fn flush_current_length(&self, id: i64) -> Result<()> {
+        let current_length = self.current_lengths.lock().await.remove(&id);
+
+        if let Some(current_length) = current_length {
+            write_current_length(&self.connection, id, current_length).await?;
+        }
 
         Ok(())
     }
 
+    // lengths in memory are newer than the ones in db
+    async fn apply_current_lengths(&self, tasks: &mut [tasks::Model]) {
+        let current_lengths = self.current_lengths.lock().await;
+
+        for task in tasks {
+            if let Some(current_length) = current_lengths.get(&task.id) {
+                task.current_length = *current_length as i64;
+            }
+        }
+    }
+
     pub async fn get_chats_current_tasks(&self) -> Result<HashMap<ChatHex, Vec<tasks::Model>>> {
         let mut chats = HashMap::new();
 
-        let tasks = tasks::Entity::find()
+        let mut tasks = tasks::Entity::find()
             .filter(tasks::Column::Status.eq(TaskStatus::Started))
             .all(&self.connection)
             .await
             .context("failed to get chat current tasks")?;
 
+        self.apply_current_lengths(&mut tasks).await;
+
         for task in tasks {
             chats
                 .entry(ChatHex {
                     chat_bot_hex: task.chat_bot_hex.clone(),
                     chat_user_hex: task.chat_user_hex.clone(),
+                    topic_id: task.topic_id,
                 })
                 .or_insert(Vec::new())
                 .push(task);
@@ -190,11 +318,16 @@ impl TaskSession {
         Ok(chats)
     }
 
-    pub async fn get_chat_pending_tasks_number(&self, chat_bot_hex: &str) -> Result<u64> {
+    pub async fn get_chat_pending_tasks_number(
+        &self,
+        chat_bot_hex: &str,
+        topic_id: Option<i32>,
+    ) -> Result<u64> {
         tasks::Entity::find()
             .filter(
                 Condition::all()
                     .add(tasks::Column::ChatBotHex.eq(chat_bot_hex))
+                    .add(topic_condition(topic_id))
                     .add(
                         Condition::any()
                             .add(tasks::Column::Status.eq(TaskStatus::Waiting))
@@ -206,11 +339,102 @@ impl TaskSession {
             .context("failed to get pending tasks number")
     }
 
-    pub async fn does_chat_has_started_tasks(&self, chat_bot_hex: &str) -> Result<bool> {
+    // bytes that still need to be uploaded to the drive by unfinished tasks,
+    // onedrive accounts and drives have their own quota
+    pub async fn get_queued_length(
+        &self,
+        storage: StorageType,
+        onedrive_user: Option<String>,
+        onedrive_drive_id: Option<String>,
+    ) -> Result<u64> {
+        let mut tasks = tasks::Entity::find()
+            .filter(
+                Condition::all()
+                    .add(tasks::Column::Storage.eq(storage))
+                    .add(onedrive_user.map_or_else(
+                        || tasks::Column::OnedriveUser.is_null(),
+                        |onedrive_user| tasks::Column::OnedriveUser.eq(onedrive_user),
+                    ))
+                    .add(onedrive_drive_id.map_or_else(
+                        || tasks::Column::OnedriveDriveId.is_null(),
+                        |onedrive_drive_id| tasks::Column::OnedriveDriveId.eq(onedrive_drive_id),
+                    ))
+                    .add(
+                        Condition::any()
+                            .add(tasks::Column::Status.eq(TaskStatus::Waiting))
+                            .add(tasks::Column::Status.eq(TaskStatus::Fetched))
+                            .add(tasks::Column::Status.eq(TaskStatus::Started))
+                            .add(tasks::Column::Status.eq(TaskStatus::Paused)),
+                    ),
+            )
+            .all(&self.connection)
+            .await
+            .context("failed to get queued tasks")?;
+
+        self.apply_current_lengths(&mut tasks).await;
+
+        let queued_length = tasks
+            .iter()
+            .map(|task| (task.total_length - task.current_length).max(0) as u64)
+            .sum();
+
+        Ok(queued_length)
+    }
+
+    // waiting, fetched, started and paused tasks of the user
+    pub async fn get_user_unfinished_tasks(&self, user_id: i64) -> Result<Vec<tasks::Model>> {
+        tasks::Entity::find()
+            .filter(
+                Condition::all().add(tasks::Column::UserId.eq(user_id)).add(
+                    Condition::any()
+                        .add(tasks::Column::Status.eq(TaskStatus::Waiting))
+                        .add(tasks::Column::Status.eq(TaskStatus::Fetched))
+                        .add(tasks::Column::Status.eq(TaskStatus::Started))
+                        .add(tasks::Column::Status.eq(TaskStatus::Paused)),
+                ),
+            )
+            .all(&self.connection)
+            .await
+            .context("failed to get user unfinished tasks")
+    }
+
+    // user id -> number of fetched and started tasks
+    pub async fn get_running_tasks_number_by_user(&self) -> Result<HashMap<i64, u64>> {
+        let tasks = tasks::Entity::find()
+            .filter(
+                Condition::all()
+                    .add(tasks::Column::UserId.is_not_null())
+                    .add(
+                        Condition::any()
+                            .add(tasks::Column::Status.eq(TaskStatus::Fetched))
+                            .add(tasks::Column::Status.eq(TaskStatus::Started)),
+                    ),
+            )
+            .all(&self.connection)
+            .await
+            .context("failed to get running tasks")?;
+
+        let mut running_tasks_number = HashMap::new();
+
+        for task in tasks {
+            if let Some(user_id) = task.user_id {
+                *running_tasks_number.entry(user_id).or_default() += 1;
+            }
+        }
+
+        Ok(running_tasks_number)
+    }
+
+    pub async fn does_chat_has_started_tasks(
+        &self,
+        chat_bot_hex: &str,
+        topic_id: Option<i32>,
+    ) -> Result<bool> {
         let has_started_tasks = tasks::Entity::find()
             .filter(
                 Condition::all()
                     .add(tasks::Column::ChatBotHex.eq(chat_bot_hex))
+                    .add(topic_condition(topic_id))
                     .add(tasks::Column::Status.eq(TaskStatus::Started)),
             )
             .count(&self.connection)
@@ -221,18 +445,80 @@ impl TaskSession {
         Ok(has_started_tasks)
     }
 
-    pub async fn update_filename(&self, id: i64, filename: &str) -> Result<()> {
+    // filename may be changed by the storage if conflicted
+    pub async fn update_uploaded_item(&self, id: i64, uploaded_item: &UploadedItem) -> Result<()> {
         tasks::Entity::update_many()
             .filter(tasks::Column::Id.eq(id))
-            .col_expr(tasks::Column::Filename, Expr::value(filename))
+            .col_expr(
+                tasks::Column::Filename,
+                Expr::value(uploaded_item.name.clone()),
+            )
+            .col_expr(tasks::Column::ItemId, Expr::value(uploaded_item.id.clone()))
             .exec(&self.connection)
             .await
-            .context("failed to update filename")?;
+            .context("failed to update uploaded item")?;
 
         Ok(())
     }
 
+    pub async fn add_history(&self, task: tasks::Model, status: TaskStatus, error: Option<String>) {
+        let mut history = self.history.lock().await;
+
+        if history.len() >= HISTORY_LIMIT {
+            history.pop_front();
+        }
+
+        history.push_back(TaskRecord {
+            task,
+            status,
+            error,
+            finished_at: get_current_timestamp(),
+        });
+    }
+
+    // latest first
+    pub async fn get_history(&self) -> Vec<TaskRecord> {
+        self.history.lock().await.iter().rev().cloned().collect()
+    }
+
+    pub async fn get_history_record(&self, id: i64) -> Option<TaskRecord> {
+        self.history
+            .lock()
+            .await
+            .iter()
+            .find(|record| record.task.id == id)
+            .cloned()
+    }
+
+    pub async fn remove_history(&self, id: i64) -> Option<TaskRecord> {
+        let mut history = self.history.lock().await;
+
+        let index = history.iter().position(|record| record.task.id == id)?;
+
+        history.remove(index)
+    }
+
+    // retried tasks keep their indicator, so the latest record is the one shown there
+    pub async fn get_history_from_message_indicator_id(
+        &self,
+        chat_id: i64,
+        message_indicator_id: i32,
+    ) -> Option<TaskRecord> {
+        self.history
+            .lock()
+            .await
+            .iter()
+            .rev()
+            .find(|record| {
+                record.task.chat_id == chat_id
+                    && record.task.message_indicator_id == message_indicator_id
+            })
+            .cloned()
+    }
+
     pub async fn delete_task(&self, id: i64) -> Result<()> {
+        self.current_lengths.lock().await.remove(&id);
+
         tasks::Entity::delete_by_id(id)
             .exec(&self.connection)
             .await
@@ -251,6 +537,8 @@ impl TaskSession {
 
         aborters_guard.clear();
 
+        self.current_lengths.lock().await.clear();
+
         tasks::Entity::delete_many()
             .exec(&self.connection)
             .await
@@ -259,6 +547,23 @@ impl TaskSession {
         Ok(())
     }
 
+    pub async fn get_task_from_message_indicator_id(
+        &self,
+        chat_id: i64,
+        message_indicator_id: i32,
+    ) -> Result<Option<tasks::Model>> {
+        let mut task = tasks::Entity::find()
+            .filter(tasks::Column::ChatId.eq(chat_id))
+            .filter(tasks::Column::MessageIndicatorId.eq(message_indicator_id))
+            .one(&self.connection)
+            .await
+            .context("failed to get task from message indicator id")?;
+
+        self.apply_current_lengths(task.as_mut_slice()).await;
+
+        Ok(task)
+    }
+
     pub async fn delete_task_from_message_indicator_id_if_exists(
         &self,
         chat_id: i64,
@@ -333,10 +638,46 @@ impl TaskSession {
     }
 }
 
-#[derive(Eq, PartialEq, Hash)]
+#[derive(Clone)]
+pub struct TaskRecord {
+    pub task: tasks::Model,
+    // completed, failed or cancelled
+    pub status: TaskStatus,
+    pub error: Option<String>,
+    pub finished_at: i64,
+}
+
+#[derive(Clone, Eq, PartialEq, Hash)]
 pub struct ChatHex {
     pub chat_bot_hex: String,
     pub chat_user_hex: String,
+    pub topic_id: Option<i32>,
+}
+
+async fn write_current_length<C: ConnectionTrait>(
+    connection: &C,
+    id: i64,
+    current_length: u64,
+) -> Result<()> {
+    tasks::Entity::update_many()
+        .filter(tasks::Column::Id.eq(id))
+        .col_expr(
+            tasks::Column::CurrentLength,
+            Expr::value(current_length as i64),
+        )
+        .exec(connection)
+        .await
+        .context("failed to update current length")?;
+
+    Ok(())
+}
+
+// tasks outside of forum topics have no topic id
+fn topic_condition(topic_id: Option<i32>) -> SimpleExpr {
+    topic_id.map_or_else(
+        || tasks::Column::TopicId.is_null(),
+        |topic_id| tasks::Column::TopicId.eq(topic_id),
+    )
 }
 
 pub struct TaskAborter {
@@ -345,6 +686,8 @@ pub struct TaskAborter {
     pub message_id: i32,
     filename: String,
     pub token: CancellationToken,
+    // the task is kept for resuming instead of being cancelled
+    pub paused: Arc<AtomicBool>,
 }
 
 impl TaskAborter {
@@ -355,6 +698,7 @@ impl TaskAborter {
             message_id,
             filename: filename.to_string(),
             token: CancellationToken::new(),
+            paused: Arc::new(AtomicBool::new(false)),
         }
     }
 
@@ -363,6 +707,13 @@ impl TaskAborter {
 
         self.token.cancel();
     }
+
+    pub fn pause(&self) {
+        tracing::info!("task {} paused", self.filename);
+
+        self.paused.store(true, Ordering::Relaxed);
+        self.token.cancel();
+    }
 }
 
 pub struct BatchAborter {
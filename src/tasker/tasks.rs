@@ -5,6 +5,7 @@
 :license: MIT, see LICENSE for more details.
 */
 
+use crate::storage::StorageType;
 use sea_orm::{
     entity::prelude::DeriveEntityModel,
     sea_query::{ArrayType, ValueType, ValueTypeErr},
@@ -23,8 +24,11 @@ pub struct Model {
     pub root_path: String,
     // for /url
     pub url: Option<String>,
-    // onedrive upload url
+    pub storage: StorageType,
+    // upload url of the storage backend
     pub upload_url: String,
+    // id of the uploaded item, if the storage backend has one
+    pub item_id: Option<String>,
     pub current_length: i64,
     pub total_length: i64,
     pub chat_id: i64,
@@ -41,8 +45,16 @@ pub struct Model {
     // message id of the origin message in the origin chat
     // for link
     pub message_origin_id: Option<i32>,
+    // forum topic the task was posted in, indicator and progress are sent there
+    pub topic_id: Option<i32>,
     pub status: TaskStatus,
     pub auto_delete: bool,
+    // telegram user who sent the task, none for tasks from http api
+    pub user_id: Option<i64>,
+    // onedrive account the upload session was created with, share links are created with it
+    pub onedrive_user: Option<String>,
+    // drive of the account the upload session was created in, none for the personal drive
+    pub onedrive_drive_id: Option<String>,
 }
 
 #[derive(Clone, Debug, EnumIter, DeriveRelation)]
@@ -129,8 +141,12 @@ pub enum TaskStatus {
     Fetched,
     // task started by handler
     Started,
+    // stopped by the pause button, the upload session is kept to be resumed
+    Paused,
     Completed,
     Failed,
+    // task cancelled from the dashboard
+    Cancelled,
 }
 
 impl ValueType for TaskStatus {
@@ -140,8 +156,10 @@ impl ValueType for TaskStatus {
                 "waiting" => Ok(Self::Waiting),
                 "fetched" => Ok(Self::Fetched),
                 "started" => Ok(Self::Started),
+                "paused" => Ok(Self::Paused),
                 "completed" => Ok(Self::Completed),
                 "failed" => Ok(Self::Failed),
+                "cancelled" => Ok(Self::Cancelled),
                 _ => Err(ValueTypeErr),
             },
             _ => Err(ValueTypeErr),
@@ -167,8 +185,10 @@ impl From<TaskStatus> for Value {
             TaskStatus::Waiting
             | TaskStatus::Fetched
             | TaskStatus::Started
+            | TaskStatus::Paused
             | TaskStatus::Completed
-            | TaskStatus::Failed => Self::String(Some(Box::new(value.to_string()))),
+            | TaskStatus::Failed
+            | TaskStatus::Cancelled => Self::String(Some(Box::new(value.to_string()))),
         }
     }
 }
@@ -181,10 +201,12 @@ impl TryGetable for TaskStatus {
             "waiting" => Ok(Self::Waiting),
             "fetched" => Ok(Self::Fetched),
             "started" => Ok(Self::Started),
+            "paused" => Ok(Self::Paused),
             "completed" => Ok(Self::Completed),
             "failed" => Ok(Self::Failed),
+            "cancelled" => Ok(Self::Cancelled),
             _ => Err(TryGetError::DbErr(DbErr::Type(format!(
-                "task status value should be one of waiting, started, completed and failed: {}",
+                "task status value should be one of waiting, fetched, started, paused, completed, failed and cancelled: {}",
                 value
             )))),
         }
@@ -197,8 +219,10 @@ impl Display for TaskStatus {
             Self::Waiting => write!(f, "waiting"),
             Self::Fetched => write!(f, "fetched"),
             Self::Started => write!(f, "started"),
+            Self::Paused => write!(f, "paused"),
             Self::Completed => write!(f, "completed"),
             Self::Failed => write!(f, "failed"),
+            Self::Cancelled => write!(f, "cancelled"),
         }
     }
 }
@@ -208,6 +232,7 @@ pub struct InsertTask {
     pub filename: String,
     pub root_path: String,
     pub url: Option<String>,
+    pub storage: StorageType,
     pub upload_url: String,
     pub current_length: u64,
     pub total_length: u64,
@@ -218,5 +243,7 @@ pub struct InsertTask {
     pub message_id: i32,
     pub message_indicator_id: i32,
     pub message_origin_id: Option<i32>,
+    pub topic_id: Option<i32>,
     pub auto_delete: bool,
+    pub user_id: Option<i64>,
 }
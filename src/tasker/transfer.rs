@@ -7,12 +7,18 @@
 
 use super::{tasks, Progress};
 use crate::{
-    client::utils::chat_from_hex, error::TaskAbortError, state::AppState, utils::get_http_client,
+    client::utils::chat_from_hex,
+    error::TaskAbortError,
+    metrics::{RetryKind, TransferSource, METRICS},
+    state::AppState,
+    storage::{Storage, StorageBackend, UploadedItem},
+    utils::get_http_client,
 };
-use anyhow::{anyhow, Context, Error, Result};
+use anyhow::{anyhow, Context, Result};
+use bytes::Bytes;
 use grammers_client::client::files::MAX_CHUNK_SIZE;
-use onedrive_api::{resource::DriveItem, UploadSession};
-use std::{collections::VecDeque, ops::Range, sync::Arc, time::Duration};
+use reqwest::{header, StatusCode};
+use std::{collections::VecDeque, sync::Arc, time::Duration};
 use tokio_util::sync::CancellationToken;
 
 const MAX_RETRIES: i32 = 5;
@@ -21,88 +27,121 @@ pub async fn multi_parts_uploader_from_url(
     tasks::Model {
         id,
         url,
+        storage: storage_type,
         upload_url,
-        current_length,
         total_length,
         ..
     }: &tasks::Model,
     progress: Arc<Progress>,
-) -> Result<String> {
-    const PART_SIZE: usize = 3276800;
+    state: AppState,
+) -> Result<UploadedItem> {
+    let part_size = state.settings.get().await.url_part_size as usize * 1024;
 
     let http_client = get_http_client()?;
 
     let url = url.clone().ok_or_else(|| anyhow!("url is none"))?;
 
-    let upload_session = UploadSession::from_upload_url(upload_url);
+    let storage = state.storage(storage_type)?;
 
-    let mut current_length = current_length.to_owned() as u64;
+    let mut current_length = storage.get_resume_offset(upload_url).await?;
     let total_length = total_length.to_owned() as u64;
 
     progress
         .set_current_length(id.to_owned(), current_length)
-        .await?;
+        .await;
+
+    let mut request = http_client.get(url);
+
+    if current_length > 0 {
+        request = request.header(header::RANGE, format!("bytes={}-", current_length));
+    }
 
-    let mut response = http_client
-        .get(url)
+    let mut response = request
         .send()
         .await
         .context("failed to send request for /url")?;
 
+    // the server may ignore the range and respond with the whole file
+    let mut skip_length = if response.status() == StatusCode::PARTIAL_CONTENT {
+        0
+    } else {
+        current_length
+    };
+
+    // the rest of a chunk that doesn't fit in the part, kept for the next one
+    let mut leftover = Bytes::new();
+
     let upload_response = loop {
-        let mut buffer = Vec::with_capacity(PART_SIZE);
+        let mut buffer = state.buffer_pool.acquire(part_size).await?;
 
-        while let Some(chunk) = response.chunk().await.context("failed to get chunk")? {
-            buffer.extend_from_slice(&chunk);
+        while buffer.len() < part_size {
+            let chunk = if leftover.is_empty() {
+                let Some(chunk) = response.chunk().await.context("failed to get chunk")? else {
+                    break;
+                };
 
-            if buffer.len() >= PART_SIZE {
-                break;
-            }
+                let skipped = skip_length.min(chunk.len() as u64);
+                skip_length -= skipped;
+
+                chunk.slice(skipped as usize..)
+            } else {
+                std::mem::take(&mut leftover)
+            };
+
+            let length = chunk.len().min(part_size - buffer.len());
+            buffer.extend_from_slice(&chunk[..length]);
+            leftover = chunk.slice(length..);
+        }
+
+        let buffer = buffer.freeze();
+
+        if buffer.is_empty() && current_length < total_length {
+            return Err(anyhow!(
+                "url response ended at {} of {} bytes",
+                current_length,
+                total_length
+            ));
         }
 
         tracing::debug!("downloaded chunk from url");
 
-        let upload_response = upload_file(
-            &upload_session,
-            &buffer,
-            current_length,
-            total_length,
-            &http_client,
-        )
-        .await?;
+        METRICS.add_downloaded_bytes(&TransferSource::Url, buffer.len());
+
+        let upload_response =
+            upload_part(&storage, upload_url, &buffer, current_length, total_length).await?;
+
+        METRICS.add_uploaded_bytes(storage_type, buffer.len());
 
         tracing::debug!("uploaded chunk from url");
 
         current_length += buffer.len() as u64;
         progress
             .set_current_length(id.to_owned(), current_length)
-            .await?;
+            .await;
 
         if current_length >= total_length {
             break upload_response;
         }
     };
 
-    let filename = upload_response
-        .ok_or_else(|| anyhow!("failed to get drive item after upload"))?
-        .name
-        .ok_or_else(|| anyhow!("drive item name not found"))?;
+    let uploaded_item =
+        upload_response.ok_or_else(|| anyhow!("failed to get uploaded item after upload"))?;
 
     tracing::info!(
         "uploaded file from url: {} size: {}",
-        filename,
+        uploaded_item.name,
         total_length
     );
 
-    Ok(filename)
+    Ok(uploaded_item)
 }
 
 pub async fn multi_parts_uploader_from_tg_file(
     tasks::Model {
         id,
         cmd_type,
+        storage: storage_type,
         upload_url,
-        current_length,
         total_length,
         chat_user_hex,
         chat_origin_hex,
@@ -113,27 +152,34 @@ pub async fn multi_parts_uploader_from_tg_file(
     progress: Arc<Progress>,
     cancellation_token: CancellationToken,
     state: AppState,
-) -> Result<String> {
-    const WORKER_COUNT: i32 = 4;
+) -> Result<UploadedItem> {
+    let worker_count = i32::from(state.settings.get().await.tg_part_chunks);
 
-    let http_client = get_http_client()?;
-
-    let upload_session = UploadSession::from_upload_url(upload_url);
+    let storage = state.storage(storage_type)?;
 
-    let mut current_length = current_length.to_owned() as u64;
     let total_length = total_length.to_owned() as u64;
 
+    // resume from the chunk that contains the resume offset
+    let mut current_chunk_num =
+        (storage.get_resume_offset(upload_url).await? / MAX_CHUNK_SIZE as u64) as i32;
+    let mut current_length = current_chunk_num as u64 * MAX_CHUNK_SIZE as u64;
+
     progress
         .set_current_length(id.to_owned(), current_length)
-        .await?;
+        .await;
 
     let mut upload_response = None;
 
     let telegram_user = &state.telegram_user;
     let chat = chat_from_hex(chat_user_hex)?;
 
-    let message = match cmd_type {
-        tasks::CmdType::File => telegram_user.get_message(chat, *message_id).await?,
+    // the media is downloaded by the client that got the message
+    let (message, client) = match cmd_type {
+        tasks::CmdType::File => {
+            let client = state.chat_client(&chat);
+
+            (client.get_message(chat, *message_id).await?, client)
+        }
         tasks::CmdType::Link => {
             let chat = chat_from_hex(
                 chat_origin_hex
@@ -145,7 +191,10 @@ pub async fn multi_parts_uploader_from_tg_file(
                 .as_ref()
                 .ok_or_else(|| anyhow!("message_id_origin is None"))?;
 
-            telegram_user.get_message(chat, *message_origin_id).await?
+            (
+                telegram_user.get_message(chat, *message_origin_id).await?,
+                telegram_user,
+            )
         }
         tasks::CmdType::Url => return Err(anyhow!("invalid cmd type")),
     };
@@ -163,158 +212,132 @@ pub async fn multi_parts_uploader_from_tg_file(
     } else {
         1
     };
-    let mut current_chunk_num = 0;
 
     while current_chunk_num < total_chunks_num {
-        let telegram_user_clone = telegram_user.clone();
-        let media_clone = media.clone();
+        let batch_chunks_num = worker_count.min(total_chunks_num - current_chunk_num);
+        // the last chunk may be shorter
+        let batch_length =
+            (batch_chunks_num as u64 * MAX_CHUNK_SIZE as u64).min(total_length - current_length);
 
-        let cancellation_token_clone = cancellation_token.clone();
+        // wait for memory before downloading the chunks
+        let mut buffer = state.buffer_pool.acquire(batch_length as usize).await?;
 
-        // create a worker
-        work_handles.push_back(tokio::spawn(async move {
-            let mut download = telegram_user_clone
-                .iter_download(media_clone.as_ref())
-                .skip_chunks(current_chunk_num);
+        for _ in 0..batch_chunks_num {
+            let client_clone = client.clone();
+            let media_clone = media.clone();
 
-            let fut = async {
-                let mut retries = 0;
+            let cancellation_token_clone = cancellation_token.clone();
 
-                loop {
-                    match download.next().await {
-                        Ok(chunk) => break Ok(chunk),
-                        Err(e) => {
-                            if retries <= MAX_RETRIES {
-                                tokio::time::sleep(Duration::from_secs(2)).await;
+            // create a worker
+            work_handles.push_back(tokio::spawn(async move {
+                let mut download = client_clone
+                    .iter_download(media_clone.as_ref())
+                    .skip_chunks(current_chunk_num);
 
-                                retries += 1;
+                let fut = async {
+                    let mut retries = 0;
 
-                                continue;
-                            }
+                    loop {
+                        match download.next().await {
+                            Ok(chunk) => break Ok(chunk),
+                            Err(e) => {
+                                if retries <= MAX_RETRIES {
+                                    METRICS.add_retry(&RetryKind::Download);
+
+                                    tokio::time::sleep(Duration::from_secs(2)).await;
+
+                                    retries += 1;
+
+                                    continue;
+                                }
 
-                            break Err(e);
+                                break Err(e);
+                            }
                         }
                     }
-                }
-            };
+                };
 
-            tokio::select! {
-                result = fut => result.context("failed to get next chunk from tg file downloader"),
-                () = cancellation_token_clone.cancelled() => Err(TaskAbortError.into())
-            }
-        }));
+                tokio::select! {
+                    result = fut => result.context("failed to get next chunk from tg file downloader"),
+                    () = cancellation_token_clone.cancelled() => Err(TaskAbortError.into())
+                }
+            }));
 
-        current_chunk_num += 1;
+            current_chunk_num += 1;
+        }
 
-        // once reached the max worker number, or the last chunk, wait for the workers to finish
         // onedrive needs the chunk to be uploaded sequentially in order
-        if current_chunk_num % WORKER_COUNT == 0 || current_chunk_num == total_chunks_num {
-            let mut chunk = Vec::new();
+        while let Some(handle) = work_handles.pop_front() {
+            let chunk_part = handle
+                .await
+                .context("failed to join handle")??
+                .ok_or_else(|| anyhow!("failed to get chunk from tg file downloader"))?;
 
-            while let Some(handle) = work_handles.pop_front() {
-                let mut chunk_part = handle
-                    .await
-                    .context("failed to join handle")??
-                    .ok_or_else(|| anyhow!("failed to get chunk from tg file downloader"))?;
+            buffer.extend_from_slice(&chunk_part);
+        }
 
-                chunk.append(&mut chunk_part);
-            }
+        let chunk = buffer.freeze();
 
-            tracing::debug!("downloaded chunk from telegram");
+        tracing::debug!("downloaded chunk from telegram");
 
-            upload_response = upload_file(
-                &upload_session,
-                &chunk,
-                current_length,
-                total_length,
-                &http_client,
-            )
-            .await?;
+        METRICS.add_downloaded_bytes(&TransferSource::Telegram, chunk.len());
 
-            tracing::debug!("uploaded chunk from telegram");
+        upload_response =
+            upload_part(&storage, upload_url, &chunk, current_length, total_length).await?;
 
-            current_length += chunk.len() as u64;
-            progress
-                .set_current_length(id.to_owned(), current_length)
-                .await?;
-        }
+        METRICS.add_uploaded_bytes(storage_type, chunk.len());
+
+        tracing::debug!("uploaded chunk from telegram");
+
+        current_length += chunk.len() as u64;
+        progress
+            .set_current_length(id.to_owned(), current_length)
+            .await;
     }
 
-    let filename = upload_response
-        .ok_or_else(|| anyhow!("failed to get drive item after upload"))?
-        .name
-        .ok_or_else(|| anyhow!("drive item name not found"))?;
+    let uploaded_item =
+        upload_response.ok_or_else(|| anyhow!("failed to get uploaded item after upload"))?;
 
     tracing::info!(
         "uploaded file from telegram: {} size: {}",
-        filename,
+        uploaded_item.name,
         total_length
     );
 
-    Ok(filename)
+    Ok(uploaded_item)
 }
 
-async fn upload_file(
-    upload_session: &UploadSession,
-    buffer: &[u8],
+async fn upload_part(
+    storage: &Storage<'_>,
+    upload_url: &str,
+    buffer: &Bytes,
     current_length: u64,
     total_length: u64,
-    http_client: &reqwest::Client,
-) -> Result<Option<DriveItem>> {
-    let mut upload_response = None;
-
+) -> Result<Option<UploadedItem>> {
     let mut tries = 0;
 
     loop {
         tries += 1;
 
-        let result = upload_session
-            .upload_part(
-                buffer.to_owned(),
-                Range {
-                    start: current_length,
-                    end: current_length + buffer.len() as u64,
-                },
-                total_length,
-                http_client,
-            )
-            .await;
-
-        match result {
-            Ok(response) => {
-                upload_response = response;
-
-                break;
-            }
+        match storage
+            .upload_part(upload_url, buffer, current_length, total_length)
+            .await
+        {
+            Ok(upload_response) => return Ok(upload_response),
             Err(e) => {
-                if let Some(status_code) = e.status_code() {
-                    // normal
-                    // 408: Request Timeout
-                    // 500: Internal Server Error
-                    // 502: Bad Gateway
-                    // 503: Service Unavailable
-                    // 504: Gateway Timeout
-                    // 416: Requested Range Not Satisfiable, probably because the fragment has already been received
-                    //
-                    // probably has some problem
-                    // 409: Conflict, probably caused by rename, too many files with the same name uploaded at once
-                    // 404: Not Found, probably because the item has already been uploaded
-
-                    if status_code.as_u16() == 416 {
-                        break;
-                    }
-                }
-
+                // most failures are timeouts or temporary server errors
                 if tries < MAX_RETRIES {
+                    tracing::debug!("retry uploading part: {:?}", e);
+
+                    METRICS.add_retry(&RetryKind::Upload);
+
                     tokio::time::sleep(Duration::from_secs(2)).await;
 
                     continue;
                 }
 
-                return Err(Error::from(e)).context("failed to upload part");
+                return Err(e);
             }
         }
     }
-
-    Ok(upload_response)
 }
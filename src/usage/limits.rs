@@ -0,0 +1,32 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use sea_orm::{
+    entity::prelude::DeriveEntityModel, ActiveModelBehavior, DerivePrimaryKey, DeriveRelation,
+    EntityTrait, EnumIter, PrimaryKeyTrait,
+};
+
+// limits of a user, not limited if not set
+#[derive(Clone, Debug, DeriveEntityModel)]
+#[sea_orm(table_name = "limits")]
+pub struct Model {
+    // DEFAULT_USER_ID for the limits of everyone
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub user_id: i64,
+    // tasks running at the same time
+    pub running: Option<i64>,
+    // unfinished tasks, including running ones
+    pub queued: Option<i64>,
+    // bytes
+    pub daily: Option<i64>,
+    pub monthly: Option<i64>,
+}
+
+#[derive(Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
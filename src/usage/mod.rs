@@ -0,0 +1,189 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+// per user limits, checked before inserting a task, and bytes transferred by each user
+
+mod limits;
+mod records;
+mod session;
+
+use anyhow::{anyhow, Result};
+use chrono::{Datelike, Utc};
+pub use session::UsageSession;
+use std::{fmt::Display, str::FromStr};
+
+// telegram user ids are positive, so 0 stands for everyone
+pub const DEFAULT_USER_ID: i64 = 0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LimitKind {
+    Running,
+    Queued,
+    Daily,
+    Monthly,
+}
+
+impl LimitKind {
+    pub const fn is_bytes(self) -> bool {
+        matches!(self, Self::Daily | Self::Monthly)
+    }
+}
+
+impl FromStr for LimitKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "running" => Ok(Self::Running),
+            "queued" => Ok(Self::Queued),
+            "daily" => Ok(Self::Daily),
+            "monthly" => Ok(Self::Monthly),
+            _ => Err(anyhow!("limit should be running, queued, daily or monthly")),
+        }
+    }
+}
+
+impl Display for LimitKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let kind = match self {
+            Self::Running => "running",
+            Self::Queued => "queued",
+            Self::Daily => "daily",
+            Self::Monthly => "monthly",
+        };
+
+        write!(f, "{}", kind)
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Limits {
+    pub running: Option<u64>,
+    pub queued: Option<u64>,
+    // bytes
+    pub daily: Option<u64>,
+    pub monthly: Option<u64>,
+}
+
+impl Limits {
+    fn set(&mut self, kind: LimitKind, value: Option<u64>) {
+        match kind {
+            LimitKind::Running => self.running = value,
+            LimitKind::Queued => self.queued = value,
+            LimitKind::Daily => self.daily = value,
+            LimitKind::Monthly => self.monthly = value,
+        }
+    }
+
+    // limits not set are taken from the other one
+    fn or(self, other: Self) -> Self {
+        Self {
+            running: self.running.or(other.running),
+            queued: self.queued.or(other.queued),
+            daily: self.daily.or(other.daily),
+            monthly: self.monthly.or(other.monthly),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.running.is_none()
+            && self.queued.is_none()
+            && self.daily.is_none()
+            && self.monthly.is_none()
+    }
+}
+
+impl From<limits::Model> for Limits {
+    fn from(entry: limits::Model) -> Self {
+        let to_limit = |value: Option<i64>| value.map(|value| value.max(0) as u64);
+
+        Self {
+            running: to_limit(entry.running),
+            queued: to_limit(entry.queued),
+            daily: to_limit(entry.daily),
+            monthly: to_limit(entry.monthly),
+        }
+    }
+}
+
+impl Display for Limits {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let format_count = |value: Option<u64>| {
+            value.map_or_else(|| "none".to_string(), |value| value.to_string())
+        };
+
+        write!(
+            f,
+            "running {}, queued {}, daily {}, monthly {}",
+            format_count(self.running),
+            format_count(self.queued),
+            format_limit_bytes(self.daily),
+            format_limit_bytes(self.monthly)
+        )
+    }
+}
+
+// days and months are in utc
+pub fn day_period() -> String {
+    let now = Utc::now();
+
+    format!("{:04}-{:02}-{:02}", now.year(), now.month(), now.day())
+}
+
+pub fn month_period() -> String {
+    let now = Utc::now();
+
+    format!("{:04}-{:02}", now.year(), now.month())
+}
+
+// like 500M or 10G, plain numbers are bytes
+pub fn parse_bytes(value: &str) -> Result<u64> {
+    let value = value.to_uppercase();
+    let value = value.trim_end_matches('B');
+
+    let (number, unit) = match value.char_indices().last() {
+        Some((index, 'K')) => (&value[..index], 1024_u64),
+        Some((index, 'M')) => (&value[..index], 1024_u64.pow(2)),
+        Some((index, 'G')) => (&value[..index], 1024_u64.pow(3)),
+        Some((index, 'T')) => (&value[..index], 1024_u64.pow(4)),
+        _ => (value, 1),
+    };
+
+    let number = number
+        .parse::<f64>()
+        .map_err(|_| anyhow!("size should be like 500M or 10G"))?;
+
+    // inf and NaN are parsed as floats too
+    if !number.is_finite() {
+        return Err(anyhow!("size should be a finite number"));
+    }
+
+    if number < 0.0 {
+        return Err(anyhow!("size should not be negative"));
+    }
+
+    let bytes = number * unit as f64;
+
+    // casting would saturate silently
+    if bytes >= u64::MAX as f64 {
+        return Err(anyhow!("size is too large"));
+    }
+
+    Ok(bytes as u64)
+}
+
+pub fn format_bytes(bytes: u64) -> String {
+    if bytes < 1024 * 1024 * 1024 {
+        format!("{:.2}MB", bytes as f64 / 1024.0 / 1024.0)
+    } else {
+        format!("{:.2}GB", bytes as f64 / 1024.0 / 1024.0 / 1024.0)
+    }
+}
+
+fn format_limit_bytes(bytes: Option<u64>) -> String {
+    bytes.map_or_else(|| "none".to_string(), format_bytes)
+}
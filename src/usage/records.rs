@@ -0,0 +1,28 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use sea_orm::{
+    entity::prelude::DeriveEntityModel, ActiveModelBehavior, DerivePrimaryKey, DeriveRelation,
+    EntityTrait, EnumIter, PrimaryKeyTrait,
+};
+
+// bytes transferred by a user in a day or a month
+#[derive(Clone, Debug, DeriveEntityModel)]
+#[sea_orm(table_name = "usage")]
+pub struct Model {
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub user_id: i64,
+    // like 2024-01-31 for a day, 2024-01 for a month
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub period: String,
+    pub bytes: i64,
+}
+
+#[derive(Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -0,0 +1,180 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use super::{day_period, limits, month_period, records, LimitKind, Limits, DEFAULT_USER_ID};
+use anyhow::{Context, Result};
+use sea_orm::{
+    sea_query::OnConflict, ColumnTrait, ConnectionTrait, DatabaseConnection, EntityName,
+    EntityTrait, QueryFilter, Schema, Set,
+};
+use tokio::sync::Mutex;
+
+pub struct UsageSession {
+    connection: DatabaseConnection,
+    // bytes are read and written back when a task completes
+    add_lock: Mutex<()>,
+}
+
+impl UsageSession {
+    pub async fn new(session_path: &str) -> Result<Self> {
+        let connection = Self::connect_db(session_path).await?;
+        let add_lock = Mutex::new(());
+
+        Ok(Self {
+            connection,
+            add_lock,
+        })
+    }
+
+    async fn connect_db(path: &str) -> Result<DatabaseConnection> {
+        let connection = sea_orm::Database::connect(format!("sqlite://{}?mode=rwc", path))
+            .await
+            .context("failed to connect to usage session")?;
+
+        Self::create_table_if_not_exists(&connection, records::Entity).await?;
+        Self::create_table_if_not_exists(&connection, limits::Entity).await?;
+
+        Ok(connection)
+    }
+
+    async fn create_table_if_not_exists<E>(connection: &DatabaseConnection, entity: E) -> Result<()>
+    where
+        E: EntityTrait,
+    {
+        if !Self::is_table_exists::<E>(connection).await {
+            let backend = connection.get_database_backend();
+
+            let table_name = entity.table_name().to_string();
+            let table_create_statement = Schema::new(backend).create_table_from_entity(entity);
+
+            connection
+                .execute(backend.build(&table_create_statement))
+                .await
+                .context(format!("failed to create table {}", table_name))?;
+        }
+
+        Ok(())
+    }
+
+    async fn is_table_exists<E: EntityTrait>(connection: &DatabaseConnection) -> bool {
+        let result = E::find().one(connection).await;
+
+        result.is_ok()
+    }
+
+    pub async fn get_bytes(&self, user_id: i64, period: &str) -> Result<u64> {
+        let record = records::Entity::find_by_id((user_id, period.to_string()))
+            .one(&self.connection)
+            .await
+            .context(format!("failed to get usage of user {}", user_id))?;
+
+        Ok(record.map_or(0, |record| record.bytes.max(0) as u64))
+    }
+
+    pub async fn get_period_records(&self, period: &str) -> Result<Vec<records::Model>> {
+        records::Entity::find()
+            .filter(records::Column::Period.eq(period))
+            .all(&self.connection)
+            .await
+            .context("failed to get usage")
+    }
+
+    // counted in both today and this month
+    pub async fn add_bytes(&self, user_id: i64, bytes: u64) -> Result<()> {
+        let _lock = self.add_lock.lock().await;
+
+        for period in [day_period(), month_period()] {
+            let used = self.get_bytes(user_id, &period).await?;
+
+            let insert_item = records::ActiveModel {
+                user_id: Set(user_id),
+                period: Set(period),
+                bytes: Set((used + bytes) as i64),
+            };
+
+            records::Entity::insert(insert_item)
+                .on_conflict(
+                    OnConflict::columns([records::Column::UserId, records::Column::Period])
+                        .update_column(records::Column::Bytes)
+                        .to_owned(),
+                )
+                .exec(&self.connection)
+                .await
+                .context(format!("failed to add usage of user {}", user_id))?;
+        }
+
+        Ok(())
+    }
+
+    async fn get_limit_entry(&self, user_id: i64) -> Result<Option<limits::Model>> {
+        limits::Entity::find_by_id(user_id)
+            .one(&self.connection)
+            .await
+            .context(format!("failed to get limits of user {}", user_id))
+    }
+
+    pub async fn get_limit_entries(&self) -> Result<Vec<limits::Model>> {
+        limits::Entity::find()
+            .all(&self.connection)
+            .await
+            .context("failed to get limits")
+    }
+
+    // limits of the user fall back to the default ones
+    pub async fn get_limits(&self, user_id: i64) -> Result<Limits> {
+        let default_limits = self
+            .get_limit_entry(DEFAULT_USER_ID)
+            .await?
+            .map(Limits::from)
+            .unwrap_or_default();
+
+        let limits = self
+            .get_limit_entry(user_id)
+            .await?
+            .map(Limits::from)
+            .unwrap_or_default();
+
+        Ok(limits.or(default_limits))
+    }
+
+    pub async fn set_limit(&self, user_id: i64, kind: LimitKind, value: Option<u64>) -> Result<()> {
+        let mut limits = self
+            .get_limit_entry(user_id)
+            .await?
+            .map(Limits::from)
+            .unwrap_or_default();
+
+        limits.set(kind, value);
+
+        let to_value = |value: Option<u64>| Set(value.map(|value| value as i64));
+
+        let insert_item = limits::ActiveModel {
+            user_id: Set(user_id),
+            running: to_value(limits.running),
+            queued: to_value(limits.queued),
+            daily: to_value(limits.daily),
+            monthly: to_value(limits.monthly),
+        };
+
+        limits::Entity::insert(insert_item)
+            .on_conflict(
+                OnConflict::column(limits::Column::UserId)
+                    .update_columns([
+                        limits::Column::Running,
+                        limits::Column::Queued,
+                        limits::Column::Daily,
+                        limits::Column::Monthly,
+                    ])
+                    .to_owned(),
+            )
+            .exec(&self.connection)
+            .await
+            .context(format!("failed to set limits of user {}", user_id))?;
+
+        Ok(())
+    }
+}
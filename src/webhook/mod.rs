@@ -0,0 +1,241 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+mod events;
+mod session;
+
+use crate::{
+    env::{WebhookEnv, ENV},
+    error::ResultExt,
+    tasker::TaskModel,
+    utils::get_current_timestamp,
+};
+use anyhow::{anyhow, Context, Result};
+use hmac::{Hmac, Mac};
+use path_slash::PathBufExt;
+use reqwest::header::CONTENT_TYPE;
+use serde_json::json;
+use session::WebhookSession;
+use sha2::Sha256;
+use std::{fmt::Display, path::Path, time::Duration};
+
+const EVENT_HEADER: &str = "X-Telegram-OneDrive-Event";
+const SIGNATURE_HEADER: &str = "X-Telegram-OneDrive-Signature";
+
+// events are dropped after that
+const MAX_ATTEMPTS: i32 = 10;
+// doubled after each failed attempt
+const RETRY_DELAY_SECS: i64 = 10;
+const MAX_RETRY_DELAY_SECS: i64 = 3600;
+
+const DELIVERY_BATCH_SIZE: u64 = 20;
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+pub enum TaskEvent {
+    Inserted,
+    Started,
+    // with final path, size and item id in the task
+    Completed,
+    // with error chain
+    Failed(Vec<String>),
+}
+
+impl Display for TaskEvent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Inserted => write!(f, "task.inserted"),
+            Self::Started => write!(f, "task.started"),
+            Self::Completed => write!(f, "task.completed"),
+            Self::Failed(_) => write!(f, "task.failed"),
+        }
+    }
+}
+
+pub struct Webhook {
+    urls: Vec<String>,
+    secret: Option<String>,
+    // None if no webhook url is set
+    session: Option<WebhookSession>,
+    http_client: reqwest::Client,
+}
+
+// unlike get_http_client, certificates are verified and a hanging url can't block other deliveries
+fn build_http_client() -> Result<reqwest::Client> {
+    reqwest::Client::builder()
+        .connect_timeout(CONNECT_TIMEOUT)
+        .timeout(REQUEST_TIMEOUT)
+        .build()
+        .context("failed to build webhook http client")
+}
+
+impl Webhook {
+    pub async fn new() -> Result<Self> {
+        let WebhookEnv {
+            urls,
+            secret,
+            session_path,
+        } = &ENV.get().unwrap().webhook;
+
+        let session = if urls.is_empty() {
+            None
+        } else {
+            Some(WebhookSession::new(session_path).await?)
+        };
+
+        Ok(Self {
+            urls: urls.clone(),
+            secret: secret.clone(),
+            session,
+            http_client: build_http_client()?,
+        })
+    }
+
+    // events are saved to the outbox and delivered by run
+    pub async fn emit(&self, event: TaskEvent, task: &TaskModel) {
+        let Some(session) = &self.session else {
+            return;
+        };
+
+        let timestamp = get_current_timestamp();
+
+        let path = Path::new(&task.root_path)
+            .join(&task.filename)
+            .to_slash_lossy()
+            .to_string();
+
+        let mut payload = json!({
+            "event": event.to_string(),
+            "timestamp": timestamp,
+            "task": {
+                "id": task.id,
+                "type": task.cmd_type.to_string(),
+                "filename": task.filename,
+                "path": path,
+                "storage": task.storage.to_string(),
+                "url": task.url,
+                "current_length": task.current_length,
+                "total_length": task.total_length,
+                "item_id": task.item_id,
+                "chat_id": task.chat_id,
+            },
+        });
+
+        if let TaskEvent::Failed(error_chain) = &event {
+            payload["error"] = json!(error_chain);
+        }
+
+        let payload = payload.to_string();
+
+        for url in &self.urls {
+            session
+                .insert_event(url, &event.to_string(), &payload, timestamp)
+                .await
+                .trace();
+        }
+    }
+
+    pub async fn run(&self) {
+        let Some(session) = &self.session else {
+            return;
+        };
+
+        tracing::info!("webhook started");
+
+        loop {
+            self.deliver_due_events(session).await.trace();
+
+            tokio::time::sleep(Duration::from_secs(2)).await;
+        }
+    }
+
+    async fn deliver_due_events(&self, session: &WebhookSession) -> Result<()> {
+        let events = session
+            .get_due_events(get_current_timestamp(), DELIVERY_BATCH_SIZE)
+            .await?;
+
+        for event in events {
+            match self.deliver(&event).await {
+                Ok(()) => session.delete_event(event.id).await?,
+                Err(e) => {
+                    let attempts = event.attempts + 1;
+
+                    if attempts >= MAX_ATTEMPTS {
+                        session.delete_event(event.id).await?;
+
+                        tracing::error!(
+                            "webhook event {} to {} dropped after {} attempts: {:#}",
+                            event.event,
+                            event.url,
+                            attempts,
+                            e
+                        );
+
+                        continue;
+                    }
+
+                    let delay = (RETRY_DELAY_SECS << (attempts - 1)).min(MAX_RETRY_DELAY_SECS);
+
+                    session
+                        .postpone_event(event.id, attempts, get_current_timestamp() + delay)
+                        .await?;
+
+                    tracing::warn!(
+                        "failed to deliver webhook event {} to {}, retry in {}s: {:#}",
+                        event.event,
+                        event.url,
+                        delay,
+                        e
+                    );
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn deliver(&self, event: &events::Model) -> Result<()> {
+        let mut request = self
+            .http_client
+            .post(&event.url)
+            .header(CONTENT_TYPE, "application/json")
+            .header(EVENT_HEADER, &event.event);
+
+        if let Some(secret) = &self.secret {
+            request = request.header(SIGNATURE_HEADER, sign(secret, &event.payload)?);
+        }
+
+        let response = request
+            .body(event.payload.clone())
+            .send()
+            .await
+            .context("failed to send webhook request")?;
+
+        let status = response.status();
+
+        if !status.is_success() {
+            return Err(anyhow!("webhook responded with {}", status));
+        }
+
+        tracing::debug!("webhook event {} delivered to {}", event.event, event.url);
+
+        Ok(())
+    }
+}
+
+// receivers verify the body with the shared secret
+fn sign(secret: &str, payload: &str) -> Result<String> {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).context("invalid webhook secret")?;
+    mac.update(payload.as_bytes());
+
+    Ok(format!(
+        "sha256={}",
+        hex::encode(mac.finalize().into_bytes())
+    ))
+}
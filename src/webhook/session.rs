@@ -0,0 +1,118 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+// outbox of webhook events, kept across restarts so that events are not lost
+
+use super::events;
+use anyhow::{Context, Result};
+use sea_orm::{
+    sea_query::Expr, ActiveValue, ColumnTrait, ConnectionTrait, DatabaseConnection, EntityName,
+    EntityTrait, QueryFilter, QueryOrder, QuerySelect, Schema, Set,
+};
+
+pub struct WebhookSession {
+    connection: DatabaseConnection,
+}
+
+impl WebhookSession {
+    pub async fn new(session_path: &str) -> Result<Self> {
+        let connection = Self::connect_db(session_path).await?;
+
+        Ok(Self { connection })
+    }
+
+    async fn connect_db(path: &str) -> Result<DatabaseConnection> {
+        let connection = sea_orm::Database::connect(format!("sqlite://{}?mode=rwc", path))
+            .await
+            .context("failed to connect to webhook session")?;
+
+        Self::create_table_if_not_exists(&connection).await?;
+
+        Ok(connection)
+    }
+
+    async fn create_table_if_not_exists(connection: &DatabaseConnection) -> Result<()> {
+        if !Self::is_table_exists(connection).await {
+            let backend = connection.get_database_backend();
+
+            let table_create_statement =
+                Schema::new(backend).create_table_from_entity(events::Entity);
+
+            connection
+                .execute(backend.build(&table_create_statement))
+                .await
+                .context(format!(
+                    "failed to create table {}",
+                    events::Entity.table_name()
+                ))?;
+        }
+
+        Ok(())
+    }
+
+    async fn is_table_exists(connection: &DatabaseConnection) -> bool {
+        let result = events::Entity::find().one(connection).await;
+
+        result.is_ok()
+    }
+
+    pub async fn insert_event(
+        &self,
+        url: &str,
+        event: &str,
+        payload: &str,
+        next_attempt_at: i64,
+    ) -> Result<()> {
+        let insert_item = events::ActiveModel {
+            id: ActiveValue::default(),
+            url: Set(url.to_string()),
+            event: Set(event.to_string()),
+            payload: Set(payload.to_string()),
+            attempts: Set(0),
+            next_attempt_at: Set(next_attempt_at),
+        };
+
+        events::Entity::insert(insert_item)
+            .exec(&self.connection)
+            .await
+            .context("failed to insert webhook event")?;
+
+        Ok(())
+    }
+
+    // events that should be delivered now, in the order they were inserted
+    pub async fn get_due_events(&self, timestamp: i64, limit: u64) -> Result<Vec<events::Model>> {
+        events::Entity::find()
+            .filter(events::Column::NextAttemptAt.lte(timestamp))
+            .order_by_asc(events::Column::Id)
+            .limit(limit)
+            .all(&self.connection)
+            .await
+            .context("failed to get due webhook events")
+    }
+
+    pub async fn postpone_event(&self, id: i64, attempts: i32, next_attempt_at: i64) -> Result<()> {
+        events::Entity::update_many()
+            .filter(events::Column::Id.eq(id))
+            .col_expr(events::Column::Attempts, Expr::value(attempts))
+            .col_expr(events::Column::NextAttemptAt, Expr::value(next_attempt_at))
+            .exec(&self.connection)
+            .await
+            .context("failed to postpone webhook event")?;
+
+        Ok(())
+    }
+
+    pub async fn delete_event(&self, id: i64) -> Result<()> {
+        events::Entity::delete_by_id(id)
+            .exec(&self.connection)
+            .await
+            .context("failed to delete webhook event")?;
+
+        Ok(())
+    }
+}